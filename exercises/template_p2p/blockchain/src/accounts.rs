@@ -0,0 +1,977 @@
+//! Signed extrinsics of the account ledger, the default one.
+//!
+//! An extrinsic is a call signed by an account, numbered by the account's nonce so it can't be
+//! replayed. Blocks carry them after their coinbase and [`Accounts`] applies them in order on
+//! top of the balances, keeping what each block changed so a reorg rolls it back like the
+//! [`crate::utxo::UtxoSet`].
+//!
+//! Extrinsics can also deploy and call [`crate::contracts`]. Their fee is the gas they used,
+//! burned from the signer's balance even if the call fails, and the contracts are part of the
+//! state root that blocks commit to.
+//!
+//! The sudo account of the chain spec can schedule new [`RuntimeParams`] with a `set_params`
+//! extrinsic. They are part of the state too, so a reorg that drops the extrinsic drops the
+//! upgrade. Without sudo, any account can propose params for the others to vote on, see
+//! [`crate::governance`].
+use crate::{
+    blocks::{hash2binary, Block, BlockError, Chain, Header},
+    consensus,
+    contracts::{self, Contract, Execution, Instr},
+    governance::{Proposal, ProposalStatus, Tally},
+    keys::{self, KeyScheme, Keypair},
+    ledger::{self, Address, Balances},
+    merkle,
+    runtime::{self, RuntimeParams},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum Call {
+    /// Moves `amount` from the signer to `to`.
+    Transfer { to: Address, amount: u64 },
+    /// Deploys a contract at [`contracts::contract_address`] of the signer and nonce.
+    Deploy { code: Vec<Instr>, gas_limit: u64 },
+    /// Runs the code of `contract` with `args`.
+    CallContract {
+        contract: Address,
+        args: Vec<i64>,
+        gas_limit: u64,
+    },
+    /// Switches to `params` from block `at` on, for the sudo account of the chain spec.
+    SetParams { at: u64, params: RuntimeParams },
+    /// Puts `params` to the vote, numbered after the proposals before it.
+    Propose { params: RuntimeParams },
+    /// Votes for a proposal, or against it, replacing any previous vote of the signer.
+    Vote { proposal: u64, aye: bool },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Extrinsic {
+    pub signer: Address,
+    #[serde(default)]
+    pub scheme: KeyScheme,
+    /// Number of extrinsics of the signer before this one.
+    pub nonce: u64,
+    pub call: Call,
+    /// Hex encoded signature of every other field.
+    pub signature: String,
+}
+
+impl Extrinsic {
+    pub fn signed(key: &Keypair, nonce: u64, call: Call) -> Self {
+        let mut extrinsic = Self {
+            signer: key.address(),
+            scheme: key.scheme(),
+            nonce,
+            call,
+            signature: String::new(),
+        };
+        extrinsic.signature = hex::encode(key.sign(&extrinsic.signing_payload()));
+        extrinsic
+    }
+
+    /// Hex encoded sha256 of the extrinsic, signature included.
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_vec(self).expect("can jsonify extrinsic");
+        hex::encode(Sha256::digest(json))
+    }
+
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut unsigned = self.clone();
+        unsigned.signature.clear();
+        serde_json::to_vec(&unsigned).expect("can jsonify extrinsic")
+    }
+
+    pub fn verify(&self) -> bool {
+        hex::decode(&self.signature).is_ok_and(|signature| {
+            keys::verify(
+                self.scheme,
+                &self.signer,
+                &self.signing_payload(),
+                &signature,
+            )
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AccountError {
+    #[error("this node doesn't follow the account ledger")]
+    Disabled,
+    #[error("extrinsic is not signed by {0}")]
+    InvalidSignature(Address),
+    #[error("expected nonce {expected}, got {nonce}")]
+    BadNonce { expected: u64, nonce: u64 },
+    #[error("balance of {balance} can't pay {amount}")]
+    InsufficientBalance { balance: u64, amount: u64 },
+    #[error("block with id: {0} is pruned, the balances can't be rebuilt")]
+    PrunedBlock(u64),
+    #[error("no contract at {0}")]
+    UnknownContract(Address),
+    #[error(
+        "code of {len} instructions is longer than {}",
+        contracts::MAX_CODE_LEN
+    )]
+    CodeTooLarge { len: usize },
+    #[error("gas limit {gas_limit} is higher than {max}")]
+    GasLimitTooHigh { gas_limit: u64, max: u64 },
+    #[error("{0} is not the sudo account of the chain")]
+    NotSudo(Address),
+    #[error("upgrade at block {at} must activate after the next block, {next}")]
+    ActivationPassed { at: u64, next: u64 },
+    #[error("runtime version {version} is not newer than {current}")]
+    StaleVersion { version: u32, current: u32 },
+    #[error("difficulty {difficulty} is higher than {}", runtime::MAX_DIFFICULTY)]
+    DifficultyTooHigh { difficulty: usize },
+    #[error("{0} has no balance to stake")]
+    NoStake(Address),
+    #[error("no proposal {0}")]
+    UnknownProposal(u64),
+    #[error("voting on proposal {proposal} closed at block {voting_ends}")]
+    VotingClosed { proposal: u64, voting_ends: u64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: u64,
+    /// Nonce of the next extrinsic of the account.
+    pub nonce: u64,
+}
+
+/// What applying a block changed, to undo it on reorg.
+#[derive(Debug, Clone, Default)]
+struct Applied {
+    id: u64,
+    hash: String,
+    previous: Vec<(Address, Option<Account>)>,
+    contracts: Vec<(Address, Option<Contract>)>,
+    upgrades: usize,
+    proposals: Vec<(u64, Option<Proposal>)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Accounts {
+    accounts: BTreeMap<Address, Account>,
+    contracts: BTreeMap<Address, Contract>,
+    /// Params scheduled on chain and the block they apply from, in the order they were.
+    upgrades: Vec<(u64, RuntimeParams)>,
+    proposals: BTreeMap<u64, Proposal>,
+    /// One entry per block applied, from the genesis block.
+    applied: Vec<Applied>,
+}
+
+impl Accounts {
+    pub fn get(&self, address: &str) -> Account {
+        self.accounts.get(address).copied().unwrap_or_default()
+    }
+
+    pub fn balances(&self) -> Balances {
+        self.accounts
+            .iter()
+            .map(|(address, account)| (address.clone(), account.balance))
+            .collect()
+    }
+
+    pub fn contract(&self, address: &str) -> Option<&Contract> {
+        self.contracts.get(address)
+    }
+
+    /// Runs a call of `contract` without keeping its changes, e.g. to read its storage.
+    pub fn query(&self, contract: &str, args: &[i64]) -> Result<Execution, AccountError> {
+        let mut contract = self
+            .contracts
+            .get(contract)
+            .cloned()
+            .ok_or_else(|| AccountError::UnknownContract(String::from(contract)))?;
+        Ok(contract.call(args, contracts::MAX_GAS_LIMIT))
+    }
+
+    /// Rules of block `id`, from the latest upgrade activated by then, on chain or in the
+    /// chain spec.
+    pub fn params(&self, id: u64) -> RuntimeParams {
+        let on_chain = self.upgrades.iter().filter(|(at, _)| *at <= id).cloned();
+        // on a tie the upgrade on chain wins, it comes last
+        std::iter::once(runtime::spec().params(id))
+            .chain(on_chain)
+            .max_by_key(|(at, _)| *at)
+            .map(|(_, params)| params)
+            .expect("the spec always has params")
+    }
+
+    /// Upgrades scheduled on chain, with the block they apply from.
+    pub fn upgrades(&self) -> &[(u64, RuntimeParams)] {
+        &self.upgrades
+    }
+
+    /// Reward of block `id`, from its runtime params or else the halving schedule.
+    pub fn reward(&self, id: u64) -> u64 {
+        self.params(id)
+            .reward
+            .unwrap_or_else(|| ledger::rewards().reward(id))
+    }
+
+    /// Prefix of the binary proof-of-work hash of block `id`, if its runtime params ask for
+    /// more leading zeros than the chain spec.
+    pub fn difficulty_prefix(&self, id: u64) -> Option<String> {
+        // the chain spec may schedule params above the limit
+        let zeros = self.params(id).difficulty?.min(runtime::MAX_DIFFICULTY);
+        (consensus::engine().is_proof_of_work() && zeros > runtime::spec().difficulty(id))
+            .then(|| "0".repeat(zeros))
+    }
+
+    pub fn proposals(&self) -> &BTreeMap<u64, Proposal> {
+        &self.proposals
+    }
+
+    /// Votes for and against proposal `id`, weighted by the current balances.
+    pub fn tally(&self, id: u64) -> Option<Tally> {
+        let proposal = self.proposals.get(&id)?;
+        Some(proposal.tally(|voter| self.get(voter).balance))
+    }
+
+    /// Id of the next block to apply.
+    pub fn next_id(&self) -> u64 {
+        self.tip().map_or(0, |(id, _)| id + 1)
+    }
+
+    /// Hex encoded Merkle root of the accounts then the contracts, in address order, then of
+    /// the upgrades scheduled on chain, if any, and of the proposals.
+    pub fn state_root(&self) -> String {
+        let accounts = self.accounts.iter().map(|entry| serde_json::to_vec(&entry));
+        let contracts = self
+            .contracts
+            .iter()
+            .map(|entry| serde_json::to_vec(&entry));
+        let upgrades = (!self.upgrades.is_empty()).then(|| serde_json::to_vec(&self.upgrades));
+        let proposals = self
+            .proposals
+            .iter()
+            .map(|entry| serde_json::to_vec(&entry));
+        let leaves: Vec<_> = accounts
+            .chain(contracts)
+            .chain(upgrades)
+            .chain(proposals)
+            .map(|leaf| merkle::leaf_hash(&leaf.expect("can jsonify state")))
+            .collect();
+        hex::encode(merkle::root(&leaves))
+    }
+
+    /// Id and hash of the last block applied.
+    pub fn tip(&self) -> Option<(u64, &str)> {
+        self.applied.last().map(|a| (a.id, a.hash.as_str()))
+    }
+
+    /// Checks that `extrinsic` is signed, next in line for its signer and can be paid for, with
+    /// the fee of its whole gas limit for contracts.
+    pub fn check(&self, extrinsic: &Extrinsic) -> Result<(), AccountError> {
+        if !extrinsic.verify() {
+            return Err(AccountError::InvalidSignature(extrinsic.signer.clone()));
+        }
+        let signer = self.get(&extrinsic.signer);
+        let next = self.next_id();
+        let max_gas_limit = self.params(next).max_gas_limit;
+        if extrinsic.nonce != signer.nonce {
+            return Err(AccountError::BadNonce {
+                expected: signer.nonce,
+                nonce: extrinsic.nonce,
+            });
+        }
+        let amount = match &extrinsic.call {
+            Call::Transfer { amount, .. } => *amount,
+            Call::Deploy { code, gas_limit } => {
+                if code.len() > contracts::MAX_CODE_LEN {
+                    return Err(AccountError::CodeTooLarge { len: code.len() });
+                }
+                check_gas_limit(*gas_limit, max_gas_limit)?
+            }
+            Call::CallContract {
+                contract,
+                gas_limit,
+                ..
+            } => {
+                if !self.contracts.contains_key(contract) {
+                    return Err(AccountError::UnknownContract(contract.clone()));
+                }
+                check_gas_limit(*gas_limit, max_gas_limit)?
+            }
+            Call::SetParams { at, params } => {
+                if runtime::spec().sudo.as_ref() != Some(&extrinsic.signer) {
+                    return Err(AccountError::NotSudo(extrinsic.signer.clone()));
+                }
+                if *at <= next {
+                    return Err(AccountError::ActivationPassed { at: *at, next });
+                }
+                self.check_params(params, *at)?;
+                0
+            }
+            Call::Propose { params } => {
+                if signer.balance == 0 {
+                    return Err(AccountError::NoStake(extrinsic.signer.clone()));
+                }
+                let proposal = Proposal::new(extrinsic.signer.clone(), params.clone(), next);
+                self.check_params(params, proposal.enact_at)?;
+                0
+            }
+            Call::Vote { proposal: id, .. } => {
+                let proposal = self
+                    .proposals
+                    .get(id)
+                    .ok_or(AccountError::UnknownProposal(*id))?;
+                if proposal.status != ProposalStatus::Open || next >= proposal.voting_ends {
+                    return Err(AccountError::VotingClosed {
+                        proposal: *id,
+                        voting_ends: proposal.voting_ends,
+                    });
+                }
+                if signer.balance == 0 {
+                    return Err(AccountError::NoStake(extrinsic.signer.clone()));
+                }
+                0
+            }
+        };
+        if amount > signer.balance {
+            return Err(AccountError::InsufficientBalance {
+                balance: signer.balance,
+                amount,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that `params` are newer than those of block `at`, and that blocks can still be
+    /// mined under them.
+    fn check_params(&self, params: &RuntimeParams, at: u64) -> Result<(), AccountError> {
+        let current = self.params(at).version;
+        if params.version <= current {
+            return Err(AccountError::StaleVersion {
+                version: params.version,
+                current,
+            });
+        }
+        match params.difficulty {
+            Some(difficulty) if difficulty > runtime::MAX_DIFFICULTY => {
+                Err(AccountError::DifficultyTooHigh { difficulty })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, applied: &mut Applied, address: &str, f: impl FnOnce(&mut Account)) {
+        if !applied.previous.iter().any(|(a, _)| a == address) {
+            let previous = self.accounts.get(address).copied();
+            applied.previous.push((String::from(address), previous));
+        }
+        f(self.accounts.entry(String::from(address)).or_default());
+    }
+
+    fn update_contract(&mut self, applied: &mut Applied, address: &str, contract: Contract) {
+        if !applied.contracts.iter().any(|(a, _)| a == address) {
+            let previous = self.contracts.get(address).cloned();
+            applied.contracts.push((String::from(address), previous));
+        }
+        self.contracts.insert(String::from(address), contract);
+    }
+
+    fn update_proposal(&mut self, applied: &mut Applied, id: u64, proposal: Proposal) {
+        if !applied.proposals.iter().any(|(p, _)| *p == id) {
+            let previous = self.proposals.get(&id).cloned();
+            applied.proposals.push((id, previous));
+        }
+        self.proposals.insert(id, proposal);
+    }
+
+    /// Tallies the proposals whose voting ends at block `id`, scheduling the approved ones.
+    fn close_votes(&mut self, applied: &mut Applied, id: u64) {
+        let closing: Vec<_> = self
+            .proposals
+            .iter()
+            .filter(|(_, p)| p.status == ProposalStatus::Open && p.voting_ends <= id)
+            .map(|(id, _)| *id)
+            .collect();
+        for proposal_id in closing {
+            let mut proposal = self.proposals[&proposal_id].clone();
+            let tally = proposal.tally(|voter| self.get(voter).balance);
+            proposal.status = match tally.approved(self.balances().total()) {
+                true => {
+                    self.upgrades
+                        .push((proposal.enact_at, proposal.params.clone()));
+                    applied.upgrades += 1;
+                    ProposalStatus::Approved
+                }
+                false => ProposalStatus::Rejected,
+            };
+            self.update_proposal(applied, proposal_id, proposal);
+        }
+    }
+
+    fn apply(&mut self, applied: &mut Applied, extrinsic: &Extrinsic) -> Result<(), AccountError> {
+        self.check(extrinsic)?;
+        self.update(applied, &extrinsic.signer, |a| a.nonce += 1);
+        match &extrinsic.call {
+            Call::Transfer { to, amount } => {
+                self.update(applied, &extrinsic.signer, |a| a.balance -= amount);
+                self.update(applied, to, |a| {
+                    a.balance = a.balance.saturating_add(*amount)
+                });
+            }
+            Call::Deploy { code, gas_limit } => {
+                let gas = contracts::deploy_cost(code);
+                if gas <= *gas_limit {
+                    let address = contracts::contract_address(&extrinsic.signer, extrinsic.nonce);
+                    let contract = Contract {
+                        code: code.clone(),
+                        storage: contracts::Storage::new(),
+                    };
+                    self.update_contract(applied, &address, contract);
+                }
+                let fee = contracts::fee(gas.min(*gas_limit));
+                self.update(applied, &extrinsic.signer, |a| a.balance -= fee);
+            }
+            Call::CallContract {
+                contract: address,
+                args,
+                gas_limit,
+            } => {
+                let mut contract = self.contracts[address].clone();
+                let execution = contract.call(args, *gas_limit);
+                if execution.result.is_ok() {
+                    self.update_contract(applied, address, contract);
+                }
+                let fee = contracts::fee(execution.gas_used);
+                self.update(applied, &extrinsic.signer, |a| a.balance -= fee);
+            }
+            Call::SetParams { at, params } => {
+                self.upgrades.push((*at, params.clone()));
+                applied.upgrades += 1;
+            }
+            Call::Propose { params } => {
+                let id = self.proposals.len() as u64;
+                let proposal =
+                    Proposal::new(extrinsic.signer.clone(), params.clone(), self.next_id());
+                self.update_proposal(applied, id, proposal);
+            }
+            Call::Vote { proposal: id, aye } => {
+                let mut proposal = self.proposals[id].clone();
+                proposal.votes.insert(extrinsic.signer.clone(), *aye);
+                self.update_proposal(applied, *id, proposal);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the proof-of-work hash of `header` has the leading zeros its runtime params
+    /// ask for, when they ask for more than the chain spec. `header` must follow the last block
+    /// applied.
+    pub fn check_seal(&self, header: &Header) -> Result<(), BlockError> {
+        if let Some(prefix) = self.difficulty_prefix(header.id) {
+            let hash = hex::decode(&header.hash).unwrap_or_default();
+            if !hash2binary(&hash).starts_with(&prefix) {
+                return Err(BlockError::InvalidDifficulty { id: header.id });
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the coinbase and extrinsics of `block`, which must follow the last block applied,
+    /// after the tally of the votes that end there. Nothing changes if an extrinsic is invalid.
+    /// The seal isn't checked, so blocks can be applied before they are mined, see
+    /// [`Accounts::check_seal`].
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), BlockError> {
+        self.params(block.id).check_block(block)?;
+        let expected = self.reward(block.id);
+        if let Some(coinbase) = block.coinbase.as_ref().filter(|c| c.amount != expected) {
+            return Err(BlockError::InvalidReward {
+                id: block.id,
+                amount: coinbase.amount,
+                expected,
+            });
+        }
+        let mut applied = Applied {
+            id: block.id,
+            hash: block.hash.clone(),
+            previous: Vec::new(),
+            contracts: Vec::new(),
+            upgrades: 0,
+            proposals: Vec::new(),
+        };
+        self.close_votes(&mut applied, block.id);
+        if let Some(coinbase) = &block.coinbase {
+            self.update(&mut applied, &coinbase.to, |a| {
+                a.balance = a.balance.saturating_add(coinbase.amount)
+            });
+        }
+        for extrinsic in block.extrinsics.iter() {
+            if let Err(source) = self.apply(&mut applied, extrinsic) {
+                self.undo(applied);
+                return Err(BlockError::InvalidExtrinsic {
+                    id: block.id,
+                    source,
+                });
+            }
+        }
+        if block
+            .state_root
+            .as_ref()
+            .is_some_and(|root| *root != self.state_root())
+        {
+            self.undo(applied);
+            return Err(BlockError::InvalidStateRoot { id: block.id });
+        }
+        self.applied.push(applied);
+        Ok(())
+    }
+
+    /// Undoes the last block applied, returns its id.
+    pub fn rollback(&mut self) -> Option<u64> {
+        let applied = self.applied.pop()?;
+        let id = applied.id;
+        self.undo(applied);
+        Some(id)
+    }
+
+    fn undo(&mut self, applied: Applied) {
+        for (address, previous) in applied.previous {
+            match previous {
+                Some(account) => self.accounts.insert(address, account),
+                None => self.accounts.remove(&address),
+            };
+        }
+        for (address, previous) in applied.contracts {
+            match previous {
+                Some(contract) => self.contracts.insert(address, contract),
+                None => self.contracts.remove(&address),
+            };
+        }
+        for (id, previous) in applied.proposals {
+            match previous {
+                Some(proposal) => self.proposals.insert(id, proposal),
+                None => self.proposals.remove(&id),
+            };
+        }
+        self.upgrades
+            .truncate(self.upgrades.len() - applied.upgrades);
+    }
+
+    /// Rolls back the blocks that are no longer in `chain` and applies the new ones.
+    pub fn sync(&mut self, chain: &Chain) -> Result<(), BlockError> {
+        while let Some((id, hash)) = self.tip() {
+            if chain.get_header(id).is_some_and(|h| h.hash == hash) {
+                break;
+            }
+            self.rollback();
+        }
+        for id in self.next_id()..chain.len() as u64 {
+            let block = chain.get_block(id).ok_or(BlockError::InvalidExtrinsic {
+                id,
+                source: AccountError::PrunedBlock(id),
+            })?;
+            self.check_seal(block)?;
+            self.apply_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the seal and extrinsics of `block` on top of the current accounts, without
+    /// applying them.
+    pub fn check_block(&self, block: &Block) -> Result<(), BlockError> {
+        self.check_seal(block)?;
+        self.clone().apply_block(block)
+    }
+
+    /// Checks the extrinsics of every block of `chain`, e.g. before switching to it.
+    pub fn check_chain(&self, chain: &Chain) -> Result<(), BlockError> {
+        self.clone().sync(chain)
+    }
+}
+
+/// Fee of the whole `gas_limit`, which the signer must be able to pay.
+fn check_gas_limit(gas_limit: u64, max: u64) -> Result<u64, AccountError> {
+    match gas_limit > max {
+        true => Err(AccountError::GasLimitTooHigh { gas_limit, max }),
+        false => Ok(contracts::fee(gas_limit)),
+    }
+}
+
+/// Extrinsics waiting to be mined, in nonce order for each signer.
+#[derive(Debug, Default)]
+pub struct ExtrinsicPool {
+    extrinsics: Vec<Extrinsic>,
+}
+
+impl ExtrinsicPool {
+    /// Adds `extrinsic` if it is valid after the pending ones, returns its hash.
+    pub fn add(
+        &mut self,
+        extrinsic: Extrinsic,
+        accounts: &Accounts,
+    ) -> Result<String, AccountError> {
+        let mut pending = accounts.clone();
+        let mut applied = Applied::default();
+        for previous in self.extrinsics.iter() {
+            // the pool is revalidated after every block, the pending extrinsics apply
+            let _ = pending.apply(&mut applied, previous);
+        }
+        pending.check(&extrinsic)?;
+        let hash = extrinsic.hash();
+        self.extrinsics.push(extrinsic);
+        Ok(hash)
+    }
+
+    /// Nonce of the next extrinsic of `address`, counting the pending ones.
+    pub fn next_nonce(&self, address: &str, accounts: &Accounts) -> u64 {
+        accounts.get(address).nonce
+            + self
+                .extrinsics
+                .iter()
+                .filter(|e| e.signer == address)
+                .count() as u64
+    }
+
+    pub fn extrinsics(&self) -> &[Extrinsic] {
+        &self.extrinsics
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extrinsics.is_empty()
+    }
+
+    /// Drops the extrinsics with the given hashes, [`ExtrinsicPool::revalidate`] then drops the
+    /// later ones of their signers.
+    pub fn remove(&mut self, hashes: &[String]) {
+        self.extrinsics
+            .retain(|extrinsic| !hashes.contains(&extrinsic.hash()));
+    }
+
+    /// Drops the extrinsics mined or made invalid by the blocks applied to `accounts`.
+    pub fn revalidate(&mut self, accounts: &Accounts) {
+        let mut pending = accounts.clone();
+        let mut applied = Applied::default();
+        self.extrinsics
+            .retain(|extrinsic| pending.apply(&mut applied, extrinsic).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger;
+
+    fn mine(chain: &mut Chain, miner: Option<&str>, extrinsics: Vec<Extrinsic>) -> Block {
+        let head = chain.head().header.clone();
+        let block = Block::with_extrinsics(&head, String::from("block"), extrinsics, miner)
+            .expect("can mine");
+        chain.blocks.push(block.clone());
+        block
+    }
+
+    fn transfer(to: &str, amount: u64) -> Call {
+        Call::Transfer {
+            to: String::from(to),
+            amount,
+        }
+    }
+
+    #[test]
+    fn transfers_are_signed_and_ordered_by_nonce() {
+        let alice = Keypair::generate(KeyScheme::Sr25519);
+        let bob = Keypair::generate(KeyScheme::Ed25519).address();
+        let mut chain = Chain::default();
+        mine(&mut chain, Some(&alice.address()), Vec::new());
+        let mut accounts = Accounts::default();
+        accounts.sync(&chain).unwrap();
+        let reward = ledger::rewards().reward(1);
+        assert_eq!(accounts.get(&alice.address()).balance, reward);
+
+        let mut forged = Extrinsic::signed(&alice, 0, transfer(&bob, 1));
+        forged.call = transfer(&bob, reward);
+        assert_eq!(
+            accounts.check(&forged),
+            Err(AccountError::InvalidSignature(alice.address()))
+        );
+        let mut pool = ExtrinsicPool::default();
+        pool.add(Extrinsic::signed(&alice, 0, transfer(&bob, 20)), &accounts)
+            .unwrap();
+        assert_eq!(pool.next_nonce(&alice.address(), &accounts), 1);
+        assert_eq!(
+            pool.add(Extrinsic::signed(&alice, 0, transfer(&bob, 5)), &accounts),
+            Err(AccountError::BadNonce {
+                expected: 1,
+                nonce: 0
+            })
+        );
+        assert_eq!(
+            pool.add(
+                Extrinsic::signed(&alice, 1, transfer(&bob, reward)),
+                &accounts
+            ),
+            Err(AccountError::InsufficientBalance {
+                balance: reward - 20,
+                amount: reward
+            })
+        );
+
+        let replay = pool.extrinsics().to_vec();
+        mine(&mut chain, None, replay.clone());
+        accounts.sync(&chain).unwrap();
+        pool.revalidate(&accounts);
+        assert!(pool.is_empty());
+        assert_eq!(accounts.get(&bob).balance, 20);
+        assert_eq!(accounts.get(&alice.address()).nonce, 1);
+        let head = chain.head().header.clone();
+        let block = Block::with_extrinsics(&head, String::new(), replay, None).unwrap();
+        assert!(matches!(
+            accounts.check_block(&block),
+            Err(BlockError::InvalidExtrinsic { id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn rolls_back_on_reorg() {
+        let alice = Keypair::generate(KeyScheme::Ed25519);
+        let mut chain = Chain::default();
+        mine(&mut chain, Some(&alice.address()), Vec::new());
+        let mut fork = Chain {
+            blocks: chain.blocks.clone(),
+            pruned: Vec::new(),
+            finalized: None,
+        };
+        mine(
+            &mut chain,
+            None,
+            vec![Extrinsic::signed(&alice, 0, transfer("bob", 10))],
+        );
+        let mut accounts = Accounts::default();
+        accounts.sync(&chain).unwrap();
+        assert_eq!(accounts.get("bob").balance, 10);
+
+        for _ in 0..2 {
+            mine(&mut fork, None, Vec::new());
+        }
+        accounts.sync(&fork).unwrap();
+        assert_eq!(accounts.get("bob"), Account::default());
+        assert_eq!(
+            accounts.get(&alice.address()),
+            Account {
+                balance: ledger::rewards().reward(1),
+                nonce: 0
+            }
+        );
+        assert_eq!(accounts.tip(), Some((3, fork.head().hash.as_str())));
+    }
+
+    #[test]
+    fn contracts_keep_state_and_commit_to_it() {
+        let alice = Keypair::generate(KeyScheme::Sr25519);
+        let mut chain = Chain::default();
+        mine(&mut chain, Some(&alice.address()), Vec::new());
+        let mut accounts = Accounts::default();
+        accounts.sync(&chain).unwrap();
+
+        let code = contracts::assemble("push 0\narg 0\nstore\npush 0\nload\nreturn").unwrap();
+        let deploy = Call::Deploy {
+            code: code.clone(),
+            gas_limit: 5_000,
+        };
+        let address = contracts::contract_address(&alice.address(), 0);
+        let call = Call::CallContract {
+            contract: address.clone(),
+            args: vec![7],
+            gas_limit: 5_000,
+        };
+        assert_eq!(
+            accounts.check(&Extrinsic::signed(&alice, 0, call.clone())),
+            Err(AccountError::UnknownContract(address.clone()))
+        );
+        let head = chain.head().header.clone();
+        let block = Block::with_state(
+            &head,
+            String::new(),
+            vec![
+                Extrinsic::signed(&alice, 0, deploy),
+                Extrinsic::signed(&alice, 1, call),
+            ],
+            None,
+            &accounts,
+        )
+        .unwrap();
+        let mut forged = block.clone();
+        forged.header.state_root = Some(Accounts::default().state_root());
+        assert_eq!(
+            accounts.check_block(&forged),
+            Err(BlockError::InvalidStateRoot { id: 2 })
+        );
+        chain.blocks.push(block);
+        accounts.sync(&chain).unwrap();
+        assert_eq!(
+            accounts.contract(&address).unwrap().storage.get(&0),
+            Some(&7)
+        );
+        let fees = contracts::fee(contracts::deploy_cost(&code)) + 1;
+        assert_eq!(
+            accounts.get(&alice.address()).balance,
+            ledger::rewards().reward(1) - fees
+        );
+        assert_eq!(accounts.query(&address, &[9]).unwrap().result, Ok(Some(9)));
+        assert_eq!(
+            accounts.contract(&address).unwrap().storage.get(&0),
+            Some(&7)
+        );
+
+        accounts.rollback();
+        assert_eq!(accounts.contract(&address), None);
+    }
+    #[test]
+    fn upgrades_on_chain_change_the_rules() {
+        let alice = Keypair::generate(KeyScheme::Sr25519);
+        let mut chain = Chain::default();
+        mine(&mut chain, Some(&alice.address()), Vec::new());
+        let mut accounts = Accounts::default();
+        accounts.sync(&chain).unwrap();
+        let params = RuntimeParams {
+            version: 2,
+            max_extrinsics: 1,
+            ..RuntimeParams::default()
+        };
+        let set_params = Call::SetParams {
+            at: 3,
+            params: params.clone(),
+        };
+        // no sudo in the default spec
+        assert_eq!(
+            accounts.check(&Extrinsic::signed(&alice, 0, set_params)),
+            Err(AccountError::NotSudo(alice.address()))
+        );
+
+        let root = accounts.state_root();
+        accounts.upgrades.push((3, params));
+        assert_ne!(accounts.state_root(), root);
+        assert_eq!(accounts.params(2).version, 1);
+        assert_eq!(accounts.params(3).version, 2);
+        let transfers = |from: u64| {
+            (from..from + 2)
+                .map(|nonce| Extrinsic::signed(&alice, nonce, transfer(&alice.address(), 1)))
+                .collect()
+        };
+        let block = mine(&mut chain, None, transfers(0));
+        accounts.apply_block(&block).unwrap();
+        let block = mine(&mut chain, None, transfers(2));
+        assert_eq!(
+            accounts.check_block(&block),
+            Err(BlockError::TooManyExtrinsics {
+                id: 3,
+                count: 2,
+                max: 1
+            })
+        );
+    }
+
+    #[test]
+    fn approved_proposals_are_enacted() {
+        let alice = Keypair::generate(KeyScheme::Sr25519);
+        let bob = Keypair::generate(KeyScheme::Ed25519);
+        let mut chain = Chain::default();
+        mine(&mut chain, Some(&alice.address()), Vec::new());
+        let mut accounts = Accounts::default();
+        accounts.sync(&chain).unwrap();
+        let params = RuntimeParams {
+            version: 2,
+            reward: Some(7),
+            ..RuntimeParams::default()
+        };
+        let propose = Call::Propose {
+            params: params.clone(),
+        };
+        assert_eq!(
+            accounts.check(&Extrinsic::signed(&bob, 0, propose.clone())),
+            Err(AccountError::NoStake(bob.address()))
+        );
+        let unminable = Call::Propose {
+            params: RuntimeParams {
+                difficulty: Some(runtime::MAX_DIFFICULTY + 1),
+                ..params.clone()
+            },
+        };
+        assert_eq!(
+            accounts.check(&Extrinsic::signed(&alice, 0, unminable)),
+            Err(AccountError::DifficultyTooHigh {
+                difficulty: runtime::MAX_DIFFICULTY + 1
+            })
+        );
+        mine(
+            &mut chain,
+            None,
+            vec![
+                Extrinsic::signed(&alice, 0, transfer(&bob.address(), 20)),
+                Extrinsic::signed(&alice, 1, propose),
+            ],
+        );
+        accounts.sync(&chain).unwrap();
+        let proposal = accounts.proposals()[&0].clone();
+        let (voting_ends, enact_at) = (proposal.voting_ends, proposal.enact_at);
+        let vote = |aye| Call::Vote { proposal: 0, aye };
+        mine(
+            &mut chain,
+            None,
+            vec![
+                Extrinsic::signed(&alice, 2, vote(true)),
+                Extrinsic::signed(&bob, 0, vote(false)),
+            ],
+        );
+        accounts.sync(&chain).unwrap();
+        let reward = ledger::rewards().reward(1);
+        let tally = Tally {
+            ayes: reward - 20,
+            nays: 20,
+        };
+        assert_eq!(accounts.tally(0), Some(tally));
+
+        while chain.head().id + 1 < voting_ends {
+            mine(&mut chain, None, Vec::new());
+        }
+        accounts.sync(&chain).unwrap();
+        assert_eq!(
+            accounts.check(&Extrinsic::signed(&bob, 1, vote(true))),
+            Err(AccountError::VotingClosed {
+                proposal: 0,
+                voting_ends
+            })
+        );
+        mine(&mut chain, None, Vec::new());
+        accounts.sync(&chain).unwrap();
+        assert_eq!(accounts.proposals()[&0].status, ProposalStatus::Approved);
+        assert_eq!(accounts.upgrades(), [(enact_at, params.clone())]);
+        accounts.rollback();
+        assert_eq!(accounts.proposals()[&0].status, ProposalStatus::Open);
+        assert!(accounts.upgrades().is_empty());
+
+        while chain.head().id + 1 < enact_at {
+            mine(&mut chain, None, Vec::new());
+        }
+        accounts.sync(&chain).unwrap();
+        assert_eq!(accounts.reward(enact_at), 7);
+        let head = chain.head().header.clone();
+        let block =
+            Block::with_extrinsics(&head, String::new(), Vec::new(), Some(&bob.address())).unwrap();
+        assert_eq!(
+            accounts.check_block(&block),
+            Err(BlockError::InvalidReward {
+                id: enact_at,
+                amount: reward,
+                expected: 7
+            })
+        );
+        let block = Block::with_state(
+            &head,
+            String::new(),
+            Vec::new(),
+            Some(&bob.address()),
+            &accounts,
+        )
+        .unwrap();
+        accounts.apply_block(&block).unwrap();
+        assert_eq!(accounts.get(&bob.address()).balance, 20 + 7);
+    }
+}
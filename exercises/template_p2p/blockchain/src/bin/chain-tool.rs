@@ -0,0 +1,101 @@
+use blockchain::{
+    consensus::{self, ChainSpec},
+    inspect, ledger, runtime, snapshot, Result,
+};
+use clap::{Parser, Subcommand};
+use std::{path::PathBuf, process::ExitCode};
+
+/// Inspects chains written by `export-chain`, without running a node.
+#[derive(Parser)]
+#[command(name = "chain-tool")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check every block and report the invalid ones by id
+    Verify {
+        file: PathBuf,
+        /// Chain spec the blocks were made with, as given to the node [default: proof-of-work]
+        #[arg(long)]
+        chain_spec: Option<PathBuf>,
+    },
+    /// Print a block, by id or by hash (or a unique prefix of it)
+    Show { file: PathBuf, block: String },
+    /// Block times and difficulty over the chain
+    Stats { file: PathBuf },
+    /// Find where two chains fork
+    Diff { a: PathBuf, b: PathBuf },
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Verify { file, chain_spec } => {
+            if let Some(path) = chain_spec {
+                // the same rules as a node started with this spec
+                let spec = ChainSpec::from_file(path)?;
+                consensus::set_engine(spec.engine(None)?)?;
+                ledger::set_rewards(spec.rewards);
+                ledger::set_mode(spec.ledger);
+                runtime::set_spec(spec.runtime);
+            }
+            let blocks = snapshot::import_blocks(&file)?;
+            let errors = inspect::verify(&blocks);
+            if errors.is_empty() {
+                println!("{}: {} valid blocks", file.display(), blocks.len());
+                return Ok(ExitCode::SUCCESS);
+            }
+            errors.iter().for_each(|e| println!("{}", e));
+            println!("{}: {} invalid blocks", file.display(), errors.len());
+            Ok(ExitCode::FAILURE)
+        }
+        Command::Show { file, block } => {
+            let blocks = snapshot::import_blocks(&file)?;
+            let Some(found) = inspect::find(&blocks, &block) else {
+                eprintln!("no block matching `{}` in {}", block, file.display());
+                return Ok(ExitCode::FAILURE);
+            };
+            println!("{}", serde_json::to_string_pretty(found)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Stats { file } => {
+            let blocks = snapshot::import_blocks(&file)?;
+            println!(
+                "{:>6} {:>10} {:>8} {:>10}",
+                "id", "time (s)", "zeros", "nonce"
+            );
+            let times = inspect::block_times(&blocks);
+            for (i, block) in blocks.iter().enumerate() {
+                // the genesis block has no block time
+                let time = i.checked_sub(1).and_then(|i| times.get(i));
+                let time = time.map_or_else(|| String::from("-"), |t| t.to_string());
+                println!(
+                    "{:>6} {:>10} {:>8} {:>10}",
+                    block.id,
+                    time,
+                    inspect::difficulty(block),
+                    block.nonce
+                );
+            }
+            if let (Some(min), Some(max)) = (times.iter().min(), times.iter().max()) {
+                let mean = times.iter().sum::<i64>() as f64 / times.len() as f64;
+                println!("block time: min {}s, mean {:.1}s, max {}s", min, mean, max);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Diff { a, b } => {
+            let (blocks_a, blocks_b) = (snapshot::import_blocks(&a)?, snapshot::import_blocks(&b)?);
+            let fork = inspect::diff(&blocks_a, &blocks_b);
+            match fork.common {
+                Some(id) => println!("chains agree up to block {}", id),
+                None => println!("chains don't share their genesis block"),
+            }
+            println!("{}: {} more blocks", a.display(), fork.only_a);
+            println!("{}: {} more blocks", b.display(), fork.only_b);
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
@@ -1,96 +1,416 @@
-use crate::Result;
+use crate::{
+    accounts::{AccountError, Accounts, Extrinsic},
+    consensus::{self, ConsensusError, ProofOfWork},
+    ledger::{self, Coinbase, LedgerMode},
+    merkle::{self, MerkleProof},
+    utxo::{Transaction, UtxoError},
+    Result,
+};
 use chrono::Utc;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::cmp::Ordering;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
 
-const DIFFICULTY_PREFIX: &str = "00";
+pub(crate) const DIFFICULTY_PREFIX: &str = "00";
 
-type Data = String;
+/// Progress of the block being mined, shared so the dashboard can show it while mining runs.
+pub struct MiningStatus {
+    active: AtomicBool,
+    id: AtomicU64,
+    nonce: AtomicU64,
+}
+
+pub static MINING_STATUS: MiningStatus = MiningStatus {
+    active: AtomicBool::new(false),
+    id: AtomicU64::new(0),
+    nonce: AtomicU64::new(0),
+};
+
+impl MiningStatus {
+    /// Returns the id of the block being mined and the last nonce tried, if mining.
+    pub fn current(&self) -> Option<(u64, u64)> {
+        self.active.load(AtomicOrdering::Relaxed).then(|| {
+            (
+                self.id.load(AtomicOrdering::Relaxed),
+                self.nonce.load(AtomicOrdering::Relaxed),
+            )
+        })
+    }
+}
+
+pub type Data = String;
 
+/// Everything needed to check the seal and linkage, without the block data.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub struct Block {
+pub struct Header {
     pub id: u64,
     pub nonce: u64,
     pub timestamp: i64,
-    pub data: Data,
     pub previous_hash: String,
+    /// Hex encoded Merkle root of the block entries, see [`Block::leaves`].
+    pub data_root: String,
+    /// Hex encoded root of the accounts and contracts after the block, see
+    /// [`crate::accounts::Accounts::state_root`]. Checked when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_root: Option<String>,
     pub hash: String,
+    /// Hex encoded author signature of `hash`, for proof-of-authority blocks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl Header {
+    /// Checks that the header can follow `previous`, the seal is checked by the consensus
+    /// engine of the node.
+    pub fn validate(&self, previous: &Header) -> std::result::Result<(), BlockError> {
+        let id = self.id;
+        if self.previous_hash != previous.hash {
+            return Err(BlockError::WrongPreviousHash { id });
+        }
+        consensus::engine().verify(self, previous)?;
+        if self.id != previous.id + 1 {
+            return Err(BlockError::NotNext {
+                id,
+                latest: previous.id,
+            });
+        } else if hex::encode(self.calculate_hash()) != self.hash {
+            return Err(BlockError::InvalidHash { id });
+        }
+        Ok(())
+    }
+
+    /// Hash of every field but `hash` and `signature`.
+    pub fn calculate_hash(&self) -> Vec<u8> {
+        calculate_hash(
+            self.id,
+            self.nonce,
+            self.timestamp,
+            &self.data_root,
+            self.state_root.as_deref(),
+            &self.previous_hash,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    #[serde(flatten)]
+    pub header: Header,
+    pub data: Data,
+    /// Reward of the miner, committed to by `data_root` after the data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase: Option<Coinbase>,
+    /// Transfers of the UTXO ledger, committed to by `data_root` after the coinbase.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<Transaction>,
+    /// Signed calls of the account ledger, committed to by `data_root` after the coinbase.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extrinsics: Vec<Extrinsic>,
 }
 
+/// Header fields can be read straight from the block, e.g. `block.hash`.
+impl Deref for Block {
+    type Target = Header;
+
+    fn deref(&self) -> &Header {
+        &self.header
+    }
+}
+
+/// The genesis block is mined the same way whatever the consensus engine, it is never checked.
 impl Default for Block {
     fn default() -> Self {
-        let id: u64 = 0;
-        let timestamp: i64 = 1_000_000_000;
-        let data = String::from("genesis");
-        let previous_hash = format!("{}00", DIFFICULTY_PREFIX);
-        let (nonce, hash) = mine_block(id, timestamp, &data, previous_hash.as_str());
-
-        Self {
-            id,
-            nonce,
-            timestamp,
-            data,
-            previous_hash,
-            hash,
-        }
+        let mut block = Self::unsealed(
+            0,
+            1_000_000_000,
+            String::from("genesis"),
+            None,
+            Vec::new(),
+            Vec::new(),
+            format!("{}00", DIFFICULTY_PREFIX),
+        );
+        ProofOfWork::mine(&mut block.header, DIFFICULTY_PREFIX);
+        block
     }
 }
 
 impl Block {
-    pub fn new(id: u64, data: Data, previous_hash: String) -> Self {
-        let timestamp = Utc::now().timestamp();
-        let (nonce, hash) = mine_block(id, timestamp, &data, &previous_hash);
+    /// Seals a new block with `data` on top of `previous`, with the consensus engine of the node.
+    /// The block reward goes to `miner`, if any.
+    pub fn new(
+        previous: &Header,
+        data: Data,
+        miner: Option<&str>,
+    ) -> std::result::Result<Self, ConsensusError> {
+        Self::with_transactions(previous, data, Vec::new(), miner)
+    }
+
+    /// Like [`Block::new`], with transactions for the UTXO ledger.
+    pub fn with_transactions(
+        previous: &Header,
+        data: Data,
+        transactions: Vec<Transaction>,
+        miner: Option<&str>,
+    ) -> std::result::Result<Self, ConsensusError> {
+        Self::sealed(previous, data, transactions, Vec::new(), miner)
+    }
+
+    /// Like [`Block::new`], with extrinsics for the account ledger.
+    pub fn with_extrinsics(
+        previous: &Header,
+        data: Data,
+        extrinsics: Vec<Extrinsic>,
+        miner: Option<&str>,
+    ) -> std::result::Result<Self, ConsensusError> {
+        Self::sealed(previous, data, Vec::new(), extrinsics, miner)
+    }
+
+    /// Like [`Block::with_extrinsics`], committing to the state of `accounts` after the block.
+    /// `accounts` must be at `previous`, their runtime params set the reward and difficulty.
+    pub fn with_state(
+        previous: &Header,
+        data: Data,
+        extrinsics: Vec<Extrinsic>,
+        miner: Option<&str>,
+        accounts: &Accounts,
+    ) -> Result<Self> {
+        let id = previous.id + 1;
+        let reward = accounts.reward(id);
+        let coinbase = miner.filter(|_| reward > 0).map(|to| Coinbase {
+            to: String::from(to),
+            amount: reward,
+        });
+        let mut block = Self::next(previous, data, Vec::new(), extrinsics, coinbase);
+        let mut after = accounts.clone();
+        after.apply_block(&block)?;
+        block.header.state_root = Some(after.state_root());
+        match accounts.difficulty_prefix(id) {
+            Some(prefix) => ProofOfWork::mine(&mut block.header, &prefix),
+            None => consensus::engine().seal(&mut block.header, previous)?,
+        }
+        Ok(block)
+    }
+
+    fn sealed(
+        previous: &Header,
+        data: Data,
+        transactions: Vec<Transaction>,
+        extrinsics: Vec<Extrinsic>,
+        miner: Option<&str>,
+    ) -> std::result::Result<Self, ConsensusError> {
+        let coinbase = miner.and_then(|to| ledger::coinbase(previous.id + 1, to));
+        let mut block = Self::next(previous, data, transactions, extrinsics, coinbase);
+        consensus::engine().seal(&mut block.header, previous)?;
+        Ok(block)
+    }
+
+    fn next(
+        previous: &Header,
+        data: Data,
+        transactions: Vec<Transaction>,
+        extrinsics: Vec<Extrinsic>,
+        coinbase: Option<Coinbase>,
+    ) -> Self {
+        Self::unsealed(
+            previous.id + 1,
+            Utc::now().timestamp(),
+            data,
+            coinbase,
+            transactions,
+            extrinsics,
+            previous.hash.clone(),
+        )
+    }
+
+    fn unsealed(
+        id: u64,
+        timestamp: i64,
+        data: Data,
+        coinbase: Option<Coinbase>,
+        transactions: Vec<Transaction>,
+        extrinsics: Vec<Extrinsic>,
+        previous_hash: String,
+    ) -> Self {
+        let leaves = Self::leaves_of(&data, &coinbase, &transactions, &extrinsics);
+        let data_root = hex::encode(merkle::root(&leaves));
         Self {
-            id,
-            nonce,
-            timestamp,
+            header: Header {
+                id,
+                nonce: 0,
+                timestamp,
+                previous_hash,
+                data_root,
+                state_root: None,
+                hash: String::new(),
+                signature: None,
+            },
             data,
-            previous_hash,
-            hash,
-        }
-    }
-    fn is_valid(&self, previous_block: &Block) -> bool {
-        if self.previous_hash != previous_block.hash {
-            log::warn!("block with id: {} has wrong previous hash", self.id);
-            return false;
-        } else if !hash2binary(&hex::decode(&self.hash).expect("can decode from hex"))
-            .starts_with(DIFFICULTY_PREFIX)
-        {
-            log::warn!("block with id: {} has invalid difficulty", self.id);
-            return false;
-        } else if self.id != previous_block.id + 1 {
-            log::warn!(
-                "block with id: {} is not the next block after the latest: {}",
-                self.id,
-                previous_block.id
-            );
-            return false;
-        } else if hex::encode(calculate_hash(
-            self.id,
-            self.nonce,
-            self.timestamp,
+            coinbase,
+            transactions,
+            extrinsics,
+        }
+    }
+
+    /// Hashes of the block entries committed to by `data_root`.
+    pub fn leaves(&self) -> Vec<merkle::Hash> {
+        Self::leaves_of(
             &self.data,
-            &self.previous_hash,
-        )) != self.hash
-        {
-            log::warn!("block with id: {} has invalid hash", self.id);
-            return false;
+            &self.coinbase,
+            &self.transactions,
+            &self.extrinsics,
+        )
+    }
+
+    fn leaves_of(
+        data: &Data,
+        coinbase: &Option<Coinbase>,
+        transactions: &[Transaction],
+        extrinsics: &[Extrinsic],
+    ) -> Vec<merkle::Hash> {
+        let coinbase = coinbase
+            .iter()
+            .map(|c| serde_json::to_vec(c).expect("can jsonify coinbase"));
+        let transactions = transactions
+            .iter()
+            .map(|tx| serde_json::to_vec(tx).expect("can jsonify transaction"));
+        let extrinsics = extrinsics
+            .iter()
+            .map(|e| serde_json::to_vec(e).expect("can jsonify extrinsic"));
+        std::iter::once(data.as_bytes().to_vec())
+            .chain(coinbase)
+            .chain(transactions)
+            .chain(extrinsics)
+            .map(|leaf| merkle::leaf_hash(&leaf))
+            .collect()
+    }
+
+    /// Proof that the data is part of this block, for light clients holding only the header.
+    pub fn data_proof(&self) -> MerkleProof {
+        merkle::proof(&self.leaves(), 0).expect("the data is the first leaf")
+    }
+
+    /// Checks that the block can follow `previous_block`.
+    pub fn validate(&self, previous_block: &Block) -> std::result::Result<(), BlockError> {
+        self.header.validate(&previous_block.header)?;
+        self.validate_body()
+    }
+    /// Checks what the block carries, without the block before it. Transactions are checked
+    /// against the outputs they spend by [`crate::utxo::UtxoSet`], extrinsics against the
+    /// balances by [`crate::accounts::Accounts`], which also check the reward as the runtime
+    /// params can change it.
+    pub fn validate_body(&self) -> std::result::Result<(), BlockError> {
+        self.validate_data()?;
+        if ledger::mode() == LedgerMode::Utxo {
+            self.validate_reward()?;
+        }
+        if !self.transactions.is_empty() && ledger::mode() != LedgerMode::Utxo {
+            return Err(BlockError::UnexpectedTransactions { id: self.id });
+        }
+        if !self.extrinsics.is_empty() && ledger::mode() != LedgerMode::Account {
+            return Err(BlockError::UnexpectedExtrinsics { id: self.id });
+        }
+        Ok(())
+    }
+    /// Checks the data against the header's `data_root`.
+    pub fn validate_data(&self) -> std::result::Result<(), BlockError> {
+        if hex::encode(merkle::root(&self.leaves())) != self.data_root {
+            return Err(BlockError::InvalidDataRoot { id: self.id });
+        }
+        Ok(())
+    }
+    /// Checks that the coinbase, if any, credits exactly the reward of the block.
+    pub fn validate_reward(&self) -> std::result::Result<(), BlockError> {
+        let expected = ledger::rewards().reward(self.id);
+        match &self.coinbase {
+            Some(coinbase) if coinbase.amount != expected => Err(BlockError::InvalidReward {
+                id: self.id,
+                amount: coinbase.amount,
+                expected,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why a block can't be added on top of the chain.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum BlockError {
+    #[error("block with id: {id} has wrong previous hash")]
+    WrongPreviousHash { id: u64 },
+    #[error("block with id: {id} has invalid difficulty")]
+    InvalidDifficulty { id: u64 },
+    #[error("block with id: {id} is not the next block after the latest: {latest}")]
+    NotNext { id: u64, latest: u64 },
+    #[error("block with id: {id} has invalid hash")]
+    InvalidHash { id: u64 },
+    #[error("block with id: {id} has data that doesn't match its data root")]
+    InvalidDataRoot { id: u64 },
+    #[error("block with id: {id} is not in a later slot than its parent")]
+    InvalidSlot { id: u64 },
+    #[error("block with id: {id} is in slot {slot}, too far ahead of the current slot {current}")]
+    FutureSlot { id: u64, slot: u64, current: u64 },
+    #[error("block with id: {id} is not signed by the authority of its slot")]
+    InvalidSeal { id: u64 },
+    #[error("block with id: {id} rewards {amount} instead of {expected}")]
+    InvalidReward { id: u64, amount: u64, expected: u64 },
+    #[error("block with id: {id} has transactions but the chain runs the account ledger")]
+    UnexpectedTransactions { id: u64 },
+    #[error("block with id: {id} has an invalid transaction: {source}")]
+    InvalidTransaction { id: u64, source: UtxoError },
+    #[error("block with id: {id} has extrinsics but the chain runs the UTXO ledger")]
+    UnexpectedExtrinsics { id: u64 },
+    #[error("block with id: {id} has an invalid extrinsic: {source}")]
+    InvalidExtrinsic { id: u64, source: AccountError },
+    #[error("block with id: {id} doesn't match its state root")]
+    InvalidStateRoot { id: u64 },
+    #[error("block with id: {id} has {len} bytes of data, the runtime allows {max}")]
+    DataTooLong { id: u64, len: usize, max: usize },
+    #[error("block with id: {id} has {count} extrinsics, the runtime allows {max}")]
+    TooManyExtrinsics { id: u64, count: usize, max: usize },
+}
+
+impl BlockError {
+    /// Short name of the rejection reason, for metrics and events.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            BlockError::WrongPreviousHash { .. } => "wrong_previous_hash",
+            BlockError::InvalidDifficulty { .. } => "invalid_difficulty",
+            BlockError::NotNext { .. } => "not_next",
+            BlockError::InvalidHash { .. } => "invalid_hash",
+            BlockError::InvalidDataRoot { .. } => "invalid_data_root",
+            BlockError::InvalidSlot { .. } => "invalid_slot",
+            BlockError::FutureSlot { .. } => "future_slot",
+            BlockError::InvalidSeal { .. } => "invalid_seal",
+            BlockError::InvalidReward { .. } => "invalid_reward",
+            BlockError::UnexpectedTransactions { .. } => "unexpected_transactions",
+            BlockError::InvalidTransaction { .. } => "invalid_transaction",
+            BlockError::UnexpectedExtrinsics { .. } => "unexpected_extrinsics",
+            BlockError::InvalidExtrinsic { .. } => "invalid_extrinsic",
+            BlockError::InvalidStateRoot { .. } => "invalid_state_root",
+            BlockError::DataTooLong { .. } => "data_too_long",
+            BlockError::TooManyExtrinsics { .. } => "too_many_extrinsics",
         }
-        true
     }
 }
 
 #[derive(PartialEq, Eq)]
 pub struct Chain {
     pub blocks: Vec<Block>,
+    /// Headers of the blocks before `blocks` whose data was dropped, see [`Chain::prune`].
+    pub pruned: Vec<Header>,
+    /// Latest block voted final, fork choice never reverts it.
+    pub finalized: Option<Header>,
 }
 
 impl Default for Chain {
     fn default() -> Self {
         Self {
             blocks: vec![Block::default()],
+            pruned: Vec::new(),
+            finalized: None,
         }
     }
 }
@@ -101,83 +421,169 @@ impl PartialOrd for Chain {
             (false, false) => None,
             (false, true) => Some(Ordering::Less),
             (true, false) => Some(Ordering::Greater),
-            (true, true) => Some(self.blocks.len().cmp(&other.blocks.len())),
+            (true, true) => Some(self.len().cmp(&other.len())),
         }
     }
 }
 
 impl Chain {
-    pub fn try_add_block(&mut self, block: Block) {
+    pub fn head(&self) -> &Block {
+        self.blocks.last().expect("there is at least one block")
+    }
+    /// Number of blocks from the genesis block, pruned ones included.
+    pub fn len(&self) -> usize {
+        self.pruned.len() + self.blocks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns `None` for unknown blocks and for blocks whose data was pruned.
+    pub fn get_block(&self, id: u64) -> Option<&Block> {
+        let first = self.blocks.first()?.id;
+        self.blocks
+            .get(usize::try_from(id.checked_sub(first)?).ok()?)
+    }
+    pub fn get_block_by_hash(&self, hash: &str) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.hash == hash)
+    }
+    /// Headers of every block, pruned ones included.
+    pub fn headers(&self) -> impl DoubleEndedIterator<Item = &Header> {
+        self.pruned
+            .iter()
+            .chain(self.blocks.iter().map(|b| &b.header))
+    }
+    pub fn get_header(&self, id: u64) -> Option<&Header> {
+        match self.pruned.get(usize::try_from(id).ok()?) {
+            Some(header) => Some(header),
+            None => self.get_block(id).map(|b| &b.header),
+        }
+    }
+    pub fn try_add_block(&mut self, block: Block) -> std::result::Result<(), BlockError> {
         let previous_block = self.blocks.last().expect("there is at least one block");
-        if block.is_valid(previous_block) {
-            self.blocks.push(block);
-        } else {
-            log::error!("could not add block - invalid");
+        if let Err(e) = block.validate(previous_block) {
+            log::error!("could not add block - invalid: {}", e);
+            return Err(e);
         }
+        self.blocks.push(block);
+        Ok(())
     }
+    /// Adds a block with `data` on top of the chain, without a reward.
     pub fn add_data(&mut self, data: Data) -> Result<Block> {
-        let Block { id, hash, .. } = self.blocks.last().expect("has to exist");
-        let new_block = Block::new(id + 1, data, hash.to_owned());
+        let new_block = Block::new(&self.head().header, data, None)?;
         self.blocks.push(new_block.to_owned());
         Ok(new_block)
     }
     pub fn is_valid(&self) -> bool {
-        if self.blocks.len() < 2 {
-            return true;
-        }
-        // chain.windows(2).all(|bs| bs[1].is_valid(&bs[0]))
-        self.blocks
-            .iter()
-            .tuple_windows::<(&Block, &Block)>()
-            .all(|(b1, b2)| b2.is_valid(b1))
+        // the headers carry the linkage and the proof-of-work, the data is checked where kept
+        self.headers()
+            .tuple_windows::<(&Header, &Header)>()
+            .all(|(h1, h2)| is_ok(h2.validate(h1)))
+            && self.blocks.iter().all(|b| is_ok(b.validate_body()))
+    }
+    /// Drops the data of every block but the last `keep` ones, keeping their headers.
+    /// Returns the number of blocks pruned.
+    pub fn prune(&mut self, keep: usize) -> usize {
+        let count = self.blocks.len().saturating_sub(keep.max(1));
+        self.pruned
+            .extend(self.blocks.drain(..count).map(|b| b.header));
+        count
     }
-    pub fn choose_chain(&mut self, remote: &Chain) {
+    /// Keeps the better of the two chains, returns the local chain if it was replaced.
+    pub fn choose_chain(&mut self, remote: &Chain) -> Option<Chain> {
+        if let Some(finalized) = &self.finalized {
+            if remote.get_header(finalized.id) != Some(finalized) {
+                log::warn!(
+                    "ignoring a chain without the finalized block {}",
+                    finalized.id
+                );
+                return None;
+            }
+        }
         let Some(cmp) = (*self).partial_cmp(remote) else {
             panic!("both chains are invalid");
         };
-        if matches!(cmp, Ordering::Less) {
-            self.blocks = remote.blocks.to_owned();
+        matches!(cmp, Ordering::Less).then(|| {
+            let remote = Chain {
+                blocks: remote.blocks.to_owned(),
+                pruned: remote.pruned.to_owned(),
+                finalized: self.finalized.clone(),
+            };
+            std::mem::replace(self, remote)
+        })
+    }
+}
+
+fn is_ok(result: std::result::Result<(), BlockError>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("{}", e);
+            false
         }
     }
 }
 
-fn calculate_hash(id: u64, nonce: u64, timestamp: i64, data: &str, previous_hash: &str) -> Vec<u8> {
-    let data = serde_json::json!({
+fn calculate_hash(
+    id: u64,
+    nonce: u64,
+    timestamp: i64,
+    data_root: &str,
+    state_root: Option<&str>,
+    previous_hash: &str,
+) -> Vec<u8> {
+    let mut data = serde_json::json!({
         "id": id,
         "previous_hash": previous_hash,
-        "data": data,
+        "data_root": data_root,
         "timestamp": timestamp,
         "nonce": nonce
     });
+    // blocks without a state root hash as they did before there was one
+    if let Some(state_root) = state_root {
+        data["state_root"] = serde_json::Value::from(state_root);
+    }
     let mut hasher = Sha256::new();
     hasher.update(data.to_string().as_bytes());
     hasher.finalize().as_slice().to_owned()
 }
 
-fn mine_block(id: u64, timestamp: i64, data: &Data, previous_hash: &str) -> (u64, String) {
+pub(crate) fn mine_block(
+    id: u64,
+    timestamp: i64,
+    data_root: &str,
+    state_root: Option<&str>,
+    previous_hash: &str,
+    difficulty_prefix: &str,
+) -> (u64, String) {
     log::info!("mining block ...");
     let mut nonce = 0;
+    MINING_STATUS.id.store(id, AtomicOrdering::Relaxed);
+    MINING_STATUS.active.store(true, AtomicOrdering::Relaxed);
 
     loop {
         if nonce % 100_000 == 0 {
             log::info!("nonce: {}", nonce);
         }
-        let hash = calculate_hash(id, nonce, timestamp, data.as_str(), previous_hash);
+        if nonce % 1_000 == 0 {
+            MINING_STATUS.nonce.store(nonce, AtomicOrdering::Relaxed);
+        }
+        let hash = calculate_hash(id, nonce, timestamp, data_root, state_root, previous_hash);
         let binary_hash = hash2binary(&hash);
-        if binary_hash.starts_with(DIFFICULTY_PREFIX) {
+        if binary_hash.starts_with(difficulty_prefix) {
             log::info!(
                 "mined! nonce: {}, hash: {}, binary hash: {}",
                 nonce,
                 hex::encode(&hash),
                 binary_hash
             );
+            MINING_STATUS.active.store(false, AtomicOrdering::Relaxed);
             return (nonce, hex::encode(hash));
         }
         nonce += 1;
     }
 }
 
-fn hash2binary(hash: &[u8]) -> String {
+pub(crate) fn hash2binary(hash: &[u8]) -> String {
     let mut res: String = String::default();
     for c in hash {
         res.push_str(&format!("{:b}", c));
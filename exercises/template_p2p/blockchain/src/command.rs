@@ -0,0 +1,810 @@
+use crate::{
+    keys::{self, KeyScheme},
+    p2p::{self, ChainApp},
+};
+use chrono::DateTime;
+use libp2p::PeerId;
+use std::{path::PathBuf, str::FromStr};
+
+/// Commands accepted by the node shell, with their arguments and a short description.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("ls peers", "list the discovered peers (alias: ls p)"),
+    ("ls chain", "print the local chain (alias: ls c)"),
+    (
+        "ls balances",
+        "print the balance of every address (alias: ls b)",
+    ),
+    (
+        "create block <data>",
+        "queue data to be mined into a new block (alias: create b)",
+    ),
+    ("sync <peer-id>", "ask a peer for its chain"),
+    (
+        "fetch <block-id> <peer-id>",
+        "ask a peer for the data of a block, in light mode",
+    ),
+    ("export-chain <file>", "write the local chain to a file"),
+    (
+        "import-chain <file>",
+        "load a chain from a file, if it is longer than the local one",
+    ),
+    (
+        "wallet new [name] [scheme]",
+        "create a wallet account, ed25519 or sr25519 (default)",
+    ),
+    (
+        "wallet import <name> [scheme] <uri>",
+        "add an account from a secret phrase, e.g. `//Alice`",
+    ),
+    ("wallet list", "list the wallet accounts"),
+    (
+        "wallet balance [name]",
+        "print the balance of a wallet account, or of all",
+    ),
+    (
+        "wallet send <to> <amount> [name]",
+        "pay an address from a wallet account, the first by default",
+    ),
+    (
+        "contract deploy <file> <gas> [name]",
+        "deploy the contract assembled from a file",
+    ),
+    (
+        "contract call <address> <gas> [args] [name]",
+        "call a contract, with comma separated arguments",
+    ),
+    (
+        "contract query <address> [args]",
+        "print what a call would return, without submitting it",
+    ),
+    (
+        "runtime",
+        "print the runtime params in effect and the scheduled upgrades",
+    ),
+    (
+        "runtime set <file> <block-id> [name]",
+        "schedule the params of a JSON file, as the sudo account",
+    ),
+    ("gov list", "list the proposals with their tallies"),
+    (
+        "gov propose <file> [name]",
+        "put the runtime params of a JSON file to the vote",
+    ),
+    (
+        "gov vote <proposal-id> <aye|nay> [name]",
+        "vote on a proposal, weighted by the balance",
+    ),
+    (
+        "find tx <hash>",
+        "print the blocks holding a transaction, extrinsic or data",
+    ),
+    (
+        "find address <address>",
+        "list the transactions and extrinsics of an address",
+    ),
+    (
+        "find time <unix|rfc3339>",
+        "print the head of the chain at a point in time",
+    ),
+    ("help", "show this help"),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    ListPeers,
+    ListChain,
+    ListBalances,
+    CreateBlock(String),
+    Sync(PeerId),
+    Fetch(u64, PeerId),
+    ExportChain(PathBuf),
+    ImportChain(PathBuf),
+    WalletNew {
+        name: Option<String>,
+        scheme: KeyScheme,
+    },
+    WalletImport {
+        name: String,
+        scheme: KeyScheme,
+        uri: String,
+    },
+    WalletList,
+    WalletBalance(Option<String>),
+    WalletSend {
+        to: String,
+        amount: u64,
+        from: Option<String>,
+    },
+    ContractDeploy {
+        file: PathBuf,
+        gas_limit: u64,
+        from: Option<String>,
+    },
+    ContractCall {
+        contract: String,
+        gas_limit: u64,
+        args: Vec<i64>,
+        from: Option<String>,
+    },
+    ContractQuery {
+        contract: String,
+        args: Vec<i64>,
+    },
+    Runtime,
+    RuntimeSet {
+        file: PathBuf,
+        at: u64,
+        from: Option<String>,
+    },
+    GovList,
+    GovPropose {
+        file: PathBuf,
+        from: Option<String>,
+    },
+    GovVote {
+        proposal: u64,
+        aye: bool,
+        from: Option<String>,
+    },
+    FindTx(String),
+    FindAddress(String),
+    FindTime(i64),
+    Help,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("empty command")]
+    Empty,
+    #[error("unknown command `{0}`, type `help` to list the commands")]
+    Unknown(String),
+    #[error("unknown subcommand `{command} {subcommand}`, type `help` to list the commands")]
+    UnknownSubcommand { command: String, subcommand: String },
+    #[error("`{command}` expects an argument: {argument}")]
+    MissingArgument {
+        command: String,
+        argument: &'static str,
+    },
+    #[error("`{command}` got an unexpected argument `{argument}`")]
+    UnexpectedArgument { command: String, argument: String },
+    #[error("invalid peer id `{0}`")]
+    InvalidPeerId(String),
+    #[error("invalid block id `{0}`")]
+    InvalidBlockId(String),
+    #[error("invalid key scheme `{0}`, expected ed25519 or sr25519")]
+    InvalidScheme(String),
+    #[error("invalid address `{0}`")]
+    InvalidAddress(String),
+    #[error("invalid amount `{0}`")]
+    InvalidAmount(String),
+    #[error("invalid gas limit `{0}`")]
+    InvalidGasLimit(String),
+    #[error("invalid proposal id `{0}`")]
+    InvalidProposal(String),
+    #[error("invalid vote `{0}`, expected aye or nay")]
+    InvalidVote(String),
+    #[error("invalid time `{0}`, expected a unix timestamp or an RFC 3339 date")]
+    InvalidTime(String),
+}
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err(CommandError::Empty);
+        };
+        let command = match name {
+            "ls" => match words.next() {
+                Some("p" | "peers") => Command::ListPeers,
+                Some("c" | "chain") => Command::ListChain,
+                Some("b" | "balances") => Command::ListBalances,
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => return Err(missing_argument(name, "peers | chain | balances")),
+            },
+            "create" => match words.next() {
+                Some(subcommand @ ("b" | "block")) => {
+                    // the data is the rest of the line, keeping its inner whitespace
+                    let data = line
+                        .trim_start()
+                        .trim_start_matches(name)
+                        .trim_start()
+                        .trim_start_matches(subcommand)
+                        .trim();
+                    if data.is_empty() {
+                        return Err(missing_argument("create block", "<data>"));
+                    }
+                    return Ok(Command::CreateBlock(String::from(data)));
+                }
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => return Err(missing_argument(name, "block <data>")),
+            },
+            "sync" => match words.next() {
+                Some(peer) => Command::Sync(parse_peer_id(peer)?),
+                None => return Err(missing_argument(name, "<peer-id>")),
+            },
+            "fetch" => match (words.next(), words.next()) {
+                (Some(id), Some(peer)) => Command::Fetch(
+                    id.parse()
+                        .map_err(|_| CommandError::InvalidBlockId(String::from(id)))?,
+                    parse_peer_id(peer)?,
+                ),
+                _ => return Err(missing_argument(name, "<block-id> <peer-id>")),
+            },
+            "export-chain" => match words.next() {
+                Some(file) => Command::ExportChain(PathBuf::from(file)),
+                None => return Err(missing_argument(name, "<file>")),
+            },
+            "import-chain" => match words.next() {
+                Some(file) => Command::ImportChain(PathBuf::from(file)),
+                None => return Err(missing_argument(name, "<file>")),
+            },
+            "wallet" => match words.next() {
+                Some("new") => {
+                    let name = words.next().map(String::from);
+                    let scheme = match words.next() {
+                        Some(scheme) => scheme
+                            .parse()
+                            .map_err(|_| CommandError::InvalidScheme(String::from(scheme)))?,
+                        None => KeyScheme::Sr25519,
+                    };
+                    Command::WalletNew { name, scheme }
+                }
+                Some("import") => {
+                    let Some(account) = words.next() else {
+                        return Err(missing_argument("wallet import", "<name> [scheme] <uri>"));
+                    };
+                    let mut uri: Vec<&str> = words.by_ref().collect();
+                    let scheme = match uri.first().map(|w| w.parse::<KeyScheme>()) {
+                        Some(Ok(scheme)) => {
+                            uri.remove(0);
+                            scheme
+                        }
+                        _ => KeyScheme::Sr25519,
+                    };
+                    if uri.is_empty() {
+                        return Err(missing_argument("wallet import", "<uri>"));
+                    }
+                    Command::WalletImport {
+                        name: String::from(account),
+                        scheme,
+                        // a phrase is several words, joined back with single spaces
+                        uri: uri.join(" "),
+                    }
+                }
+                Some("list") => Command::WalletList,
+                Some("balance") => Command::WalletBalance(words.next().map(String::from)),
+                Some("send") => match (words.next(), words.next()) {
+                    (Some(to), Some(amount)) => {
+                        keys::decode_address(to)
+                            .map_err(|_| CommandError::InvalidAddress(String::from(to)))?;
+                        Command::WalletSend {
+                            to: String::from(to),
+                            amount: amount
+                                .parse()
+                                .map_err(|_| CommandError::InvalidAmount(String::from(amount)))?,
+                            from: words.next().map(String::from),
+                        }
+                    }
+                    _ => return Err(missing_argument("wallet send", "<to> <amount>")),
+                },
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => {
+                    return Err(missing_argument(
+                        name,
+                        "new | import | list | balance | send",
+                    ))
+                }
+            },
+            "contract" => match words.next() {
+                Some("deploy") => match (words.next(), words.next()) {
+                    (Some(file), Some(gas_limit)) => Command::ContractDeploy {
+                        file: PathBuf::from(file),
+                        gas_limit: parse_gas_limit(gas_limit)?,
+                        from: words.next().map(String::from),
+                    },
+                    _ => return Err(missing_argument("contract deploy", "<file> <gas>")),
+                },
+                Some("call") => match (words.next(), words.next()) {
+                    (Some(contract), Some(gas_limit)) => {
+                        let mut next = words.next();
+                        let args = match next.and_then(parse_args) {
+                            Some(args) => {
+                                next = words.next();
+                                args
+                            }
+                            None => Vec::new(),
+                        };
+                        Command::ContractCall {
+                            contract: parse_address(contract)?,
+                            gas_limit: parse_gas_limit(gas_limit)?,
+                            args,
+                            from: next.map(String::from),
+                        }
+                    }
+                    _ => return Err(missing_argument("contract call", "<address> <gas>")),
+                },
+                Some("query") => match words.next() {
+                    Some(contract) => Command::ContractQuery {
+                        contract: parse_address(contract)?,
+                        args: match words.next() {
+                            Some(args) => parse_args(args).ok_or_else(|| {
+                                CommandError::UnexpectedArgument {
+                                    command: String::from("contract query"),
+                                    argument: String::from(args),
+                                }
+                            })?,
+                            None => Vec::new(),
+                        },
+                    },
+                    None => return Err(missing_argument("contract query", "<address>")),
+                },
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => return Err(missing_argument(name, "deploy | call | query")),
+            },
+            "runtime" => match words.next() {
+                Some("set") => match (words.next(), words.next()) {
+                    (Some(file), Some(at)) => Command::RuntimeSet {
+                        file: PathBuf::from(file),
+                        at: at
+                            .parse()
+                            .map_err(|_| CommandError::InvalidBlockId(String::from(at)))?,
+                        from: words.next().map(String::from),
+                    },
+                    _ => return Err(missing_argument("runtime set", "<file> <block-id>")),
+                },
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => Command::Runtime,
+            },
+            "gov" => match words.next() {
+                Some("list") => Command::GovList,
+                Some("propose") => match words.next() {
+                    Some(file) => Command::GovPropose {
+                        file: PathBuf::from(file),
+                        from: words.next().map(String::from),
+                    },
+                    None => return Err(missing_argument("gov propose", "<file>")),
+                },
+                Some("vote") => match (words.next(), words.next()) {
+                    (Some(proposal), Some(vote)) => Command::GovVote {
+                        proposal: proposal
+                            .parse()
+                            .map_err(|_| CommandError::InvalidProposal(String::from(proposal)))?,
+                        aye: match vote {
+                            "aye" => true,
+                            "nay" => false,
+                            _ => return Err(CommandError::InvalidVote(String::from(vote))),
+                        },
+                        from: words.next().map(String::from),
+                    },
+                    _ => return Err(missing_argument("gov vote", "<proposal-id> <aye|nay>")),
+                },
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => return Err(missing_argument(name, "list | propose | vote")),
+            },
+            "find" => match words.next() {
+                Some("tx") => match words.next() {
+                    Some(hash) => Command::FindTx(String::from(hash)),
+                    None => return Err(missing_argument("find tx", "<hash>")),
+                },
+                Some("address") => match words.next() {
+                    Some(address) => Command::FindAddress(parse_address(address)?),
+                    None => return Err(missing_argument("find address", "<address>")),
+                },
+                Some("time") => match words.next() {
+                    Some(time) => Command::FindTime(parse_time(time)?),
+                    None => return Err(missing_argument("find time", "<unix|rfc3339>")),
+                },
+                Some(subcommand) => return Err(unknown_subcommand(name, subcommand)),
+                None => return Err(missing_argument(name, "tx | address | time")),
+            },
+            "help" => Command::Help,
+            _ => return Err(CommandError::Unknown(String::from(name))),
+        };
+        match words.next() {
+            Some(argument) => Err(CommandError::UnexpectedArgument {
+                command: String::from(name),
+                argument: String::from(argument),
+            }),
+            None => Ok(command),
+        }
+    }
+}
+
+fn parse_peer_id(peer: &str) -> Result<PeerId, CommandError> {
+    peer.parse()
+        .map_err(|_| CommandError::InvalidPeerId(String::from(peer)))
+}
+
+/// Parses a unix timestamp in seconds, or an RFC 3339 date like `2024-05-01T12:00:00Z`.
+fn parse_time(time: &str) -> Result<i64, CommandError> {
+    time.parse()
+        .or_else(|_| DateTime::parse_from_rfc3339(time).map(|t| t.timestamp()))
+        .map_err(|_| CommandError::InvalidTime(String::from(time)))
+}
+
+fn parse_address(address: &str) -> Result<String, CommandError> {
+    keys::decode_address(address)
+        .map_err(|_| CommandError::InvalidAddress(String::from(address)))?;
+    Ok(String::from(address))
+}
+
+fn parse_gas_limit(gas_limit: &str) -> Result<u64, CommandError> {
+    gas_limit
+        .parse()
+        .map_err(|_| CommandError::InvalidGasLimit(String::from(gas_limit)))
+}
+
+/// Contract arguments, comma separated integers like `1,-2`.
+fn parse_args(args: &str) -> Option<Vec<i64>> {
+    args.split(',').map(|arg| arg.parse().ok()).collect()
+}
+
+fn unknown_subcommand(command: &str, subcommand: &str) -> CommandError {
+    CommandError::UnknownSubcommand {
+        command: String::from(command),
+        subcommand: String::from(subcommand),
+    }
+}
+
+fn missing_argument(command: &str, argument: &'static str) -> CommandError {
+    CommandError::MissingArgument {
+        command: String::from(command),
+        argument,
+    }
+}
+
+/// Whether the line holds a secret phrase, which is kept out of the shell history. Lines
+/// that don't parse are matched too, in case of a typo in the rest of the command.
+pub fn holds_secret(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    words.next() == Some("wallet") && words.next() == Some("import")
+}
+
+pub fn handle_input(line: &str, chain_app: &mut ChainApp) {
+    match line.parse() {
+        Ok(command) => handle_command(command, chain_app),
+        Err(CommandError::Empty) => {}
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+pub fn handle_command(command: Command, chain_app: &mut ChainApp) {
+    match command {
+        Command::ListPeers => p2p::handle_print_peers(&chain_app.swarm),
+        Command::ListChain => match &chain_app.light {
+            Some(light) => p2p::handle_print_headers(&light.headers),
+            None => p2p::handle_print_chain(&chain_app.chain),
+        },
+        Command::ListBalances if chain_app.light.is_some() => {
+            log::error!("light clients don't keep the rewards, ask a full node")
+        }
+        Command::ListBalances => p2p::handle_print_balances(chain_app),
+        Command::CreateBlock(data) => {
+            p2p::submit_data(data, chain_app);
+        }
+        Command::Sync(peer) => p2p::request_chain(&peer, chain_app),
+        Command::Fetch(id, peer) => match &chain_app.light {
+            Some(light) if light.headers.get(id).is_none() => {
+                log::error!("no header for block {}, sync first", id)
+            }
+            Some(_) => p2p::request_body(id, &peer, chain_app),
+            None => log::error!("`fetch` is only for light clients, use `ls c`"),
+        },
+        Command::ExportChain(path) => p2p::handle_export_chain(&path, chain_app),
+        Command::ImportChain(path) => p2p::handle_import_chain(&path, chain_app),
+        Command::WalletNew { name, scheme } => p2p::handle_wallet_new(name, scheme, chain_app),
+        Command::WalletImport { name, scheme, uri } => {
+            p2p::handle_wallet_import(name, scheme, &uri, chain_app)
+        }
+        Command::WalletList => p2p::handle_print_wallet(chain_app),
+        Command::WalletBalance(name) => p2p::handle_wallet_balance(name.as_deref(), chain_app),
+        Command::WalletSend { to, amount, from } => {
+            match p2p::wallet_send(&to, amount, from.as_deref(), chain_app) {
+                Ok(hash) => log::info!("sent {} to {} in {}", amount, to, hash),
+                Err(e) => log::error!("can't send: {}", e),
+            }
+        }
+        Command::ContractDeploy {
+            file,
+            gas_limit,
+            from,
+        } => p2p::handle_contract_deploy(&file, gas_limit, from.as_deref(), chain_app),
+        Command::ContractCall {
+            contract,
+            gas_limit,
+            args,
+            from,
+        } => p2p::handle_contract_call(contract, gas_limit, args, from.as_deref(), chain_app),
+        Command::ContractQuery { contract, args } => {
+            p2p::handle_contract_query(&contract, &args, chain_app)
+        }
+        Command::Runtime => p2p::handle_print_runtime(chain_app),
+        Command::RuntimeSet { file, at, from } => {
+            p2p::handle_set_runtime(&file, at, from.as_deref(), chain_app)
+        }
+        Command::GovList => p2p::handle_print_proposals(chain_app),
+        Command::GovPropose { file, from } => {
+            p2p::handle_propose(&file, from.as_deref(), chain_app)
+        }
+        Command::GovVote {
+            proposal,
+            aye,
+            from,
+        } => p2p::handle_vote(proposal, aye, from.as_deref(), chain_app),
+        Command::FindTx(hash) => p2p::handle_find_tx(&hash, chain_app),
+        Command::FindAddress(address) => p2p::handle_find_address(&address, chain_app),
+        Command::FindTime(timestamp) => p2p::handle_find_time(timestamp, chain_app),
+        Command::Help => handle_print_help(),
+    }
+}
+
+pub fn handle_print_help() {
+    log::info!("Commands:");
+    COMMANDS
+        .iter()
+        .for_each(|(usage, about)| log::info!("  {:<45}{}", usage, about));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_aliases() {
+        assert_eq!("ls p".parse(), Ok(Command::ListPeers));
+        assert_eq!("ls peers".parse(), Ok(Command::ListPeers));
+        assert_eq!("  ls   c ".parse(), Ok(Command::ListChain));
+        assert_eq!("ls b".parse(), Ok(Command::ListBalances));
+        assert_eq!("help".parse(), Ok(Command::Help));
+        assert_eq!(
+            "export-chain chain.jsonl".parse(),
+            Ok(Command::ExportChain(PathBuf::from("chain.jsonl")))
+        );
+        let peer = PeerId::random();
+        assert_eq!(format!("sync {}", peer).parse(), Ok(Command::Sync(peer)));
+        assert_eq!(
+            format!("fetch 3 {}", peer).parse(),
+            Ok(Command::Fetch(3, peer))
+        );
+    }
+
+    #[test]
+    fn create_block_keeps_the_rest_of_the_line() {
+        assert_eq!(
+            "create b hello  world".parse(),
+            Ok(Command::CreateBlock(String::from("hello  world")))
+        );
+        assert_eq!(
+            "create block b".parse(),
+            Ok(Command::CreateBlock(String::from("b")))
+        );
+        assert_eq!(
+            "create b".parse::<Command>(),
+            Err(missing_argument("create block", "<data>"))
+        );
+    }
+
+    #[test]
+    fn parses_wallet_commands() {
+        assert_eq!(
+            "wallet new".parse(),
+            Ok(Command::WalletNew {
+                name: None,
+                scheme: KeyScheme::Sr25519
+            })
+        );
+        assert_eq!(
+            "wallet new alice ed25519".parse(),
+            Ok(Command::WalletNew {
+                name: Some(String::from("alice")),
+                scheme: KeyScheme::Ed25519
+            })
+        );
+        assert_eq!(
+            "wallet import bob //Bob".parse(),
+            Ok(Command::WalletImport {
+                name: String::from("bob"),
+                scheme: KeyScheme::Sr25519,
+                uri: String::from("//Bob"),
+            })
+        );
+        assert_eq!(
+            format!("wallet import mine ed25519  {}//stash", keys::DEV_PHRASE).parse(),
+            Ok(Command::WalletImport {
+                name: String::from("mine"),
+                scheme: KeyScheme::Ed25519,
+                uri: format!("{}//stash", keys::DEV_PHRASE),
+            })
+        );
+        assert!(holds_secret(" wallet  import mine //stash"));
+        assert!(holds_secret("wallet import bob sr25519"));
+        assert!(!holds_secret("wallet new bob"));
+        assert_eq!(
+            "wallet import bob sr25519".parse::<Command>(),
+            Err(CommandError::MissingArgument {
+                command: String::from("wallet import"),
+                argument: "<uri>",
+            })
+        );
+        assert_eq!(
+            "wallet balance alice".parse(),
+            Ok(Command::WalletBalance(Some(String::from("alice"))))
+        );
+        let to = keys::encode_address(&[1; 32]);
+        assert_eq!(
+            format!("wallet send {} 20 alice", to).parse(),
+            Ok(Command::WalletSend {
+                to: to.clone(),
+                amount: 20,
+                from: Some(String::from("alice")),
+            })
+        );
+        assert_eq!(
+            format!("wallet send {} lots", to).parse::<Command>(),
+            Err(CommandError::InvalidAmount(String::from("lots")))
+        );
+        assert_eq!(
+            "wallet send bob 20".parse::<Command>(),
+            Err(CommandError::InvalidAddress(String::from("bob")))
+        );
+        assert_eq!(
+            "wallet new alice rsa".parse::<Command>(),
+            Err(CommandError::InvalidScheme(String::from("rsa")))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!("".parse::<Command>(), Err(CommandError::Empty));
+        assert_eq!(
+            "ls cheese".parse::<Command>(),
+            Err(unknown_subcommand("ls", "cheese"))
+        );
+        assert_eq!(
+            "ls c extra".parse::<Command>(),
+            Err(CommandError::UnexpectedArgument {
+                command: String::from("ls"),
+                argument: String::from("extra"),
+            })
+        );
+        assert_eq!(
+            "mine".parse::<Command>(),
+            Err(CommandError::Unknown(String::from("mine")))
+        );
+        assert_eq!(
+            "sync alice".parse::<Command>(),
+            Err(CommandError::InvalidPeerId(String::from("alice")))
+        );
+        assert_eq!(
+            format!("fetch tip {}", PeerId::random()).parse::<Command>(),
+            Err(CommandError::InvalidBlockId(String::from("tip")))
+        );
+    }
+
+    #[test]
+    fn parses_contract_commands() {
+        let contract = keys::Keypair::generate(KeyScheme::Ed25519).address();
+        assert_eq!(
+            "contract deploy counter.asm 5000".parse(),
+            Ok(Command::ContractDeploy {
+                file: PathBuf::from("counter.asm"),
+                gas_limit: 5000,
+                from: None,
+            })
+        );
+        assert_eq!(
+            format!("contract call {} 5000 1,-2 alice", contract).parse(),
+            Ok(Command::ContractCall {
+                contract: contract.clone(),
+                gas_limit: 5000,
+                args: vec![1, -2],
+                from: Some(String::from("alice")),
+            })
+        );
+        assert_eq!(
+            format!("contract call {} 5000 alice", contract).parse(),
+            Ok(Command::ContractCall {
+                contract: contract.clone(),
+                gas_limit: 5000,
+                args: Vec::new(),
+                from: Some(String::from("alice")),
+            })
+        );
+        assert_eq!(
+            format!("contract query {} 3", contract).parse(),
+            Ok(Command::ContractQuery {
+                contract,
+                args: vec![3],
+            })
+        );
+        assert_eq!(
+            "contract deploy counter.asm lots".parse::<Command>(),
+            Err(CommandError::InvalidGasLimit(String::from("lots")))
+        );
+    }
+
+    #[test]
+    fn parses_runtime_commands() {
+        assert_eq!("runtime".parse(), Ok(Command::Runtime));
+        assert_eq!(
+            "runtime set v2.json 100 root".parse(),
+            Ok(Command::RuntimeSet {
+                file: PathBuf::from("v2.json"),
+                at: 100,
+                from: Some(String::from("root")),
+            })
+        );
+        assert_eq!(
+            "runtime set v2.json soon".parse::<Command>(),
+            Err(CommandError::InvalidBlockId(String::from("soon")))
+        );
+        assert_eq!(
+            "runtime set v2.json".parse::<Command>(),
+            Err(CommandError::MissingArgument {
+                command: String::from("runtime set"),
+                argument: "<file> <block-id>",
+            })
+        );
+    }
+
+    #[test]
+    fn parses_governance_commands() {
+        assert_eq!("gov list".parse(), Ok(Command::GovList));
+        assert_eq!(
+            "gov propose v2.json".parse(),
+            Ok(Command::GovPropose {
+                file: PathBuf::from("v2.json"),
+                from: None,
+            })
+        );
+        assert_eq!(
+            "gov vote 0 nay bob".parse(),
+            Ok(Command::GovVote {
+                proposal: 0,
+                aye: false,
+                from: Some(String::from("bob")),
+            })
+        );
+        assert_eq!(
+            "gov vote 0 maybe".parse::<Command>(),
+            Err(CommandError::InvalidVote(String::from("maybe")))
+        );
+        assert_eq!(
+            "gov vote first aye".parse::<Command>(),
+            Err(CommandError::InvalidProposal(String::from("first")))
+        );
+    }
+
+    #[test]
+    fn parses_find_commands() {
+        assert_eq!(
+            "find tx abc".parse(),
+            Ok(Command::FindTx(String::from("abc")))
+        );
+        assert_eq!(
+            "find time 1700000000".parse(),
+            Ok(Command::FindTime(1700000000))
+        );
+        assert_eq!(
+            "find time 2023-11-14T22:13:20Z".parse(),
+            Ok(Command::FindTime(1700000000))
+        );
+        assert_eq!(
+            "find time yesterday".parse::<Command>(),
+            Err(CommandError::InvalidTime(String::from("yesterday")))
+        );
+        assert_eq!(
+            "find address bob".parse::<Command>(),
+            Err(CommandError::InvalidAddress(String::from("bob")))
+        );
+        assert_eq!(
+            "find tx".parse::<Command>(),
+            Err(CommandError::MissingArgument {
+                command: String::from("find tx"),
+                argument: "<hash>",
+            })
+        );
+    }
+}
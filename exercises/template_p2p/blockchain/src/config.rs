@@ -0,0 +1,104 @@
+//! Node settings read from a TOML file, every section and field is optional:
+//!
+//! ```toml
+//! [limits]
+//! max_inbound_peers = 25
+//! max_connections_per_ip = 4
+//!
+//! [gossipsub]
+//! max_transmit_size = 65536
+//! history_length = 5
+//! ```
+use crate::{limits::Limits, Result};
+use libp2p::gossipsub;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    pub limits: Limits,
+    pub gossipsub: GossipsubLimits,
+}
+
+impl NodeConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let config: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        // catch settings gossipsub would refuse now, rather than when the node starts
+        let mut gossipsub_config = gossipsub::ConfigBuilder::default();
+        config.gossipsub.apply(&mut gossipsub_config);
+        gossipsub_config.build()?;
+        Ok(config)
+    }
+}
+
+/// Caps on the memory gossipsub uses for the messages it keeps around.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GossipsubLimits {
+    /// Largest message accepted or published, in bytes. Whole chains travel in one message.
+    pub max_transmit_size: usize,
+    /// Heartbeats a published message stays in the cache, to answer gossip about it.
+    pub history_length: usize,
+    /// Heartbeats of the cache advertised to peers, at most `history_length`.
+    pub history_gossip: usize,
+    /// How long the ids of seen messages are remembered, to drop duplicates.
+    pub duplicate_cache_secs: u64,
+    /// Messages accepted in a single RPC from a peer.
+    pub max_messages_per_rpc: Option<usize>,
+}
+
+impl Default for GossipsubLimits {
+    fn default() -> Self {
+        Self {
+            max_transmit_size: 1024 * 1024,
+            history_length: 5,
+            history_gossip: 3,
+            duplicate_cache_secs: 60,
+            max_messages_per_rpc: Some(64),
+        }
+    }
+}
+
+impl GossipsubLimits {
+    pub fn apply(&self, builder: &mut gossipsub::ConfigBuilder) {
+        builder
+            .max_transmit_size(self.max_transmit_size)
+            .history_length(self.history_length)
+            .history_gossip(self.history_gossip)
+            .duplicate_cache_time(Duration::from_secs(self.duplicate_cache_secs))
+            .max_messages_per_rpc(self.max_messages_per_rpc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        let config: NodeConfig = toml::from_str(
+            r#"
+            [limits]
+            max_connections_per_ip = 2
+
+            [gossipsub]
+            history_length = 10
+            "#,
+        )
+        .expect("valid config");
+        assert_eq!(config.limits.max_connections_per_ip, Some(2));
+        assert_eq!(
+            config.limits.max_inbound_peers,
+            Limits::default().max_inbound_peers
+        );
+        assert_eq!(config.gossipsub.history_length, 10);
+        assert_eq!(config.gossipsub.history_gossip, 3);
+        assert_eq!(toml::from_str::<NodeConfig>(""), Ok(NodeConfig::default()));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(toml::from_str::<NodeConfig>("[limits]\nmax_peers = 3").is_err());
+    }
+}
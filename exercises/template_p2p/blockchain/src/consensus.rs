@@ -0,0 +1,374 @@
+//! Block production and seal checking, behind the [`Consensus`] trait.
+//!
+//! A node runs one engine, picked by its chain spec: proof-of-work by default, or Aura-style
+//! proof-of-authority where a fixed set of validators take turns to sign blocks, one slot each.
+use crate::{
+    blocks::{hash2binary, mine_block, BlockError, Header},
+    finality::FinalitySpec,
+    governance::GovernanceSpec,
+    ledger::{LedgerMode, RewardSpec},
+    runtime::{self, RuntimeSpec},
+};
+use chrono::Utc;
+use libp2p::identity::ed25519;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, thread, time::Duration};
+
+pub trait Consensus: Send + Sync {
+    /// Fills in the seal of `header` so it can follow `previous`: nonce, hash and signature.
+    /// May wait, or move the timestamp forward, until the node is allowed to author a block.
+    fn seal(&self, header: &mut Header, previous: &Header) -> Result<(), ConsensusError>;
+    /// Checks the seal of `header`, the linkage and hash are checked by [`Header::validate`].
+    fn verify(&self, header: &Header, previous: &Header) -> Result<(), BlockError>;
+    /// Whether this node can author blocks at all.
+    fn can_author(&self) -> bool {
+        true
+    }
+    /// Whether the seal is a proof-of-work, whose difficulty the runtime params can raise.
+    fn is_proof_of_work(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConsensusError {
+    #[error("this node is not one of the validators")]
+    NotAuthority,
+    #[error("invalid validator key: {0}")]
+    InvalidKey(String),
+    #[error("the validator set is empty")]
+    NoValidators,
+    #[error("the slot duration must be at least a second")]
+    InvalidSlotDuration,
+    #[error("the consensus engine is already set")]
+    AlreadySet,
+}
+
+static ENGINE: OnceCell<Box<dyn Consensus>> = OnceCell::new();
+
+/// Engine of this node, proof-of-work unless [`set_engine`] was called.
+pub fn engine() -> &'static dyn Consensus {
+    ENGINE.get().map_or(&ProofOfWork, Box::as_ref)
+}
+
+/// Sets the engine of this node, once, before any block is produced or checked.
+pub fn set_engine(engine: Box<dyn Consensus>) -> Result<(), ConsensusError> {
+    ENGINE.set(engine).map_err(|_| ConsensusError::AlreadySet)
+}
+
+/// Blocks are sealed with a nonce that gives their hash enough leading zeros.
+pub struct ProofOfWork;
+
+impl ProofOfWork {
+    /// Mines until the hash in binary starts with `difficulty_prefix`.
+    pub fn mine(header: &mut Header, difficulty_prefix: &str) {
+        let (nonce, hash) = mine_block(
+            header.id,
+            header.timestamp,
+            &header.data_root,
+            header.state_root.as_deref(),
+            &header.previous_hash,
+            difficulty_prefix,
+        );
+        header.nonce = nonce;
+        header.hash = hash;
+    }
+}
+
+impl Consensus for ProofOfWork {
+    fn seal(&self, header: &mut Header, _previous: &Header) -> Result<(), ConsensusError> {
+        Self::mine(header, &runtime::difficulty_prefix(header.id));
+        Ok(())
+    }
+
+    fn verify(&self, header: &Header, _previous: &Header) -> Result<(), BlockError> {
+        let hash = hex::decode(&header.hash).unwrap_or_default();
+        if !hash2binary(&hash).starts_with(&runtime::difficulty_prefix(header.id)) {
+            return Err(BlockError::InvalidDifficulty { id: header.id });
+        }
+        Ok(())
+    }
+
+    fn is_proof_of_work(&self) -> bool {
+        true
+    }
+}
+
+/// Time is cut in slots of `slot_duration_secs`, the validators take turns to author the block
+/// of a slot in the order they are listed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuraSpec {
+    #[serde(default = "default_slot_duration")]
+    pub slot_duration_secs: u64,
+    /// Hex encoded ed25519 public keys.
+    pub validators: Vec<String>,
+}
+
+fn default_slot_duration() -> u64 {
+    2
+}
+
+/// Slots a block can be ahead of the local clock, for clocks a bit off.
+pub const MAX_FUTURE_SLOTS: u64 = 1;
+
+pub struct Aura {
+    slot_duration: u64,
+    validators: Vec<ed25519::PublicKey>,
+    /// Key of this node, if it is a validator.
+    author: Option<ed25519::Keypair>,
+}
+
+impl Aura {
+    pub fn new(spec: &AuraSpec, author: Option<ed25519::Keypair>) -> Result<Self, ConsensusError> {
+        if spec.slot_duration_secs == 0 {
+            return Err(ConsensusError::InvalidSlotDuration);
+        }
+        Ok(Self {
+            slot_duration: spec.slot_duration_secs,
+            validators: parse_validators(&spec.validators)?,
+            author,
+        })
+    }
+
+    fn slot(&self, timestamp: i64) -> u64 {
+        timestamp.max(0) as u64 / self.slot_duration
+    }
+
+    fn author_of(&self, slot: u64) -> &ed25519::PublicKey {
+        &self.validators[(slot % self.validators.len() as u64) as usize]
+    }
+
+    fn author_index(&self) -> Option<usize> {
+        let public = self.author.as_ref()?.public();
+        self.validators.iter().position(|v| *v == public)
+    }
+}
+
+impl Consensus for Aura {
+    fn seal(&self, header: &mut Header, previous: &Header) -> Result<(), ConsensusError> {
+        let (Some(author), Some(index)) = (&self.author, self.author_index()) else {
+            return Err(ConsensusError::NotAuthority);
+        };
+        let n = self.validators.len() as u64;
+        let now = Utc::now().timestamp();
+        // our next slot after the parent's
+        let earliest = self.slot(now).max(self.slot(previous.timestamp) + 1);
+        let slot = earliest + (index as u64 + n - earliest % n) % n;
+        let start = (slot * self.slot_duration) as i64;
+        if start > now {
+            log::info!("waiting for slot {}", slot);
+            thread::sleep(Duration::from_secs((start - now) as u64));
+        }
+        header.timestamp = start.max(now);
+        header.nonce = 0;
+        let hash = header.calculate_hash();
+        header.signature = Some(hex::encode(author.sign(&hash)));
+        header.hash = hex::encode(hash);
+        Ok(())
+    }
+
+    fn verify(&self, header: &Header, previous: &Header) -> Result<(), BlockError> {
+        let id = header.id;
+        let slot = self.slot(header.timestamp);
+        if slot <= self.slot(previous.timestamp) {
+            return Err(BlockError::InvalidSlot { id });
+        }
+        // authors wait for a slot after the parent's, a far future one would stall them
+        let current = self.slot(Utc::now().timestamp());
+        if slot > current + MAX_FUTURE_SLOTS {
+            return Err(BlockError::FutureSlot { id, slot, current });
+        }
+        let signed = header
+            .signature
+            .as_ref()
+            .and_then(|signature| hex::decode(signature).ok())
+            .zip(hex::decode(&header.hash).ok())
+            .is_some_and(|(signature, hash)| self.author_of(slot).verify(&hash, &signature));
+        if !signed {
+            return Err(BlockError::InvalidSeal { id });
+        }
+        Ok(())
+    }
+
+    fn can_author(&self) -> bool {
+        self.author_index().is_some()
+    }
+}
+
+/// Decodes hex encoded ed25519 public keys, refusing an empty set.
+pub(crate) fn parse_validators(keys: &[String]) -> Result<Vec<ed25519::PublicKey>, ConsensusError> {
+    if keys.is_empty() {
+        return Err(ConsensusError::NoValidators);
+    }
+    keys.iter()
+        .map(|key| {
+            hex::decode(key)
+                .ok()
+                .and_then(|bytes| ed25519::PublicKey::try_from_bytes(&bytes).ok())
+                .ok_or_else(|| ConsensusError::InvalidKey(key.clone()))
+        })
+        .collect()
+}
+
+/// Which engine a network runs, read from a JSON chain spec such as:
+///
+/// ```json
+/// { "name": "classroom", "consensus": { "engine": "aura", "slot_duration_secs": 2,
+///   "validators": ["3b6a27bc...", "8a88e3dd..."] } }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSpec {
+    pub name: String,
+    pub consensus: ConsensusSpec,
+    /// Validators voting blocks final, on top of the engine's fork choice.
+    #[serde(default)]
+    pub finality: Option<FinalitySpec>,
+    /// Block reward and its halving schedule.
+    #[serde(default)]
+    pub rewards: RewardSpec,
+    /// Accounts or unspent transaction outputs.
+    #[serde(default)]
+    pub ledger: LedgerMode,
+    /// Upgrades of the rules at given heights, and who can schedule more on chain.
+    #[serde(default)]
+    pub runtime: RuntimeSpec,
+    /// Voting periods of the proposals to change the runtime params.
+    #[serde(default)]
+    pub governance: GovernanceSpec,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "engine", rename_all = "lowercase")]
+pub enum ConsensusSpec {
+    Pow,
+    Aura(AuraSpec),
+}
+
+impl ChainSpec {
+    pub fn from_file(path: impl AsRef<Path>) -> crate::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Builds the engine of the spec, `author` is the key this node signs blocks with.
+    pub fn engine(
+        &self,
+        author: Option<ed25519::Keypair>,
+    ) -> Result<Box<dyn Consensus>, ConsensusError> {
+        Ok(match &self.consensus {
+            ConsensusSpec::Pow => Box::new(ProofOfWork),
+            ConsensusSpec::Aura(spec) => Box::new(Aura::new(spec, author)?),
+        })
+    }
+}
+
+/// Reads a hex encoded ed25519 secret key, as written by e.g. `openssl rand -hex 32`.
+pub fn read_authority_key(path: impl AsRef<Path>) -> crate::Result<ed25519::Keypair> {
+    let secret = hex::decode(fs::read_to_string(path)?.trim())?;
+    Ok(ed25519::SecretKey::try_from_bytes(secret)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::Block;
+
+    fn aura(keys: &[&ed25519::Keypair], author: &ed25519::Keypair) -> Aura {
+        let spec = AuraSpec {
+            slot_duration_secs: 1,
+            validators: keys
+                .iter()
+                .map(|k| hex::encode(k.public().to_bytes()))
+                .collect(),
+        };
+        Aura::new(&spec, Some(author.clone())).expect("valid spec")
+    }
+
+    #[test]
+    fn validators_take_turns() {
+        let (alice, bob) = (ed25519::Keypair::generate(), ed25519::Keypair::generate());
+        let (by_alice, by_bob) = (aura(&[&alice, &bob], &alice), aura(&[&alice, &bob], &bob));
+        let genesis = Block::default().header;
+
+        let mut first = genesis.clone();
+        first.id = 1;
+        first.previous_hash = genesis.hash.clone();
+        by_alice
+            .seal(&mut first, &genesis)
+            .expect("alice is a validator");
+        assert_eq!(by_bob.verify(&first, &genesis), Ok(()));
+        assert_eq!(by_alice.slot(first.timestamp) % 2, 0);
+
+        let mut second = first.clone();
+        second.id = 2;
+        second.previous_hash = first.hash.clone();
+        by_bob
+            .seal(&mut second, &first)
+            .expect("bob is a validator");
+        assert_eq!(by_alice.verify(&second, &first), Ok(()));
+        assert!(by_alice.slot(second.timestamp) > by_alice.slot(first.timestamp));
+
+        // bob's block claiming alice's slot
+        let mut forged = second.clone();
+        forged.timestamp = first.timestamp + 2;
+        let hash = forged.calculate_hash();
+        forged.signature = Some(hex::encode(bob.sign(&hash)));
+        forged.hash = hex::encode(hash);
+        assert_eq!(
+            by_alice.verify(&forged, &first),
+            Err(BlockError::InvalidSeal { id: 2 })
+        );
+        forged.timestamp = first.timestamp;
+        assert_eq!(
+            by_alice.verify(&forged, &first),
+            Err(BlockError::InvalidSlot { id: 2 })
+        );
+    }
+
+    #[test]
+    fn only_validators_author_blocks() {
+        let (alice, eve) = (ed25519::Keypair::generate(), ed25519::Keypair::generate());
+        let by_eve = aura(&[&alice], &eve);
+        assert!(!by_eve.can_author());
+        let genesis = Block::default().header;
+        assert!(matches!(
+            by_eve.seal(&mut genesis.clone(), &genesis),
+            Err(ConsensusError::NotAuthority)
+        ));
+        assert!(matches!(
+            Aura::new(
+                &AuraSpec {
+                    slot_duration_secs: 1,
+                    validators: vec![String::from("00")],
+                },
+                None
+            ),
+            Err(ConsensusError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn blocks_from_the_future_are_refused() {
+        let alice = ed25519::Keypair::generate();
+        let by_alice = aura(&[&alice], &alice);
+        let genesis = Block::default().header;
+        let mut next = genesis.clone();
+        next.id = 1;
+        next.previous_hash = genesis.hash.clone();
+        let sign = |header: &mut Header, timestamp: i64| {
+            header.timestamp = timestamp;
+            let hash = header.calculate_hash();
+            header.signature = Some(hex::encode(alice.sign(&hash)));
+            header.hash = hex::encode(hash);
+        };
+        let now = Utc::now().timestamp();
+        sign(&mut next, now + 1);
+        assert_eq!(by_alice.verify(&next, &genesis), Ok(()));
+        // a year ahead
+        sign(&mut next, now + 365 * 24 * 3600);
+        assert!(matches!(
+            by_alice.verify(&next, &genesis),
+            Err(BlockError::FutureSlot { id: 1, .. })
+        ));
+    }
+}
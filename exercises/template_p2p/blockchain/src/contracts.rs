@@ -0,0 +1,386 @@
+//! Contracts of the account ledger, run by a small stack machine.
+//!
+//! Contract code is a list of [`Instr`] over a stack of `i64`, with a key-value [`Storage`] per
+//! contract. Every instruction costs gas and a call stops with [`VmError::OutOfGas`] once its
+//! gas limit is spent, so a loop can't stall block production. There is no clock, randomness
+//! or I/O and the arithmetic is checked, every node gets the same result from the same call.
+//!
+//! Code is written in a one instruction per line assembly, see [`assemble`]:
+//!
+//! ```text
+//! # counter: adds the first argument to the value under key 0 and returns it
+//! push 0
+//! push 0
+//! load
+//! arg 0
+//! add
+//! store
+//! push 0
+//! load
+//! return
+//! ```
+use crate::keys;
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Longest code a contract can be deployed with.
+pub const MAX_CODE_LEN: usize = 4096;
+/// Highest gas limit of an extrinsic, which bounds the time a block takes to check.
+pub const MAX_GAS_LIMIT: u64 = 1_000_000;
+/// Gas one coin of fee buys, the fee of an extrinsic is its gas used rounded up to a coin.
+pub const GAS_PER_COIN: u64 = 1_000;
+/// Gas of a deployment, on top of [`DEPLOY_GAS_PER_INSTR`] for each instruction.
+pub const DEPLOY_BASE_GAS: u64 = 1_000;
+pub const DEPLOY_GAS_PER_INSTR: u64 = 10;
+/// Gas of a call, on top of the instructions it runs.
+pub const CALL_BASE_GAS: u64 = 100;
+const MAX_STACK: usize = 1024;
+
+/// Values of a contract by key, a key absent is 0.
+pub type Storage = BTreeMap<i64, i64>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Instr {
+    Push(i64),
+    Pop,
+    Dup,
+    Swap,
+    /// Pushes the argument of the call at this index.
+    Arg(usize),
+    /// Pushes the number of arguments of the call.
+    ArgCount,
+    /// Binary operations pop `b` then `a` and push `a op b`.
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    /// Comparisons push 1 if true, 0 if false.
+    Eq,
+    Lt,
+    Gt,
+    Not,
+    Jump(usize),
+    /// Pops a value and jumps if it isn't 0.
+    JumpIf(usize),
+    /// Replaces the key on top of the stack with its value in storage.
+    Load,
+    /// Pops a value then a key and stores the value under the key.
+    Store,
+    /// Stops, returning the value on top of the stack.
+    Return,
+    /// Stops without a value, like running past the last instruction.
+    Stop,
+    /// Stops and discards the storage changes of the call.
+    Revert,
+}
+
+impl Instr {
+    pub fn cost(&self) -> u64 {
+        match self {
+            Instr::Load => 20,
+            Instr::Store => 100,
+            Instr::Jump(_) | Instr::JumpIf(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl std::str::FromStr for Instr {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let operand = words.next();
+        let instr = match (name, operand) {
+            ("push", Some(value)) => Instr::Push(value.parse().map_err(|_| line)?),
+            ("arg", Some(index)) => Instr::Arg(index.parse().map_err(|_| line)?),
+            ("jump", Some(target)) => Instr::Jump(target.parse().map_err(|_| line)?),
+            ("jump_if", Some(target)) => Instr::JumpIf(target.parse().map_err(|_| line)?),
+            (name, None) => serde_json::from_value(serde_json::Value::from(name))
+                .map_err(|_| String::from(line))?,
+            _ => return Err(String::from(line)),
+        };
+        match words.next() {
+            Some(_) => Err(String::from(line)),
+            None => Ok(instr),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("line {line}: can't parse `{text}`")]
+pub struct AssemblyError {
+    pub line: usize,
+    pub text: String,
+}
+
+/// Parses contract code, one instruction per line. Empty lines and `#` comments are skipped,
+/// jump targets are instruction indexes from 0.
+pub fn assemble(source: &str) -> Result<Vec<Instr>, AssemblyError> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line, text)| text.parse().map_err(|text| AssemblyError { line, text }))
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VmError {
+    #[error("out of gas")]
+    OutOfGas,
+    #[error("stack underflow at {0}")]
+    StackUnderflow(usize),
+    #[error("stack overflow at {0}")]
+    StackOverflow(usize),
+    #[error("arithmetic overflow or division by zero at {0}")]
+    Arithmetic(usize),
+    #[error("jump to {target} outside of the code at {at}")]
+    InvalidJump { at: usize, target: usize },
+    #[error("no argument {index} at {at}")]
+    MissingArgument { at: usize, index: usize },
+    #[error("reverted at {0}")]
+    Reverted(usize),
+}
+
+/// What a call did, its storage changes are kept only if it succeeded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Execution {
+    /// Value returned by the contract, if any.
+    pub result: Result<Option<i64>, VmError>,
+    pub gas_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contract {
+    pub code: Vec<Instr>,
+    pub storage: Storage,
+}
+
+impl Contract {
+    /// Runs the code with `args`, updating the storage if the call succeeds. The gas used
+    /// includes [`CALL_BASE_GAS`].
+    pub fn call(&mut self, args: &[i64], gas_limit: u64) -> Execution {
+        let mut vm = Vm {
+            stack: Vec::new(),
+            storage: self.storage.clone(),
+            gas_used: CALL_BASE_GAS,
+            gas_limit,
+        };
+        let result = vm.run(&self.code, args);
+        if result.is_ok() {
+            self.storage = vm.storage;
+        }
+        Execution {
+            result,
+            gas_used: vm.gas_used.min(gas_limit),
+        }
+    }
+}
+
+/// Address of the contract deployed by `deployer` with its extrinsic numbered `nonce`.
+pub fn contract_address(deployer: &str, nonce: u64) -> String {
+    let hash = Blake2b::<U32>::new()
+        .chain_update(b"contract")
+        .chain_update(deployer)
+        .chain_update(nonce.to_le_bytes())
+        .finalize();
+    keys::encode_address(&hash.into())
+}
+
+pub fn deploy_cost(code: &[Instr]) -> u64 {
+    DEPLOY_BASE_GAS + DEPLOY_GAS_PER_INSTR * code.len() as u64
+}
+
+/// Fee of `gas`, in coins.
+pub fn fee(gas: u64) -> u64 {
+    gas.div_ceil(GAS_PER_COIN)
+}
+
+struct Vm {
+    stack: Vec<i64>,
+    storage: Storage,
+    gas_used: u64,
+    gas_limit: u64,
+}
+
+impl Vm {
+    fn run(&mut self, code: &[Instr], args: &[i64]) -> Result<Option<i64>, VmError> {
+        if self.gas_used > self.gas_limit {
+            return Err(VmError::OutOfGas);
+        }
+        let mut pc = 0;
+        while let Some(instr) = code.get(pc) {
+            self.gas_used += instr.cost();
+            if self.gas_used > self.gas_limit {
+                return Err(VmError::OutOfGas);
+            }
+            let at = pc;
+            pc += 1;
+            match *instr {
+                Instr::Push(value) => self.push(value, at)?,
+                Instr::Pop => {
+                    self.pop(at)?;
+                }
+                Instr::Dup => {
+                    let value = self.pop(at)?;
+                    self.push(value, at)?;
+                    self.push(value, at)?;
+                }
+                Instr::Swap => {
+                    let (a, b) = (self.pop(at)?, self.pop(at)?);
+                    self.push(a, at)?;
+                    self.push(b, at)?;
+                }
+                Instr::Arg(index) => {
+                    let arg = args
+                        .get(index)
+                        .ok_or(VmError::MissingArgument { at, index })?;
+                    self.push(*arg, at)?;
+                }
+                Instr::ArgCount => self.push(args.len() as i64, at)?,
+                Instr::Add => self.binary(at, i64::checked_add)?,
+                Instr::Sub => self.binary(at, i64::checked_sub)?,
+                Instr::Mul => self.binary(at, i64::checked_mul)?,
+                Instr::Div => self.binary(at, i64::checked_div)?,
+                Instr::Rem => self.binary(at, i64::checked_rem)?,
+                Instr::Eq => self.binary(at, |a, b| Some((a == b) as i64))?,
+                Instr::Lt => self.binary(at, |a, b| Some((a < b) as i64))?,
+                Instr::Gt => self.binary(at, |a, b| Some((a > b) as i64))?,
+                Instr::Not => {
+                    let value = self.pop(at)?;
+                    self.push((value == 0) as i64, at)?;
+                }
+                Instr::Jump(target) => pc = Self::target(code, at, target)?,
+                Instr::JumpIf(target) => {
+                    if self.pop(at)? != 0 {
+                        pc = Self::target(code, at, target)?;
+                    }
+                }
+                Instr::Load => {
+                    let key = self.pop(at)?;
+                    let value = self.storage.get(&key).copied().unwrap_or_default();
+                    self.push(value, at)?;
+                }
+                Instr::Store => {
+                    let (value, key) = (self.pop(at)?, self.pop(at)?);
+                    // 0 is the value of absent keys, so the storage has a single form
+                    match value {
+                        0 => self.storage.remove(&key),
+                        value => self.storage.insert(key, value),
+                    };
+                }
+                Instr::Return => return self.pop(at).map(Some),
+                Instr::Stop => return Ok(None),
+                Instr::Revert => return Err(VmError::Reverted(at)),
+            }
+        }
+        Ok(None)
+    }
+
+    fn push(&mut self, value: i64, at: usize) -> Result<(), VmError> {
+        if self.stack.len() == MAX_STACK {
+            return Err(VmError::StackOverflow(at));
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self, at: usize) -> Result<i64, VmError> {
+        self.stack.pop().ok_or(VmError::StackUnderflow(at))
+    }
+
+    fn binary(&mut self, at: usize, op: impl Fn(i64, i64) -> Option<i64>) -> Result<(), VmError> {
+        let (b, a) = (self.pop(at)?, self.pop(at)?);
+        let value = op(a, b).ok_or(VmError::Arithmetic(at))?;
+        self.push(value, at)
+    }
+
+    fn target(code: &[Instr], at: usize, target: usize) -> Result<usize, VmError> {
+        match target < code.len() {
+            true => Ok(target),
+            false => Err(VmError::InvalidJump { at, target }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = "
+        # adds the first argument to the value under key 0 and returns it
+        push 0
+        push 0
+        load
+        arg 0
+        add
+        store
+        push 0
+        load
+        return
+    ";
+
+    #[test]
+    fn runs_and_stores() {
+        let mut counter = Contract {
+            code: assemble(COUNTER).unwrap(),
+            storage: Storage::new(),
+        };
+        let execution = counter.call(&[5], 10_000);
+        assert_eq!(execution.result, Ok(Some(5)));
+        assert_eq!(counter.call(&[-2], 10_000).result, Ok(Some(3)));
+        assert_eq!(counter.storage, Storage::from([(0, 3)]));
+        assert_eq!(execution.gas_used, CALL_BASE_GAS + 5 + 2 * 20 + 100 + 1);
+
+        // failed calls keep the storage as it was
+        assert_eq!(
+            counter.call(&[], 10_000).result,
+            Err(VmError::MissingArgument { at: 3, index: 0 })
+        );
+        assert_eq!(
+            counter.call(&[i64::MAX], 10_000).result,
+            Err(VmError::Arithmetic(4))
+        );
+        assert_eq!(counter.storage, Storage::from([(0, 3)]));
+        assert_eq!(counter.call(&[-3], 10_000).result, Ok(Some(0)));
+        assert!(counter.storage.is_empty());
+    }
+
+    #[test]
+    fn gas_bounds_loops() {
+        let mut spin = Contract {
+            code: assemble("push 1\npop\njump 0").unwrap(),
+            storage: Storage::new(),
+        };
+        let execution = spin.call(&[], 5_000);
+        assert_eq!(execution.result, Err(VmError::OutOfGas));
+        assert_eq!(execution.gas_used, 5_000);
+        assert_eq!(fee(execution.gas_used), 5);
+        assert_eq!(fee(5_001), 6);
+
+        let mut revert = Contract {
+            code: assemble("push 1\npush 7\nstore\nrevert").unwrap(),
+            storage: Storage::new(),
+        };
+        assert_eq!(revert.call(&[], 5_000).result, Err(VmError::Reverted(3)));
+        assert!(revert.storage.is_empty());
+    }
+
+    #[test]
+    fn assembly_errors_point_at_the_line() {
+        let error = assemble("push 1\n\n  # comment\npush one").unwrap_err();
+        assert_eq!((error.line, error.text.as_str()), (4, "push one"));
+        assert!(assemble("add 1").is_err());
+        assert_eq!(
+            assemble("jump_if 3 # loop\narg_count").unwrap(),
+            vec![Instr::JumpIf(3), Instr::ArgCount]
+        );
+    }
+}
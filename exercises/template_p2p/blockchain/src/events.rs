@@ -0,0 +1,251 @@
+//! Structured domain events written as JSON lines, for tools that would otherwise scrape logs.
+use crate::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Events waiting for a slow socket reader, the next ones are dropped.
+pub const SOCKET_BACKLOG: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChainEvent {
+    /// The head of the chain changed, by a new block or a replaced chain.
+    NewBestBlock {
+        id: u64,
+        hash: String,
+    },
+    /// A block was added on top of the chain, `peer` is `None` for blocks mined locally.
+    BlockImported {
+        id: u64,
+        hash: String,
+        peer: Option<String>,
+    },
+    BlockRejected {
+        id: u64,
+        hash: String,
+        peer: String,
+        reason: String,
+        error: String,
+    },
+    PeerDiscovered {
+        peer: String,
+    },
+    PeerExpired {
+        peer: String,
+    },
+    /// The first connection with `peer` was established.
+    PeerConnected {
+        peer: String,
+    },
+    /// The last connection with `peer` was closed.
+    PeerDisconnected {
+        peer: String,
+    },
+    /// `choose_chain` replaced the local chain by a peer's.
+    ChainReplaced {
+        peer: String,
+        old_length: usize,
+        new_length: usize,
+        tip: String,
+    },
+    /// The replaced chain dropped `depth` blocks of the old one, above block `fork_id`.
+    Reorg {
+        fork_id: u64,
+        depth: usize,
+        old_tip: String,
+        new_tip: String,
+    },
+    /// Enough validators voted for block `id`, fork choice won't revert it.
+    BlockFinalized {
+        id: u64,
+        hash: String,
+    },
+}
+
+/// One line of the stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: ChainEvent,
+}
+
+pub struct EventSink {
+    writer: Box<dyn Write + Send>,
+}
+
+impl EventSink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+        }
+    }
+
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn file(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Connects to a listening Unix socket at `path`. A thread writes to it, so a reader that
+    /// falls behind loses events instead of blocking the node.
+    pub fn unix_socket(path: impl AsRef<Path>) -> Result<Self> {
+        let mut stream = UnixStream::connect(path)?;
+        let (lines, backlog) = mpsc::sync_channel::<Vec<u8>>(SOCKET_BACKLOG);
+        thread::spawn(move || {
+            for line in backlog {
+                if let Err(e) = stream.write_all(&line) {
+                    log::error!("event socket closed: {}", e);
+                    return;
+                }
+            }
+        });
+        Ok(Self::new(SocketWriter(lines)))
+    }
+
+    /// Keeps the events in memory, returning the log to read them back from.
+    pub fn memory() -> (Self, EventLog) {
+        let log = EventLog::default();
+        (Self::new(log.clone()), log)
+    }
+
+    pub fn emit(&mut self, event: ChainEvent) {
+        let record = EventRecord {
+            timestamp: Utc::now().timestamp_millis(),
+            event,
+        };
+        let mut line = serde_json::to_vec(&record).expect("can jsonify event");
+        line.push(b'\n');
+        if let Err(e) = self
+            .writer
+            .write_all(&line)
+            .and_then(|_| self.writer.flush())
+        {
+            log::error!("can't write event: {}", e);
+        }
+    }
+}
+
+/// Hands the lines to the thread writing to the socket, see [`EventSink::unix_socket`].
+struct SocketWriter(SyncSender<Vec<u8>>);
+
+impl Write for SocketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.try_send(buf.to_vec()) {
+            Ok(()) => Ok(buf.len()),
+            Err(TrySendError::Full(_)) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "the reader is behind, event dropped",
+            )),
+            Err(TrySendError::Disconnected(_)) => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// In-memory event stream, see [`EventSink::memory`].
+#[derive(Clone, Default)]
+pub struct EventLog(Arc<Mutex<Vec<u8>>>);
+
+impl EventLog {
+    pub fn events(&self) -> Vec<ChainEvent> {
+        let buffer = self.0.lock().expect("event log lock");
+        buffer
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                serde_json::from_slice::<EventRecord>(line)
+                    .expect("valid event")
+                    .event
+            })
+            .collect()
+    }
+}
+
+impl Write for EventLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .expect("event log lock")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_json_object_per_line() {
+        let (mut sink, log) = EventSink::memory();
+        sink.emit(ChainEvent::PeerDiscovered {
+            peer: String::from("alice"),
+        });
+        sink.emit(ChainEvent::BlockImported {
+            id: 1,
+            hash: String::from("00ab"),
+            peer: None,
+        });
+
+        let text = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["event"], "peer_discovered");
+        assert_eq!(first["peer"], "alice");
+        assert!(first["timestamp"].is_i64());
+        assert_eq!(
+            log.events()[1],
+            ChainEvent::BlockImported {
+                id: 1,
+                hash: String::from("00ab"),
+                peer: None,
+            }
+        );
+    }
+
+    #[test]
+    fn slow_socket_readers_lose_events() {
+        let path = std::env::temp_dir().join(format!("events-{}.sock", rand::random::<u64>()));
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let mut sink = EventSink::unix_socket(&path).unwrap();
+        let (reader, _) = listener.accept().unwrap();
+        // nobody reads: the socket buffer and the backlog fill up, emitting goes on
+        for id in 0..10 * SOCKET_BACKLOG as u64 {
+            sink.emit(ChainEvent::BlockFinalized {
+                id,
+                hash: String::from("00ab"),
+            });
+        }
+        let mut first = String::new();
+        io::BufRead::read_line(&mut io::BufReader::new(reader), &mut first).unwrap();
+        let record: EventRecord = serde_json::from_str(&first).unwrap();
+        assert_eq!(
+            record.event,
+            ChainEvent::BlockFinalized {
+                id: 0,
+                hash: String::from("00ab"),
+            }
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
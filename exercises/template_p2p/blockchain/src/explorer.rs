@@ -0,0 +1,353 @@
+//! Read-only block explorer, plain HTML served on localhost.
+//!
+//! Pages are rendered from the answers of the event loop to [`RpcRequest`]s, the same ones
+//! the JSON-RPC server sends, so the explorer shows what `ls c` and `ls p` print.
+use crate::{
+    blocks::{Block, Header},
+    rpc::{BlockId, RpcRequest},
+    Result,
+};
+use chrono::NaiveDateTime;
+use hyper::{
+    header::{CONTENT_TYPE, LOCATION},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{convert::Infallible, fmt::Write, net::SocketAddr};
+use tokio::sync::{mpsc, oneshot};
+
+/// Blocks listed per page, newest first.
+pub const PAGE_SIZE: u64 = 20;
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:2em}td,th{padding:.2em .8em;text-align:left}\
+    code,pre{font-size:.9em}pre{background:#f4f4f4;padding:.5em;overflow-x:auto}";
+
+/// Serves the explorer on `http://127.0.0.1:<port>/`, returns the bound address.
+pub fn serve(port: u16, requests: mpsc::UnboundedSender<RpcRequest>) -> Result<SocketAddr> {
+    let make_service = make_service_fn(move |_| {
+        let requests = requests.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let requests = requests.clone();
+                async move { Ok::<_, Infallible>(respond(request, &requests).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], port)))?.serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("explorer server stopped: {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+async fn respond(
+    request: Request<Body>,
+    requests: &mpsc::UnboundedSender<RpcRequest>,
+) -> Response<Body> {
+    if request.method() != Method::GET {
+        return html(StatusCode::METHOD_NOT_ALLOWED, "Read only", "");
+    }
+    let query = request.uri().query().unwrap_or_default();
+    let param = |name: &str| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    let path = request.uri().path();
+    let response = match path.trim_end_matches('/') {
+        "" | "/blocks" => {
+            let page = param("page").and_then(|p| p.parse().ok()).unwrap_or(0);
+            blocks_page(page, requests).await
+        }
+        "/peers" => call(requests, RpcRequest::Peers)
+            .await
+            .map(|peers| html(StatusCode::OK, "Peers", &render_peers(&peers))),
+        "/search" => Some(search(&param("q").unwrap_or_default())),
+        _ => match path.strip_prefix("/block/") {
+            Some(id) => block_page(id, requests).await,
+            None => Some(not_found(&format!("No page at {}", escape(path)))),
+        },
+    };
+    response.unwrap_or_else(|| {
+        html(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Unavailable",
+            "<p>The node is shutting down.</p>",
+        )
+    })
+}
+
+/// Sends a request to the event loop and waits for the answer, `None` if it stopped.
+async fn call<T>(
+    requests: &mpsc::UnboundedSender<RpcRequest>,
+    request: impl FnOnce(oneshot::Sender<T>) -> RpcRequest,
+) -> Option<T> {
+    let (sender, receiver) = oneshot::channel();
+    requests.send(request(sender)).ok()?;
+    receiver.await.ok()
+}
+
+async fn blocks_page(
+    page: u64,
+    requests: &mpsc::UnboundedSender<RpcRequest>,
+) -> Option<Response<Body>> {
+    let head = call(requests, RpcRequest::GetHead).await?;
+    let Some(top) = head.id.checked_sub(page.saturating_mul(PAGE_SIZE)) else {
+        return Some(not_found(&format!("No page {}", page)));
+    };
+    let from = (top + 1).saturating_sub(PAGE_SIZE);
+    let headers = call(requests, |reply| {
+        RpcRequest::GetHeaders(from..top + 1, reply)
+    })
+    .await?;
+    let body = render_blocks(&headers, page, from > 0);
+    Some(html(StatusCode::OK, "Blocks", &body))
+}
+
+async fn block_page(
+    id: &str,
+    requests: &mpsc::UnboundedSender<RpcRequest>,
+) -> Option<Response<Body>> {
+    let block_id = match id.parse() {
+        Ok(number) => BlockId::Number(number),
+        Err(_) => BlockId::Hash(String::from(id)),
+    };
+    let head = call(requests, RpcRequest::GetHead).await?.id;
+    if let Some(block) = call(requests, |reply| {
+        RpcRequest::GetBlock(block_id.clone(), reply)
+    })
+    .await?
+    {
+        let title = format!("Block {}", block.id);
+        return Some(html(StatusCode::OK, &title, &render_block(&block, head)));
+    }
+    // the data of pruned blocks is gone, their header is still there
+    if let BlockId::Number(number) = block_id {
+        let headers = call(requests, |reply| {
+            RpcRequest::GetHeaders(number..number.saturating_add(1), reply)
+        })
+        .await?;
+        if let Some(header) = headers.first() {
+            let title = format!("Block {}", header.id);
+            let body = render_header(header, head) + "<p>The data of this block was pruned.</p>";
+            return Some(html(StatusCode::OK, &title, &body));
+        }
+    }
+    Some(not_found(&format!("No block {}", escape(id))))
+}
+
+/// Sends block ids and hashes to their block page.
+fn search(query: &str) -> Response<Body> {
+    let query = query.trim();
+    let is_hash = query.len() == 64 && query.bytes().all(|b| b.is_ascii_hexdigit());
+    if query.parse::<u64>().is_ok() || is_hash {
+        return Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(LOCATION, format!("/block/{}", query.to_ascii_lowercase()))
+            .body(Body::empty())
+            .expect("valid response");
+    }
+    not_found(&format!(
+        "Nothing found for <code>{}</code>, search a block id or hash",
+        escape(query)
+    ))
+}
+
+fn render_blocks(headers: &[Header], page: u64, has_older: bool) -> String {
+    let mut body =
+        String::from("<table><tr><th>Id</th><th>Hash</th><th>Time</th><th>Previous</th></tr>");
+    for header in headers.iter().rev() {
+        let _ = write!(
+            body,
+            "<tr><td><a href=\"/block/{id}\">{id}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            hash_link(&header.hash),
+            time(header.timestamp),
+            hash_link(&header.previous_hash),
+            id = header.id,
+        );
+    }
+    body.push_str("</table><p>");
+    if page > 0 {
+        let _ = write!(body, "<a href=\"/blocks?page={}\">newer</a> ", page - 1);
+    }
+    if has_older {
+        let _ = write!(body, "<a href=\"/blocks?page={}\">older</a>", page + 1);
+    }
+    body.push_str("</p>");
+    body
+}
+
+fn render_header(header: &Header, head: u64) -> String {
+    let mut rows = vec![
+        ("Id", header.id.to_string()),
+        ("Hash", format!("<code>{}</code>", escape(&header.hash))),
+        ("Previous", hash_link(&header.previous_hash)),
+        ("Time", time(header.timestamp)),
+        ("Nonce", header.nonce.to_string()),
+        (
+            "Data root",
+            format!("<code>{}</code>", escape(&header.data_root)),
+        ),
+    ];
+    if let Some(root) = &header.state_root {
+        rows.push(("State root", format!("<code>{}</code>", escape(root))));
+    }
+    if let Some(signature) = &header.signature {
+        rows.push(("Signature", format!("<code>{}</code>", escape(signature))));
+    }
+    if header.id < head {
+        let next = header.id + 1;
+        rows.push(("Next", format!("<a href=\"/block/{next}\">{next}</a>")));
+    }
+    let mut body = String::from("<table>");
+    for (name, value) in rows {
+        let _ = write!(body, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+    }
+    body.push_str("</table>");
+    body
+}
+
+fn render_block(block: &Block, head: u64) -> String {
+    let mut body = render_header(&block.header, head);
+    let _ = write!(body, "<h2>Data</h2><pre>{}</pre>", escape(&block.data));
+    if let Some(coinbase) = &block.coinbase {
+        let _ = write!(
+            body,
+            "<h2>Coinbase</h2><p>{} to <code>{}</code></p>",
+            coinbase.amount,
+            escape(&coinbase.to)
+        );
+    }
+    let transactions = block.transactions.iter().map(|tx| (tx.hash(), json(tx)));
+    let extrinsics = block.extrinsics.iter().map(|e| (e.hash(), json(e)));
+    for (title, entries) in [
+        ("Transactions", transactions.collect::<Vec<_>>()),
+        ("Extrinsics", extrinsics.collect()),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        let _ = write!(body, "<h2>{}</h2>", title);
+        for (hash, json) in entries {
+            let _ = write!(
+                body,
+                "<h3><code>{}</code></h3><pre>{}</pre>",
+                hash,
+                escape(&json)
+            );
+        }
+    }
+    body
+}
+
+fn render_peers(peers: &[String]) -> String {
+    if peers.is_empty() {
+        return String::from("<p>No peers.</p>");
+    }
+    let items: String = peers
+        .iter()
+        .map(|peer| format!("<li><code>{}</code></li>", escape(peer)))
+        .collect();
+    format!("<ul>{}</ul>", items)
+}
+
+fn hash_link(hash: &str) -> String {
+    let hash = escape(hash);
+    format!(
+        "<a href=\"/block/{}\"><code>{}</code></a>",
+        hash,
+        &hash[..hash.len().min(16)]
+    )
+}
+
+fn time(timestamp: i64) -> String {
+    NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn json(value: &impl serde::Serialize) -> String {
+    serde_json::to_string_pretty(value).expect("can jsonify block entries")
+}
+
+fn escape(text: &str) -> String {
+    text.chars().fold(String::new(), |mut escaped, c| {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+        escaped
+    })
+}
+
+fn not_found(message: &str) -> Response<Body> {
+    html(
+        StatusCode::NOT_FOUND,
+        "Not found",
+        &format!("<p>{}</p>", message),
+    )
+}
+
+fn html(status: StatusCode, title: &str, body: &str) -> Response<Body> {
+    let page = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>{STYLE}</style></head><body><nav><a href=\"/\">Blocks</a> | \
+         <a href=\"/peers\">Peers</a> | <form action=\"/search\" style=\"display:inline\">\
+         <input name=\"q\" placeholder=\"block id or hash\"></form></nav>\
+         <h1>{title}</h1>{body}</body></html>"
+    );
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(page))
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::Chain;
+
+    #[test]
+    fn pages_link_blocks_and_escape_data() {
+        let mut chain = Chain::default();
+        chain.add_data(String::from("<script>")).unwrap();
+        let head = chain.head();
+        let page = render_block(head, head.id);
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(!page.contains("<script>"));
+        assert!(page.contains(&format!("href=\"/block/{}\"", head.previous_hash)));
+        assert!(!page.contains("Next"));
+
+        let headers: Vec<_> = chain.headers().cloned().collect();
+        let list = render_blocks(&headers, 0, false);
+        assert!(
+            list.find("/block/1\"") < list.find("/block/0\""),
+            "newest first"
+        );
+        assert!(!list.contains("older") && !list.contains("newer"));
+        assert!(render_blocks(&headers, 1, true).contains("/blocks?page=2"));
+    }
+
+    #[test]
+    fn search_goes_to_blocks() {
+        let hash = "00".repeat(32);
+        for (query, location) in [
+            ("12", String::from("/block/12")),
+            (&hash, format!("/block/{}", hash)),
+        ] {
+            let response = search(query);
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            assert_eq!(response.headers()[LOCATION], location.as_str());
+        }
+        assert_eq!(search("<nope>").status(), StatusCode::NOT_FOUND);
+    }
+}
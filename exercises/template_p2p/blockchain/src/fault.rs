@@ -0,0 +1,284 @@
+//! Fault injection for the gossip messages a node receives and publishes.
+//!
+//! Faults are applied to whole application messages, after gossipsub hands them to the node
+//! and before they are handled, or before the node hands them to gossipsub, instead of to the
+//! raw transport bytes: dropping or reordering bytes inside a noise/yamux stream would only
+//! tear the connection down.
+use crate::Result;
+use libp2p::PeerId;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// What can go wrong with the messages received from a peer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkFaults {
+    /// Probability of dropping a message.
+    pub drop: f64,
+    /// Probability of handling a message twice.
+    pub duplicate: f64,
+    /// Fixed delay added to every message.
+    pub delay_ms: u64,
+    /// Random extra delay, up to this many milliseconds.
+    pub jitter_ms: u64,
+    /// Probability of holding a message back for up to `reorder_window_ms`,
+    /// so the messages that follow overtake it.
+    pub reorder: f64,
+    pub reorder_window_ms: u64,
+}
+
+/// What can go wrong with the messages the node publishes. Gossipsub sends a message once
+/// only, so they can't be duplicated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutgoingFaults {
+    pub drop: f64,
+    pub delay_ms: u64,
+    pub jitter_ms: u64,
+    pub reorder: f64,
+    pub reorder_window_ms: u64,
+}
+
+impl From<&OutgoingFaults> for LinkFaults {
+    fn from(faults: &OutgoingFaults) -> Self {
+        Self {
+            drop: faults.drop,
+            duplicate: 0.0,
+            delay_ms: faults.delay_ms,
+            jitter_ms: faults.jitter_ms,
+            reorder: faults.reorder,
+            reorder_window_ms: faults.reorder_window_ms,
+        }
+    }
+}
+
+/// Faults for every peer, and for what the node publishes, loaded from a JSON file such as:
+///
+/// ```json
+/// { "seed": 7, "default": { "drop": 0.1 }, "peers": { "12D3KooW...": { "delay_ms": 500 } },
+///   "outgoing": { "drop": 0.1 } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultProfile {
+    /// Seed for the random decisions, so a run can be replayed.
+    pub seed: u64,
+    /// Faults for the peers that are not listed in `peers`.
+    pub default: LinkFaults,
+    /// Faults per peer id.
+    pub peers: HashMap<String, LinkFaults>,
+    /// Faults of the messages the node publishes, none unless set.
+    pub outgoing: OutgoingFaults,
+}
+
+impl FaultProfile {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn link(&self, peer: &PeerId) -> &LinkFaults {
+        self.peers.get(&peer.to_string()).unwrap_or(&self.default)
+    }
+}
+
+/// A message received from `peer` on its way to the node, or published by the node, `peer`
+/// then being the local peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub peer: PeerId,
+    pub data: Vec<u8>,
+    /// Topic an outgoing message is published on, `None` for a received one.
+    pub topic: Option<String>,
+}
+
+pub struct FaultInjector {
+    profile: FaultProfile,
+    rng: StdRng,
+    delayed: Vec<(Instant, Delivery)>,
+}
+
+impl FaultInjector {
+    pub fn new(profile: FaultProfile) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(profile.seed),
+            profile,
+            delayed: Vec::new(),
+        }
+    }
+
+    /// Decides what happens to a message received or published at `now`,
+    /// returning the deliveries to go through right away.
+    pub fn inject(&mut self, delivery: Delivery, now: Instant) -> Vec<Delivery> {
+        let link = match delivery.topic {
+            Some(_) => LinkFaults::from(&self.profile.outgoing),
+            None => self.profile.link(&delivery.peer).clone(),
+        };
+        if self.rng.gen_bool(link.drop.clamp(0.0, 1.0)) {
+            log::debug!("fault: dropping message from {}", delivery.peer);
+            return Vec::new();
+        }
+        let copies = if self.rng.gen_bool(link.duplicate.clamp(0.0, 1.0)) {
+            log::debug!("fault: duplicating message from {}", delivery.peer);
+            vec![delivery.clone(), delivery]
+        } else {
+            vec![delivery]
+        };
+
+        let mut ready = Vec::new();
+        for delivery in copies {
+            let mut delay = link.delay_ms + self.rng.gen_range(0..=link.jitter_ms);
+            if self.rng.gen_bool(link.reorder.clamp(0.0, 1.0)) {
+                log::debug!("fault: holding back message from {}", delivery.peer);
+                delay += self.rng.gen_range(0..=link.reorder_window_ms);
+            }
+            if delay == 0 {
+                ready.push(delivery);
+            } else {
+                self.delayed
+                    .push((now + Duration::from_millis(delay), delivery));
+            }
+        }
+        ready
+    }
+
+    /// Takes the delayed deliveries that are due at `now`, earliest first.
+    pub fn due(&mut self, now: Instant) -> Vec<Delivery> {
+        self.delayed.sort_by_key(|(at, _)| *at);
+        let due = self.delayed.partition_point(|(at, _)| *at <= now);
+        self.delayed.drain(..due).map(|(_, d)| d).collect()
+    }
+
+    /// When the next delayed delivery is due, if there is any.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.delayed.iter().map(|(at, _)| *at).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery(peer: PeerId, data: &str) -> Delivery {
+        Delivery {
+            peer,
+            data: data.as_bytes().to_vec(),
+            topic: None,
+        }
+    }
+
+    fn injector(link: LinkFaults) -> FaultInjector {
+        FaultInjector::new(FaultProfile {
+            default: link,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn no_faults_delivers_right_away() {
+        let mut faults = FaultInjector::new(FaultProfile::default());
+        let msg = delivery(PeerId::random(), "block");
+        assert_eq!(faults.inject(msg.clone(), Instant::now()), vec![msg]);
+        assert_eq!(faults.next_due(), None);
+    }
+
+    #[test]
+    fn drops_and_duplicates() {
+        let msg = delivery(PeerId::random(), "block");
+        let mut dropping = injector(LinkFaults {
+            drop: 1.0,
+            ..Default::default()
+        });
+        assert!(dropping.inject(msg.clone(), Instant::now()).is_empty());
+
+        let mut duplicating = injector(LinkFaults {
+            duplicate: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(
+            duplicating.inject(msg.clone(), Instant::now()),
+            vec![msg.clone(), msg]
+        );
+    }
+
+    #[test]
+    fn delays_until_due() {
+        let mut faults = injector(LinkFaults {
+            delay_ms: 100,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        let msg = delivery(PeerId::random(), "block");
+        assert!(faults.inject(msg.clone(), now).is_empty());
+        assert_eq!(faults.next_due(), Some(now + Duration::from_millis(100)));
+        assert!(faults.due(now + Duration::from_millis(99)).is_empty());
+        assert_eq!(faults.due(now + Duration::from_millis(100)), vec![msg]);
+    }
+
+    #[test]
+    fn faults_are_per_peer() {
+        let (lossy, healthy) = (PeerId::random(), PeerId::random());
+        let mut faults = FaultInjector::new(FaultProfile {
+            peers: HashMap::from([(
+                lossy.to_string(),
+                LinkFaults {
+                    drop: 1.0,
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(faults.inject(delivery(lossy, "a"), now).is_empty());
+        assert_eq!(faults.inject(delivery(healthy, "b"), now).len(), 1);
+    }
+
+    #[test]
+    fn outgoing_faults_are_set_apart() {
+        let now = Instant::now();
+        let published = Delivery {
+            topic: Some(String::from("blocks")),
+            ..delivery(PeerId::random(), "block")
+        };
+        let mut incoming_only = injector(LinkFaults {
+            drop: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(incoming_only.inject(published.clone(), now).len(), 1);
+
+        let mut faults = FaultInjector::new(FaultProfile {
+            outgoing: OutgoingFaults {
+                delay_ms: 100,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert!(faults.inject(published.clone(), now).is_empty());
+        assert_eq!(
+            faults.due(now + Duration::from_millis(100)),
+            vec![published]
+        );
+    }
+
+    #[test]
+    fn outgoing_messages_are_not_duplicated() {
+        let profile = r#"{ "outgoing": { "duplicate": 0.5 } }"#;
+        assert!(serde_json::from_str::<FaultProfile>(profile).is_err());
+    }
+
+    #[test]
+    fn parses_profile() {
+        let profile: FaultProfile =
+            serde_json::from_str(r#"{ "seed": 7, "default": { "drop": 0.5, "delay_ms": 20 } }"#)
+                .expect("valid profile");
+        assert_eq!(profile.seed, 7);
+        assert_eq!(profile.default.drop, 0.5);
+        assert_eq!(profile.default.delay_ms, 20);
+        assert!(profile.peers.is_empty());
+    }
+}
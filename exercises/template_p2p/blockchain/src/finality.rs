@@ -0,0 +1,204 @@
+//! Finality votes on top of the fork choice rule.
+//!
+//! Validators sign a vote for every new head they follow and gossip it on the finality topic, a
+//! vote for a block backs its ancestors too. A block backed by more than two thirds of the
+//! validators is final: [`Chain::choose_chain`] never switches to a chain without it.
+use crate::{
+    blocks::{Chain, Header},
+    consensus::{self, ConsensusError},
+};
+use libp2p::identity::ed25519;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Signed votes are prefixed, so a block seal can't be replayed as a vote.
+const VOTE_CONTEXT: &[u8] = b"finality-vote:";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalitySpec {
+    /// Hex encoded ed25519 public keys.
+    pub validators: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    pub id: u64,
+    pub hash: String,
+    /// Hex encoded public key of the validator.
+    pub voter: String,
+    pub signature: String,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum VoteError {
+    #[error("vote from {0}, which is not a validator")]
+    UnknownVoter(String),
+    #[error("vote for block with id: {0} has an invalid signature")]
+    InvalidSignature(u64),
+}
+
+pub struct Finality {
+    validators: Vec<ed25519::PublicKey>,
+    /// Key of this node, if it is a validator.
+    key: Option<ed25519::Keypair>,
+    /// Latest vote of each validator, by index in `validators`.
+    votes: HashMap<usize, Vote>,
+    last_vote: Option<String>,
+}
+
+impl Finality {
+    pub fn new(spec: &FinalitySpec, key: Option<ed25519::Keypair>) -> Result<Self, ConsensusError> {
+        Ok(Self {
+            validators: consensus::parse_validators(&spec.validators)?,
+            key,
+            votes: HashMap::new(),
+            last_vote: None,
+        })
+    }
+
+    /// Votes needed to finalize a block, more than two thirds of the validators.
+    pub fn threshold(&self) -> usize {
+        self.validators.len() * 2 / 3 + 1
+    }
+
+    /// Signs and records a vote for `head`, unless this node is not a validator or already voted
+    /// for it.
+    pub fn vote(&mut self, head: &Header) -> Option<Vote> {
+        let key = self.key.as_ref()?;
+        if self.last_vote.as_ref() == Some(&head.hash) {
+            return None;
+        }
+        let vote = Vote {
+            id: head.id,
+            hash: head.hash.clone(),
+            voter: hex::encode(key.public().to_bytes()),
+            signature: hex::encode(key.sign(&vote_message(&head.hash))),
+        };
+        self.last_vote = Some(head.hash.clone());
+        self.add_vote(&vote).ok()?;
+        Some(vote)
+    }
+
+    /// Checks and records a vote, returns whether it replaced the voter's previous one. Votes for
+    /// a lower block than the previous one are ignored.
+    pub fn add_vote(&mut self, vote: &Vote) -> Result<bool, VoteError> {
+        let voter = hex::decode(&vote.voter)
+            .ok()
+            .and_then(|key| self.validators.iter().position(|v| v.to_bytes()[..] == key))
+            .ok_or_else(|| VoteError::UnknownVoter(vote.voter.clone()))?;
+        let signed = hex::decode(&vote.signature).is_ok_and(|signature| {
+            self.validators[voter].verify(&vote_message(&vote.hash), &signature)
+        });
+        if !signed {
+            return Err(VoteError::InvalidSignature(vote.id));
+        }
+        let newer = self
+            .votes
+            .get(&voter)
+            .is_none_or(|previous| previous.id <= vote.id && previous.hash != vote.hash);
+        if newer {
+            self.votes.insert(voter, vote.clone());
+        }
+        Ok(newer)
+    }
+
+    /// The highest block of `chain` backed by enough votes, if above its finalized block.
+    pub fn finalize(&self, chain: &Chain) -> Option<Header> {
+        let mut backed: Vec<u64> = self
+            .votes
+            .values()
+            .filter(|vote| {
+                chain
+                    .get_header(vote.id)
+                    .is_some_and(|h| h.hash == vote.hash)
+            })
+            .map(|vote| vote.id)
+            .collect();
+        backed.sort_unstable_by(|a, b| b.cmp(a));
+        let id = *backed.get(self.threshold() - 1)?;
+        let finalized = chain.finalized.as_ref().map(|f| f.id);
+        (Some(id) > finalized)
+            .then(|| chain.get_header(id).cloned())
+            .flatten()
+    }
+}
+
+fn vote_message(hash: &str) -> Vec<u8> {
+    [VOTE_CONTEXT, hash.as_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validators(n: usize) -> (Vec<ed25519::Keypair>, FinalitySpec) {
+        let keys: Vec<_> = (0..n).map(|_| ed25519::Keypair::generate()).collect();
+        let spec = FinalitySpec {
+            validators: keys
+                .iter()
+                .map(|k| hex::encode(k.public().to_bytes()))
+                .collect(),
+        };
+        (keys, spec)
+    }
+
+    #[test]
+    fn finalizes_with_two_thirds_of_the_votes() {
+        let (keys, spec) = validators(4);
+        let mut chain = Chain::default();
+        let block = chain.add_data(String::from("one")).unwrap();
+        let head = chain.add_data(String::from("two")).unwrap();
+        let mut local = Finality::new(&spec, Some(keys[0].clone())).unwrap();
+        assert_eq!(local.threshold(), 3);
+
+        let vote = local
+            .vote(&block.header)
+            .expect("local node is a validator");
+        assert_eq!(local.vote(&block.header), None, "votes once per head");
+        let mut remote = Finality::new(&spec, Some(keys[1].clone())).unwrap();
+        assert_eq!(remote.add_vote(&vote), Ok(true));
+        assert_eq!(remote.add_vote(&vote), Ok(false));
+        let vote = remote.vote(&block.header).unwrap();
+        local.add_vote(&vote).unwrap();
+        assert_eq!(local.finalize(&chain), None);
+
+        // a vote for the head backs its ancestors
+        let mut third = Finality::new(&spec, Some(keys[2].clone())).unwrap();
+        local.add_vote(&third.vote(&head.header).unwrap()).unwrap();
+        assert_eq!(local.finalize(&chain), Some(block.header.clone()));
+        chain.finalized = Some(block.header.clone());
+        assert_eq!(local.finalize(&chain), None);
+
+        let mut forged = vote.clone();
+        forged.hash = chain.blocks[0].hash.clone();
+        assert_eq!(local.add_vote(&forged), Err(VoteError::InvalidSignature(1)));
+        let (outsider, other_spec) = validators(1);
+        let outsider = Finality::new(&other_spec, Some(outsider[0].clone()))
+            .unwrap()
+            .vote(&block.header);
+        assert!(matches!(
+            local.add_vote(&outsider.unwrap()),
+            Err(VoteError::UnknownVoter(_))
+        ));
+    }
+
+    #[test]
+    fn fork_choice_keeps_finalized_blocks() {
+        let mut chain = Chain::default();
+        let finalized = chain.add_data(String::from("final")).unwrap();
+        chain.finalized = Some(finalized.header.clone());
+
+        let mut longer = Chain::default();
+        for data in ["a", "b", "c"] {
+            longer.add_data(String::from(data)).unwrap();
+        }
+        assert!(chain.choose_chain(&longer).is_none());
+        assert_eq!(chain.head(), &finalized);
+
+        let mut extended = Chain::default();
+        extended.blocks.push(finalized.clone());
+        extended.add_data(String::from("next")).unwrap();
+        assert!(chain.choose_chain(&extended).is_some());
+        assert_eq!(chain.finalized, Some(finalized.header));
+    }
+}
@@ -0,0 +1,162 @@
+//! On-chain governance: accounts propose new [`RuntimeParams`] and vote on them, weighted by
+//! their balance.
+//!
+//! Votes are counted from the block carrying the proposal until `voting_period` blocks later.
+//! The tally is taken when that block is applied, with the balances of the voters at that
+//! point, so coins moved after voting don't count twice. If enough of the balances voted and
+//! the ayes outweigh the nays, the params apply `enactment_delay` blocks later, like an upgrade
+//! set by the sudo account. See
+//! [`crate::accounts::Call::Propose`] and [`crate::accounts::Call::Vote`].
+use crate::{ledger::Address, runtime::RuntimeParams};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GovernanceSpec {
+    /// Blocks during which a proposal takes votes.
+    #[serde(default = "default_voting_period")]
+    pub voting_period: u64,
+    /// Blocks between the tally and the params of an approved proposal, at least one.
+    #[serde(default = "default_enactment_delay")]
+    pub enactment_delay: u64,
+    /// Share of the total balance that must vote, aye or nay, for a proposal to pass, in
+    /// percent.
+    #[serde(default = "default_quorum_percent")]
+    pub quorum_percent: u64,
+}
+
+fn default_voting_period() -> u64 {
+    10
+}
+
+fn default_enactment_delay() -> u64 {
+    5
+}
+
+fn default_quorum_percent() -> u64 {
+    20
+}
+
+impl Default for GovernanceSpec {
+    fn default() -> Self {
+        Self {
+            voting_period: default_voting_period(),
+            enactment_delay: default_enactment_delay(),
+            quorum_percent: default_quorum_percent(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProposalStatus {
+    Open,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub proposer: Address,
+    pub params: RuntimeParams,
+    /// Block where the votes are tallied, votes must be in a block before it.
+    pub voting_ends: u64,
+    /// Block the params apply from, if approved.
+    pub enact_at: u64,
+    /// Latest vote of each account, `true` for aye.
+    pub votes: BTreeMap<Address, bool>,
+    pub status: ProposalStatus,
+}
+
+impl Proposal {
+    /// A proposal carried by block `id`, open for votes.
+    pub fn new(proposer: Address, params: RuntimeParams, id: u64) -> Self {
+        let spec = spec();
+        let voting_ends = id + spec.voting_period;
+        Self {
+            proposer,
+            params,
+            voting_ends,
+            enact_at: voting_ends + spec.enactment_delay.max(1),
+            votes: BTreeMap::new(),
+            status: ProposalStatus::Open,
+        }
+    }
+
+    /// Sums the votes, each weighted by the `stake` of the voter.
+    pub fn tally(&self, stake: impl Fn(&str) -> u64) -> Tally {
+        self.votes
+            .iter()
+            .fold(Tally::default(), |mut tally, (voter, aye)| {
+                let side = match aye {
+                    true => &mut tally.ayes,
+                    false => &mut tally.nays,
+                };
+                *side = side.saturating_add(stake(voter));
+                tally
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub ayes: u64,
+    pub nays: u64,
+}
+
+impl Tally {
+    /// Whether the votes reach the quorum of `total` balance and the ayes win.
+    pub fn approved(&self, total: u64) -> bool {
+        let turnout = u128::from(self.ayes) + u128::from(self.nays);
+        let quorum = u128::from(total) * u128::from(spec().quorum_percent);
+        turnout * 100 >= quorum && self.ayes > self.nays
+    }
+}
+
+static SPEC: OnceCell<GovernanceSpec> = OnceCell::new();
+
+/// Voting periods of this node, the default ones unless [`set_spec`] was called.
+pub fn spec() -> &'static GovernanceSpec {
+    SPEC.get_or_init(GovernanceSpec::default)
+}
+
+/// Sets the voting periods of this node, once, before any block is checked.
+/// Returns `false` if it was already set.
+pub fn set_spec(spec: GovernanceSpec) -> bool {
+    SPEC.set(spec).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn votes_weigh_their_stake() {
+        let mut proposal = Proposal::new(String::from("alice"), RuntimeParams::default(), 3);
+        assert_eq!(proposal.voting_ends, 3 + spec().voting_period);
+        assert!(proposal.enact_at > proposal.voting_ends);
+        let stake = |voter: &str| match voter {
+            "alice" => 30,
+            "bob" => 20,
+            _ => 15,
+        };
+        proposal.votes.insert(String::from("alice"), true);
+        proposal.votes.insert(String::from("bob"), false);
+        assert_eq!(proposal.tally(stake), Tally { ayes: 30, nays: 20 });
+        assert!(proposal.tally(stake).approved(65));
+        proposal.votes.insert(String::from("charlie"), false);
+        assert!(!proposal.tally(stake).approved(65));
+        // a new vote replaces the previous one
+        proposal.votes.insert(String::from("alice"), false);
+        assert_eq!(proposal.tally(stake), Tally { ayes: 0, nays: 65 });
+    }
+
+    #[test]
+    fn too_few_votes_miss_the_quorum() {
+        let tally = Tally { ayes: 20, nays: 0 };
+        let quorum = spec().quorum_percent;
+        assert!(tally.approved(20 * 100 / quorum));
+        assert!(!tally.approved(20 * 100 / quorum + 1));
+    }
+}
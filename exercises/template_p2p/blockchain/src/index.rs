@@ -0,0 +1,315 @@
+//! Secondary index of the chain: where each entry of a block is, which entries touch an address
+//! and which block was mined by a given time.
+//!
+//! [`ChainIndex`] follows the chain like [`crate::accounts::Accounts`] does. It remembers what
+//! each block added, so a reorg removes the blocks that left the chain before indexing the new
+//! ones. Blocks pruned before they were indexed only add their timestamp.
+use crate::{
+    accounts::{Call, Extrinsic},
+    blocks::{Block, Chain, Header},
+    contracts,
+    ledger::Address,
+    utxo::{OutPoint, Transaction},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// The data of the block, hashed like [`crate::p2p::submit_data`] does.
+    Data,
+    Transaction,
+    Extrinsic,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub block_id: u64,
+    pub block_hash: String,
+    pub kind: EntryKind,
+    /// Position among the entries of its kind in the block.
+    pub index: usize,
+}
+
+/// An entry touching an address, as listed by [`ChainIndex::history`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub hash: String,
+    #[serde(flatten)]
+    pub location: Location,
+}
+
+/// What indexing a block added, to remove it on reorg.
+#[derive(Debug, Clone, Default)]
+struct Indexed {
+    id: u64,
+    hash: String,
+    timestamp: i64,
+    entries: Vec<String>,
+    addresses: Vec<Address>,
+    outputs: Vec<OutPoint>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChainIndex {
+    /// Locations of each entry hash, the same data can be in several blocks.
+    entries: HashMap<String, Vec<Location>>,
+    /// Entries touching each address, oldest first.
+    addresses: HashMap<Address, Vec<Entry>>,
+    /// Timestamp and id of every block.
+    times: BTreeSet<(i64, u64)>,
+    /// Owner of each output of the UTXO ledger, to list the spender of an input.
+    outputs: HashMap<OutPoint, Address>,
+    /// One entry per block indexed, from the genesis block.
+    indexed: Vec<Indexed>,
+}
+
+impl ChainIndex {
+    /// Where the data, transaction or extrinsic with hash `hash` is, oldest first.
+    pub fn locate(&self, hash: &str) -> &[Location] {
+        self.entries.get(hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// Transactions and extrinsics touching `address`, as sender, recipient or contract.
+    pub fn history(&self, address: &str) -> &[Entry] {
+        self.addresses.get(address).map_or(&[], Vec::as_slice)
+    }
+
+    /// Id of the block with the latest timestamp at or before `timestamp`, which is the head
+    /// at that time unless clocks were off.
+    pub fn height_at(&self, timestamp: i64) -> Option<u64> {
+        self.times
+            .range(..=(timestamp, u64::MAX))
+            .next_back()
+            .map(|(_, id)| *id)
+    }
+
+    /// Id and hash of the last block indexed.
+    pub fn tip(&self) -> Option<(u64, &str)> {
+        self.indexed.last().map(|i| (i.id, i.hash.as_str()))
+    }
+
+    /// Removes the blocks that are no longer in `chain` and indexes the new ones.
+    pub fn sync(&mut self, chain: &Chain) {
+        while let Some((id, hash)) = self.tip() {
+            if chain.get_header(id).is_some_and(|h| h.hash == hash) {
+                break;
+            }
+            self.rollback();
+        }
+        let next = self.tip().map_or(0, |(id, _)| id + 1);
+        for id in next..chain.len() as u64 {
+            match (chain.get_block(id), chain.get_header(id)) {
+                (Some(block), _) => self.add_block(block),
+                (None, Some(header)) => self.add_header(header),
+                (None, None) => break,
+            }
+        }
+    }
+
+    fn add_header(&mut self, header: &Header) {
+        self.times.insert((header.timestamp, header.id));
+        self.indexed.push(Indexed {
+            id: header.id,
+            hash: header.hash.clone(),
+            timestamp: header.timestamp,
+            ..Indexed::default()
+        });
+    }
+
+    /// Indexes the entries of `block`, which must follow the last block indexed.
+    pub fn add_block(&mut self, block: &Block) {
+        self.add_header(&block.header);
+        let mut added = self.indexed.pop().expect("just pushed");
+        let location = |kind, index| Location {
+            block_id: block.id,
+            block_hash: block.hash.clone(),
+            kind,
+            index,
+        };
+        if !block.data.is_empty() {
+            let hash = hex::encode(Sha256::digest(block.data.as_bytes()));
+            self.add_entry(&mut added, hash, location(EntryKind::Data, 0), &[]);
+        }
+        if let Some(coinbase) = &block.coinbase {
+            let outpoint = OutPoint {
+                tx: block.hash.clone(),
+                index: 0,
+            };
+            self.add_output(&mut added, outpoint, &coinbase.to);
+        }
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let hash = tx.hash();
+            let addresses = self.addresses_of_transaction(tx);
+            for (output, to) in tx.outputs.iter().enumerate() {
+                let outpoint = OutPoint {
+                    tx: hash.clone(),
+                    index: output as u32,
+                };
+                self.add_output(&mut added, outpoint, &to.to);
+            }
+            let location = location(EntryKind::Transaction, index);
+            self.add_entry(&mut added, hash, location, &addresses);
+        }
+        for (index, extrinsic) in block.extrinsics.iter().enumerate() {
+            let location = location(EntryKind::Extrinsic, index);
+            let addresses = addresses_of_extrinsic(extrinsic);
+            self.add_entry(&mut added, extrinsic.hash(), location, &addresses);
+        }
+        self.indexed.push(added);
+    }
+
+    fn add_entry(
+        &mut self,
+        added: &mut Indexed,
+        hash: String,
+        location: Location,
+        addresses: &[Address],
+    ) {
+        for address in addresses {
+            let entry = Entry {
+                hash: hash.clone(),
+                location: location.clone(),
+            };
+            self.addresses
+                .entry(address.clone())
+                .or_default()
+                .push(entry);
+            added.addresses.push(address.clone());
+        }
+        self.entries.entry(hash.clone()).or_default().push(location);
+        added.entries.push(hash);
+    }
+
+    fn add_output(&mut self, added: &mut Indexed, outpoint: OutPoint, to: &str) {
+        self.outputs.insert(outpoint.clone(), String::from(to));
+        added.outputs.push(outpoint);
+    }
+
+    fn addresses_of_transaction(&self, tx: &Transaction) -> Vec<Address> {
+        let spenders = tx
+            .inputs
+            .iter()
+            .filter_map(|input| self.outputs.get(&input.outpoint));
+        let recipients = tx.outputs.iter().map(|output| &output.to);
+        let mut addresses: Vec<_> = spenders.chain(recipients).cloned().collect();
+        addresses.sort();
+        addresses.dedup();
+        addresses
+    }
+
+    /// Removes the last block indexed, returns its id.
+    pub fn rollback(&mut self) -> Option<u64> {
+        let indexed = self.indexed.pop()?;
+        // entries were pushed in order, so the last ones are those of this block
+        for hash in indexed.entries.iter().rev() {
+            if let Some(locations) = self.entries.get_mut(hash) {
+                locations.pop();
+                if locations.is_empty() {
+                    self.entries.remove(hash);
+                }
+            }
+        }
+        for address in indexed.addresses.iter().rev() {
+            if let Some(entries) = self.addresses.get_mut(address) {
+                entries.pop();
+                if entries.is_empty() {
+                    self.addresses.remove(address);
+                }
+            }
+        }
+        for outpoint in &indexed.outputs {
+            self.outputs.remove(outpoint);
+        }
+        self.times.remove(&(indexed.timestamp, indexed.id));
+        Some(indexed.id)
+    }
+}
+
+fn addresses_of_extrinsic(extrinsic: &Extrinsic) -> Vec<Address> {
+    let other = match &extrinsic.call {
+        Call::Transfer { to, .. } => Some(to.clone()),
+        Call::Deploy { .. } => Some(contracts::contract_address(
+            &extrinsic.signer,
+            extrinsic.nonce,
+        )),
+        Call::CallContract { contract, .. } => Some(contract.clone()),
+        Call::SetParams { .. } | Call::Propose { .. } | Call::Vote { .. } => None,
+    };
+    let mut addresses = vec![extrinsic.signer.clone()];
+    addresses.extend(other.filter(|address| *address != extrinsic.signer));
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{KeyScheme, Keypair};
+
+    fn mine(chain: &mut Chain, data: &str, extrinsics: Vec<Extrinsic>, timestamp: i64) -> Block {
+        let head = chain.head().header.clone();
+        let mut block =
+            Block::with_extrinsics(&head, String::from(data), extrinsics, None).unwrap();
+        // the index doesn't check blocks, only reads them
+        block.header.timestamp = timestamp;
+        chain.blocks.push(block.clone());
+        block
+    }
+
+    fn data_hash(data: &str) -> String {
+        hex::encode(Sha256::digest(data.as_bytes()))
+    }
+
+    #[test]
+    fn follows_the_chain_across_reorgs() {
+        let alice = Keypair::generate(KeyScheme::Sr25519);
+        let bob = Keypair::generate(KeyScheme::Ed25519).address();
+        let transfer = Extrinsic::signed(
+            &alice,
+            0,
+            Call::Transfer {
+                to: bob.clone(),
+                amount: 5,
+            },
+        );
+        let mut chain = Chain::default();
+        let start = chain.head().timestamp;
+        let first = mine(&mut chain, "hello", Vec::new(), start + 10);
+        let fork = Chain {
+            blocks: chain.blocks.clone(),
+            ..Chain::default()
+        };
+        let second = mine(&mut chain, "paid", vec![transfer.clone()], start + 20);
+        let mut index = ChainIndex::default();
+        index.sync(&chain);
+
+        assert_eq!(
+            index.locate(&transfer.hash()),
+            [Location {
+                block_id: 2,
+                block_hash: second.hash.clone(),
+                kind: EntryKind::Extrinsic,
+                index: 0,
+            }]
+        );
+        assert_eq!(index.locate(&data_hash("hello"))[0].block_hash, first.hash);
+        assert_eq!(index.history(&bob), index.history(&alice.address()));
+        assert_eq!(index.history(&bob)[0].hash, transfer.hash());
+        assert_eq!(index.height_at(start - 1), None);
+        assert_eq!(index.height_at(start + 15), Some(1));
+        assert_eq!(index.height_at(start + 20), Some(2));
+
+        // a longer fork without the transfer replaces the chain
+        let mut other = fork;
+        mine(&mut other, "hello", Vec::new(), start + 30);
+        mine(&mut other, "longer", Vec::new(), start + 40);
+        index.sync(&other);
+        assert!(index.locate(&transfer.hash()).is_empty());
+        assert!(index.history(&bob).is_empty());
+        assert_eq!(index.locate(&data_hash("hello")).len(), 2);
+        assert_eq!(index.height_at(start + 20), Some(1));
+        assert_eq!(index.height_at(start + 40), Some(3));
+    }
+}
@@ -0,0 +1,151 @@
+//! Offline checks and statistics over a list of blocks, used by the `chain-tool` binary.
+use crate::blocks::{self, Block, BlockError};
+use itertools::Itertools;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum VerifyError {
+    #[error("block with id: {0} is not the genesis block")]
+    WrongGenesis(u64),
+    #[error(transparent)]
+    Block(#[from] BlockError),
+}
+
+/// Every validation failure, each block being checked against the one before it even when
+/// that one is invalid.
+pub fn verify(blocks: &[Block]) -> Vec<VerifyError> {
+    let genesis = blocks
+        .first()
+        .filter(|first| **first != Block::default())
+        .map(|first| VerifyError::WrongGenesis(first.id));
+    let blocks = blocks
+        .iter()
+        .tuple_windows()
+        .filter_map(|(previous, block)| block.validate(previous).err())
+        .map(VerifyError::from);
+    genesis.into_iter().chain(blocks).collect()
+}
+
+/// Block ids are tried before hashes, hashes can be shortened to a unique prefix.
+pub fn find<'a>(blocks: &'a [Block], key: &str) -> Option<&'a Block> {
+    if let Ok(id) = key.parse::<u64>() {
+        if let Some(block) = blocks.iter().find(|b| b.id == id) {
+            return Some(block);
+        }
+    }
+    blocks
+        .iter()
+        .filter(|b| b.hash.starts_with(key))
+        .exactly_one()
+        .ok()
+}
+
+/// Leading zeros of the block hash counted like proof-of-work does, one per zero byte, the
+/// work the block actually shows.
+pub fn difficulty(block: &Block) -> usize {
+    let hash = hex::decode(&block.hash).unwrap_or_default();
+    blocks::hash2binary(&hash)
+        .chars()
+        .take_while(|c| *c == '0')
+        .count()
+}
+
+/// Seconds between the timestamps of each block and the one before it.
+pub fn block_times(blocks: &[Block]) -> Vec<i64> {
+    blocks
+        .iter()
+        .tuple_windows()
+        .map(|(previous, block)| block.timestamp - previous.timestamp)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fork {
+    /// Last block both chains agree on, `None` if they don't share the genesis block.
+    pub common: Option<u64>,
+    /// Blocks of each chain after the common block.
+    pub only_a: usize,
+    pub only_b: usize,
+}
+
+/// Finds where two chains stop agreeing.
+pub fn diff(a: &[Block], b: &[Block]) -> Fork {
+    let common = a
+        .iter()
+        .zip(b.iter())
+        .take_while(|(x, y)| x.hash == y.hash)
+        .count();
+    Fork {
+        common: common.checked_sub(1).map(|i| a[i].id),
+        only_a: a.len() - common,
+        only_b: b.len() - common,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::Chain;
+
+    fn chain(data: &[&str]) -> Vec<Block> {
+        let mut chain = Chain::default();
+        for d in data {
+            chain.add_data(String::from(*d)).expect("can add data");
+        }
+        chain.blocks
+    }
+
+    #[test]
+    fn reports_every_bad_block() {
+        let mut blocks = chain(&["a", "b", "c"]);
+        assert_eq!(verify(&blocks), []);
+        blocks[1].data = String::from("tampered");
+        blocks[3].header.previous_hash = String::from("00");
+        assert_eq!(
+            verify(&blocks),
+            [
+                VerifyError::Block(BlockError::InvalidDataRoot { id: 1 }),
+                VerifyError::Block(BlockError::WrongPreviousHash { id: 3 }),
+            ]
+        );
+        assert_eq!(verify(&blocks[1..2]), [VerifyError::WrongGenesis(1)]);
+    }
+
+    #[test]
+    fn finds_blocks_and_forks() {
+        let a = chain(&["shared", "a"]);
+        let mut b = a[..2].to_vec();
+        let mut fork = Chain {
+            blocks: b.clone(),
+            pruned: Vec::new(),
+            finalized: None,
+        };
+        b.push(fork.add_data(String::from("b1")).unwrap());
+        b.push(fork.add_data(String::from("b2")).unwrap());
+
+        assert_eq!(find(&a, "2"), Some(&a[2]));
+        assert_eq!(find(&a, &a[1].hash[..12]), Some(&a[1]));
+        assert_eq!(find(&a, "ffff"), None);
+        assert_eq!(
+            diff(&a, &b),
+            Fork {
+                common: Some(1),
+                only_a: 1,
+                only_b: 2,
+            }
+        );
+        assert!(difficulty(&a[1]) >= 2);
+        assert_eq!(block_times(&a).len(), 2);
+    }
+
+    #[test]
+    fn difficulty_counts_zero_bytes_like_the_chain() {
+        let block = &chain(&["mined"])[1];
+        let zero_bytes = block.hash.as_bytes().chunks(2).take_while(|b| b == b"00");
+        assert_eq!(difficulty(block), zero_bytes.count());
+        assert!(difficulty(block) >= crate::runtime::spec().difficulty(block.id));
+
+        let mut easy = block.clone();
+        easy.header.hash = format!("0f{}", "ff".repeat(31));
+        assert_eq!(difficulty(&easy), 0);
+    }
+}
@@ -0,0 +1,358 @@
+//! Account keys and addresses.
+//!
+//! Accounts sign with ed25519 or sr25519 (Schnorr over Ristretto, as in Substrate), both with
+//! 32 byte public keys. Addresses are SS58-style: base58 of a network prefix, the public key
+//! and two checksum bytes, so a mistyped address is caught before coins are sent to it.
+//!
+//! Keys can be derived from a secret URI like Substrate's: a BIP39 phrase followed by hard
+//! junctions, e.g. `<phrase>//stash//1`. Without a phrase the well-known [`DEV_PHRASE`] is used,
+//! so `//Alice` is the same dev account as on any Substrate chain.
+use blake2::{digest::consts::U32, Blake2b, Blake2b512, Digest};
+use libp2p::identity::ed25519;
+use serde::{Deserialize, Serialize};
+
+/// Generic Substrate prefix, addresses start with a `5`.
+const SS58_PREFIX: u8 = 42;
+const SS58_CONTEXT: &[u8] = b"SS58PRE";
+/// Signing context of sr25519 signatures, the same as Substrate's.
+const SR25519_CONTEXT: &[u8] = b"substrate";
+
+/// Phrase of the Substrate dev accounts, never to be used for real coins.
+pub const DEV_PHRASE: &str =
+    "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+/// Names of the well-known dev accounts, derived as `//<name>` from [`DEV_PHRASE`].
+pub const DEV_ACCOUNTS: &[&str] = &["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyScheme {
+    #[default]
+    Ed25519,
+    Sr25519,
+}
+
+impl std::fmt::Display for KeyScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyScheme::Ed25519 => write!(f, "ed25519"),
+            KeyScheme::Sr25519 => write!(f, "sr25519"),
+        }
+    }
+}
+
+impl std::str::FromStr for KeyScheme {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ed25519" => Ok(KeyScheme::Ed25519),
+            "sr25519" => Ok(KeyScheme::Sr25519),
+            _ => Err(KeyError::UnknownScheme(String::from(s))),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum KeyError {
+    #[error("unknown key scheme `{0}`, expected ed25519 or sr25519")]
+    UnknownScheme(String),
+    #[error("invalid secret key")]
+    InvalidSecret,
+    #[error("`{0}` is not a valid address")]
+    InvalidAddress(String),
+    #[error("address `{0}` has a wrong checksum")]
+    InvalidChecksum(String),
+    #[error("invalid mnemonic phrase: {0}")]
+    InvalidPhrase(String),
+    #[error("soft junction `/{0}` is not supported, use hard ones: `//{0}`")]
+    SoftJunction(String),
+}
+
+/// Signing key of an account, made from a 32 byte seed.
+#[derive(Clone)]
+pub enum Keypair {
+    Ed25519(ed25519::Keypair),
+    Sr25519(schnorrkel::Keypair),
+}
+
+impl Keypair {
+    pub fn generate(scheme: KeyScheme) -> Self {
+        Self::from_seed(scheme, &rand::random()).expect("any 32 bytes are a valid seed")
+    }
+
+    pub fn from_seed(scheme: KeyScheme, seed: &[u8; 32]) -> Result<Self, KeyError> {
+        Ok(match scheme {
+            KeyScheme::Ed25519 => {
+                let secret = ed25519::SecretKey::try_from_bytes(seed.to_vec())
+                    .map_err(|_| KeyError::InvalidSecret)?;
+                Keypair::Ed25519(secret.into())
+            }
+            KeyScheme::Sr25519 => {
+                let secret = schnorrkel::MiniSecretKey::from_bytes(seed)
+                    .map_err(|_| KeyError::InvalidSecret)?;
+                Keypair::Sr25519(secret.expand_to_keypair(schnorrkel::ExpansionMode::Ed25519))
+            }
+        })
+    }
+
+    /// Key of the secret URI `uri`, see [`seed_from_uri`].
+    pub fn from_uri(scheme: KeyScheme, uri: &str) -> Result<Self, KeyError> {
+        Self::from_seed(scheme, &seed_from_uri(scheme, uri)?)
+    }
+
+    pub fn scheme(&self) -> KeyScheme {
+        match self {
+            Keypair::Ed25519(_) => KeyScheme::Ed25519,
+            Keypair::Sr25519(_) => KeyScheme::Sr25519,
+        }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        match self {
+            Keypair::Ed25519(key) => key.public().to_bytes(),
+            Keypair::Sr25519(key) => key.public.to_bytes(),
+        }
+    }
+
+    pub fn address(&self) -> String {
+        encode_address(&self.public())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            Keypair::Ed25519(key) => key.sign(message),
+            Keypair::Sr25519(key) => key
+                .sign_simple(SR25519_CONTEXT, message)
+                .to_bytes()
+                .to_vec(),
+        }
+    }
+}
+
+/// Checks a signature of `message` by the owner of `address`.
+pub fn verify(scheme: KeyScheme, address: &str, message: &[u8], signature: &[u8]) -> bool {
+    let Ok(public) = decode_address(address) else {
+        return false;
+    };
+    match scheme {
+        KeyScheme::Ed25519 => ed25519::PublicKey::try_from_bytes(&public)
+            .is_ok_and(|key| key.verify(message, signature)),
+        KeyScheme::Sr25519 => schnorrkel::PublicKey::from_bytes(&public)
+            .ok()
+            .zip(schnorrkel::Signature::from_bytes(signature).ok())
+            .is_some_and(|(key, signature)| {
+                key.verify_simple(SR25519_CONTEXT, message, &signature)
+                    .is_ok()
+            }),
+    }
+}
+
+/// A new random 12 word BIP39 phrase.
+pub fn generate_phrase() -> String {
+    let entropy: [u8; 16] = rand::random();
+    bip39::Mnemonic::from_entropy(&entropy)
+        .expect("16 bytes is a valid entropy length")
+        .to_string()
+}
+
+/// Seed of the phrase of `uri` with its hard junctions applied in order, `//Alice//stash`
+/// derives `stash` from `Alice`. A `///password` suffix is the BIP39 password.
+pub fn seed_from_uri(scheme: KeyScheme, uri: &str) -> Result<[u8; 32], KeyError> {
+    let (uri, password) = uri.split_once("///").unwrap_or((uri, ""));
+    let (phrase, path) = uri.find('/').map_or((uri, ""), |i| uri.split_at(i));
+    let phrase = match phrase.trim() {
+        "" => DEV_PHRASE,
+        phrase => phrase,
+    };
+    let mnemonic =
+        bip39::Mnemonic::parse(phrase).map_err(|e| KeyError::InvalidPhrase(e.to_string()))?;
+    let seed = substrate_bip39::seed_from_entropy(&mnemonic.to_entropy(), password)
+        .map_err(|_| KeyError::InvalidPhrase(String::from("bad entropy")))?;
+    let mut seed: [u8; 32] = seed[..32].try_into().expect("64 byte seed");
+    let mut rest = path;
+    while let Some(junction) = rest.strip_prefix('/') {
+        let hard = junction.strip_prefix('/');
+        let junction = hard.unwrap_or(junction);
+        let end = junction.find('/').unwrap_or(junction.len());
+        if hard.is_none() || end == 0 {
+            return Err(KeyError::SoftJunction(String::from(&junction[..end])));
+        }
+        seed = hard_derive(scheme, &seed, &chain_code(&junction[..end]));
+        rest = &junction[end..];
+    }
+    Ok(seed)
+}
+
+/// Chain code of a junction: a number as 8 little endian bytes, text SCALE encoded as in
+/// Substrate, hashed if longer than 32 bytes.
+fn chain_code(junction: &str) -> [u8; 32] {
+    let encoded = match junction.parse::<u64>() {
+        Ok(n) => n.to_le_bytes().to_vec(),
+        Err(_) => [compact_len(junction.len()), junction.as_bytes().to_vec()].concat(),
+    };
+    let mut code = [0; 32];
+    match encoded.len() > 32 {
+        true => code.copy_from_slice(&Blake2b::<U32>::digest(&encoded)),
+        false => code[..encoded.len()].copy_from_slice(&encoded),
+    }
+    code
+}
+
+/// SCALE compact encoding of a length.
+fn compact_len(len: usize) -> Vec<u8> {
+    let len = len as u32;
+    match len {
+        0..=0x3f => vec![(len << 2) as u8],
+        0x40..=0x3fff => ((len << 2) as u16 | 0b01).to_le_bytes().to_vec(),
+        _ => ((len << 2) | 0b10).to_le_bytes().to_vec(),
+    }
+}
+
+fn hard_derive(scheme: KeyScheme, seed: &[u8; 32], chain_code: &[u8; 32]) -> [u8; 32] {
+    match scheme {
+        KeyScheme::Ed25519 => {
+            let domain = b"Ed25519HDKD";
+            Blake2b::<U32>::new()
+                .chain_update(compact_len(domain.len()))
+                .chain_update(domain)
+                .chain_update(seed)
+                .chain_update(chain_code)
+                .finalize()
+                .into()
+        }
+        KeyScheme::Sr25519 => schnorrkel::MiniSecretKey::from_bytes(seed)
+            .expect("32 byte seed")
+            .hard_derive_mini_secret_key(
+                Some(schnorrkel::derive::ChainCode(*chain_code)),
+                b"",
+                schnorrkel::ExpansionMode::Ed25519,
+            )
+            .0
+            .to_bytes(),
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; 2] {
+    let hash = Blake2b512::new()
+        .chain_update(SS58_CONTEXT)
+        .chain_update(payload)
+        .finalize();
+    [hash[0], hash[1]]
+}
+
+pub fn encode_address(public: &[u8; 32]) -> String {
+    let mut bytes = vec![SS58_PREFIX];
+    bytes.extend_from_slice(public);
+    let checksum = checksum(&bytes);
+    bytes.extend_from_slice(&checksum);
+    bs58::encode(bytes).into_string()
+}
+
+/// Public key of an address.
+pub fn decode_address(address: &str) -> Result<[u8; 32], KeyError> {
+    let invalid = || KeyError::InvalidAddress(String::from(address));
+    let bytes = bs58::decode(address).into_vec().map_err(|_| invalid())?;
+    if bytes.len() != 35 || bytes[0] != SS58_PREFIX {
+        return Err(invalid());
+    }
+    let (payload, sum) = bytes.split_at(33);
+    if checksum(payload) != sum {
+        return Err(KeyError::InvalidChecksum(String::from(address)));
+    }
+    Ok(payload[1..].try_into().expect("32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_round_trip_and_catch_typos() {
+        let key = Keypair::generate(KeyScheme::Sr25519);
+        let address = key.address();
+        assert!(address.starts_with('5'));
+        assert_eq!(decode_address(&address), Ok(key.public()));
+
+        let typo: String = address
+            .chars()
+            .enumerate()
+            .map(|(i, c)| match (i, c) {
+                (10, 'a') => 'b',
+                (10, _) => 'a',
+                _ => c,
+            })
+            .collect();
+        assert!(decode_address(&typo).is_err());
+        assert_eq!(
+            decode_address("not an address"),
+            Err(KeyError::InvalidAddress(String::from("not an address")))
+        );
+    }
+
+    #[test]
+    fn dev_accounts_match_substrate() {
+        let alice = |scheme| Keypair::from_uri(scheme, "//Alice").unwrap().address();
+        assert_eq!(
+            alice(KeyScheme::Sr25519),
+            "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+        );
+        assert_eq!(
+            alice(KeyScheme::Ed25519),
+            "5FA9nQDVg267DEd8m1ZypXLBnvN7SFxYwV7ndqSYGiN9TTpu"
+        );
+        let bob = Keypair::from_uri(KeyScheme::Sr25519, &format!("{}//Bob", DEV_PHRASE));
+        assert_eq!(
+            bob.unwrap().address(),
+            "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty"
+        );
+        let stash = Keypair::from_uri(KeyScheme::Sr25519, "//Alice//stash").unwrap();
+        assert_eq!(
+            stash.address(),
+            "5GNJqTPyNqANBkUVMN1LPPrxXnFouWXoe2wNSmmEoLctxiZY"
+        );
+    }
+
+    #[test]
+    fn phrases_are_checked() {
+        let phrase = generate_phrase();
+        assert_eq!(phrase.split_whitespace().count(), 12);
+        let key = Keypair::from_uri(KeyScheme::Ed25519, &phrase).unwrap();
+        assert_eq!(
+            key.address(),
+            Keypair::from_uri(KeyScheme::Ed25519, &phrase)
+                .unwrap()
+                .address()
+        );
+        let with_password = format!("{}///secret", phrase);
+        assert_ne!(
+            Keypair::from_uri(KeyScheme::Ed25519, &with_password)
+                .unwrap()
+                .address(),
+            key.address()
+        );
+        assert!(matches!(
+            seed_from_uri(KeyScheme::Sr25519, "bottom drive obey"),
+            Err(KeyError::InvalidPhrase(_))
+        ));
+        assert_eq!(
+            seed_from_uri(KeyScheme::Sr25519, "//Alice/soft"),
+            Err(KeyError::SoftJunction(String::from("soft")))
+        );
+    }
+
+    #[test]
+    fn both_schemes_sign_and_verify() {
+        for scheme in [KeyScheme::Ed25519, KeyScheme::Sr25519] {
+            let key = Keypair::from_seed(scheme, &[7; 32]).unwrap();
+            let signature = key.sign(b"message");
+            assert!(verify(scheme, &key.address(), b"message", &signature));
+            assert!(!verify(scheme, &key.address(), b"other", &signature));
+            let other = Keypair::generate(scheme);
+            assert!(!verify(scheme, &other.address(), b"message", &signature));
+        }
+        // the same seed gives different keys in each scheme
+        let ed = Keypair::from_seed(KeyScheme::Ed25519, &[7; 32]).unwrap();
+        let sr = Keypair::from_seed(KeyScheme::Sr25519, &[7; 32]).unwrap();
+        assert_ne!(ed.address(), sr.address());
+    }
+}
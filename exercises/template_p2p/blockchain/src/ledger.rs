@@ -0,0 +1,199 @@
+//! Accounts and block rewards.
+//!
+//! Each block may start with a coinbase entry crediting the block reward to its miner. The
+//! reward halves every `halving_interval` blocks, following the chain spec, and balances are
+//! the sum of what each address was credited. Chains can run a UTXO ledger instead of
+//! accounts, see [`crate::utxo`].
+use crate::blocks::Block;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Account an amount is credited to, the SS58 address of its owner's public key, see
+/// [`crate::keys::encode_address`].
+pub type Address = String;
+
+/// Credits the block reward to the miner of the block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Coinbase {
+    pub to: Address,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardSpec {
+    /// Reward of the first block.
+    #[serde(default = "default_initial_reward")]
+    pub initial: u64,
+    /// Number of blocks after which the reward halves, 0 to keep it constant.
+    #[serde(default = "default_halving_interval")]
+    pub halving_interval: u64,
+}
+
+fn default_initial_reward() -> u64 {
+    50
+}
+
+fn default_halving_interval() -> u64 {
+    100
+}
+
+impl Default for RewardSpec {
+    fn default() -> Self {
+        Self {
+            initial: default_initial_reward(),
+            halving_interval: default_halving_interval(),
+        }
+    }
+}
+
+impl RewardSpec {
+    /// Reward of the block with id `id`, the genesis block has none.
+    pub fn reward(&self, id: u64) -> u64 {
+        if id == 0 {
+            return 0;
+        }
+        let halvings = (id - 1).checked_div(self.halving_interval).unwrap_or(0);
+        u32::try_from(halvings)
+            .ok()
+            .and_then(|halvings| self.initial.checked_shr(halvings))
+            .unwrap_or(0)
+    }
+}
+
+/// How coins are tracked, picked by the chain spec.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerMode {
+    /// A balance per address, blocks carry no transactions.
+    #[default]
+    Account,
+    /// Unspent transaction outputs, spent by signed transactions.
+    Utxo,
+}
+
+static REWARDS: OnceCell<RewardSpec> = OnceCell::new();
+static MODE: OnceCell<LedgerMode> = OnceCell::new();
+
+/// Reward schedule of this node, the default one unless [`set_rewards`] was called.
+pub fn rewards() -> &'static RewardSpec {
+    REWARDS.get_or_init(RewardSpec::default)
+}
+
+/// Sets the reward schedule of this node, once, before any block is produced or checked.
+/// Returns `false` if it was already set.
+pub fn set_rewards(spec: RewardSpec) -> bool {
+    REWARDS.set(spec).is_ok()
+}
+
+/// Ledger of this node, accounts unless [`set_mode`] was called.
+pub fn mode() -> LedgerMode {
+    *MODE.get_or_init(LedgerMode::default)
+}
+
+/// Sets the ledger of this node, once, before any block is checked.
+/// Returns `false` if it was already set.
+pub fn set_mode(mode: LedgerMode) -> bool {
+    MODE.set(mode).is_ok()
+}
+
+/// Coinbase paying the reward of block `id` to `to`, if there is a reward left.
+pub fn coinbase(id: u64, to: &str) -> Option<Coinbase> {
+    let amount = rewards().reward(id);
+    (amount > 0).then(|| Coinbase {
+        to: String::from(to),
+        amount,
+    })
+}
+
+/// Balance of every address credited in the blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Balances(BTreeMap<Address, u64>);
+
+impl Balances {
+    pub fn from_blocks<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        blocks
+            .into_iter()
+            .filter_map(|b| b.coinbase.as_ref())
+            .map(|coinbase| (coinbase.to.clone(), coinbase.amount))
+            .collect()
+    }
+
+    pub fn get(&self, address: &str) -> u64 {
+        self.0.get(address).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Address, &u64)> {
+        self.0.iter()
+    }
+
+    /// Sum of every balance, the coins issued so far.
+    pub fn total(&self) -> u64 {
+        self.0.values().sum()
+    }
+}
+
+/// Sums the amounts of each address.
+impl FromIterator<(Address, u64)> for Balances {
+    fn from_iter<I: IntoIterator<Item = (Address, u64)>>(amounts: I) -> Self {
+        let mut balances = Self::default();
+        for (address, amount) in amounts {
+            *balances.0.entry(address).or_default() += amount;
+        }
+        balances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{BlockError, Chain};
+
+    #[test]
+    fn reward_halves() {
+        let spec = RewardSpec {
+            initial: 50,
+            halving_interval: 2,
+        };
+        let rewards: Vec<u64> = (0..=7).map(|id| spec.reward(id)).collect();
+        assert_eq!(rewards, [0, 50, 50, 25, 25, 12, 12, 6]);
+        assert_eq!(spec.reward(1000), 0);
+        let constant = RewardSpec {
+            initial: 10,
+            halving_interval: 0,
+        };
+        assert_eq!(constant.reward(1000), 10);
+    }
+
+    #[test]
+    fn miners_are_credited_the_exact_reward() {
+        let mut chain = Chain::default();
+        let head = chain.head().header.clone();
+        let block = Block::new(&head, String::from("paid"), Some("alice")).unwrap();
+        assert_eq!(block.coinbase, coinbase(1, "alice"));
+        chain.try_add_block(block.clone()).expect("valid reward");
+        chain.add_data(String::from("unpaid")).unwrap();
+        let balances = Balances::from_blocks(&chain.blocks);
+        assert_eq!(balances.get("alice"), rewards().reward(1));
+        assert_eq!(balances.total(), rewards().reward(1));
+
+        let mut greedy = block;
+        greedy.coinbase = Some(Coinbase {
+            to: String::from("alice"),
+            amount: 1_000,
+        });
+        assert_eq!(
+            greedy.validate_reward(),
+            Err(BlockError::InvalidReward {
+                id: 1,
+                amount: 1_000,
+                expected: rewards().reward(1),
+            })
+        );
+        // the coinbase is committed to by the data root
+        assert_eq!(
+            greedy.validate_data(),
+            Err(BlockError::InvalidDataRoot { id: 1 })
+        );
+    }
+}
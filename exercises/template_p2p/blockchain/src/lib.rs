@@ -1,6 +1,32 @@
+pub mod accounts;
 pub mod blocks;
+pub mod command;
+pub mod config;
+pub mod consensus;
+pub mod contracts;
 pub mod encryption;
+pub mod events;
+pub mod explorer;
+pub mod fault;
+pub mod finality;
+pub mod governance;
+pub mod index;
+pub mod inspect;
+pub mod keys;
+pub mod ledger;
+pub mod light;
+pub mod limits;
+pub mod merkle;
+pub mod metrics;
 pub mod p2p;
+pub mod rpc;
+pub mod runtime;
+pub mod shell;
+pub mod sim;
+pub mod snapshot;
+pub mod tui;
 pub mod utils_crypto;
+pub mod utxo;
+pub mod wallet;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
@@ -0,0 +1,197 @@
+//! Light client: follows the best chain by headers only, checking proof-of-work and linkage,
+//! and fetches block data on demand with a Merkle proof against the header's `data_root`.
+use crate::{
+    blocks::{Block, BlockError, Data, Header},
+    merkle::{self, MerkleProof},
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderChain {
+    pub headers: Vec<Header>,
+}
+
+impl Default for HeaderChain {
+    fn default() -> Self {
+        Self {
+            headers: vec![Block::default().header],
+        }
+    }
+}
+
+impl HeaderChain {
+    pub fn head(&self) -> &Header {
+        self.headers.last().expect("there is at least one header")
+    }
+    pub fn get(&self, id: u64) -> Option<&Header> {
+        self.headers.get(usize::try_from(id).ok()?)
+    }
+    pub fn try_add_header(&mut self, header: Header) -> Result<(), BlockError> {
+        header.validate(self.head())?;
+        self.headers.push(header);
+        Ok(())
+    }
+    pub fn is_valid(&self) -> bool {
+        self.headers
+            .iter()
+            .tuple_windows::<(&Header, &Header)>()
+            .all(|(h1, h2)| h2.validate(h1).is_ok())
+    }
+    /// Switches to `remote` if it is valid, starts from the same genesis and is longer.
+    /// Returns whether it did.
+    pub fn choose_chain(&mut self, remote: HeaderChain) -> bool {
+        let better = remote.headers.first() == self.headers.first()
+            && remote.headers.len() > self.headers.len()
+            && remote.is_valid();
+        if better {
+            *self = remote;
+        }
+        better
+    }
+}
+
+/// The data of a block and the proof it belongs to the block, sent to light clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockBody {
+    pub id: u64,
+    pub data: Data,
+    pub proof: MerkleProof,
+}
+
+impl From<&Block> for BlockBody {
+    fn from(block: &Block) -> Self {
+        Self {
+            id: block.id,
+            data: block.data.clone(),
+            proof: block.data_proof(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum BodyError {
+    #[error("no header for block with id: {0}")]
+    UnknownBlock(u64),
+    #[error("body of block with id: {0} doesn't match its header")]
+    InvalidProof(u64),
+}
+
+#[derive(Default)]
+pub struct LightClient {
+    pub headers: HeaderChain,
+    /// Data of the blocks fetched so far, checked against their header.
+    pub bodies: HashMap<u64, Data>,
+}
+
+impl LightClient {
+    /// Checks `body` against the header we hold for it and keeps it. The data is the first
+    /// leaf of the block, so another entry of the block can't be passed off as its data.
+    pub fn add_body(&mut self, body: BlockBody) -> Result<(), BodyError> {
+        let header = self
+            .headers
+            .get(body.id)
+            .ok_or(BodyError::UnknownBlock(body.id))?;
+        let proof = &body.proof;
+        // the first leaf has a sibling at every level of the tree
+        let depth = proof
+            .leaf_count
+            .checked_sub(1)
+            .map(|last| usize::BITS - last.leading_zeros());
+        if proof.index != 0 || depth != u32::try_from(proof.siblings.len()).ok() {
+            return Err(BodyError::InvalidProof(body.id));
+        }
+        let leaf = merkle::leaf_hash(body.data.as_bytes());
+        let root = hex::decode(&header.data_root)
+            .ok()
+            .and_then(|root| merkle::Hash::try_from(root).ok());
+        if !root.is_some_and(|root| proof.verify(&leaf, &root)) {
+            return Err(BodyError::InvalidProof(body.id));
+        }
+        self.bodies.insert(body.id, body.data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(previous: &Header, data: &str) -> Block {
+        Block::new(previous, String::from(data), None).expect("proof-of-work can always seal")
+    }
+
+    #[test]
+    fn follows_headers_and_checks_bodies() {
+        let mut light = LightClient::default();
+        let block = next(light.headers.head(), "hello");
+        light
+            .headers
+            .try_add_header(block.header.clone())
+            .expect("valid header");
+
+        let mut forged = BlockBody::from(&block);
+        forged.data = String::from("goodbye");
+        assert_eq!(light.add_body(forged), Err(BodyError::InvalidProof(1)));
+        assert!(light.bodies.is_empty());
+
+        light.add_body(BlockBody::from(&block)).expect("valid body");
+        assert_eq!(light.bodies[&1], "hello");
+
+        let mut unknown = BlockBody::from(&block);
+        unknown.id = 2;
+        assert_eq!(light.add_body(unknown), Err(BodyError::UnknownBlock(2)));
+    }
+
+    #[test]
+    fn rejects_other_entries_served_as_data() {
+        let mut light = LightClient::default();
+        let block = Block::new(light.headers.head(), String::from("hello"), Some("miner"))
+            .expect("proof-of-work can always seal");
+        let coinbase = block.coinbase.as_ref().expect("the block has a reward");
+        light
+            .headers
+            .try_add_header(block.header.clone())
+            .expect("valid header");
+
+        // the coinbase is a valid leaf of the block, but not its data
+        let leaves = block.leaves();
+        let other = BlockBody {
+            id: 1,
+            data: serde_json::to_string(coinbase).expect("can jsonify coinbase"),
+            proof: merkle::proof(&leaves, 1).expect("second leaf exists"),
+        };
+        let leaf = merkle::leaf_hash(other.data.as_bytes());
+        assert!(other.proof.verify(&leaf, &merkle::root(&leaves)));
+        assert_eq!(light.add_body(other), Err(BodyError::InvalidProof(1)));
+
+        let mut padded = BlockBody::from(&block);
+        padded.proof.leaf_count = 1;
+        assert_eq!(light.add_body(padded), Err(BodyError::InvalidProof(1)));
+        assert!(light.bodies.is_empty());
+    }
+
+    #[test]
+    fn rejects_unlinked_headers_and_shorter_chains() {
+        let mut headers = HeaderChain::default();
+        let first = next(headers.head(), "one");
+        let second = next(&first.header, "two");
+        assert_eq!(
+            headers.try_add_header(second.header.clone()),
+            Err(BlockError::WrongPreviousHash { id: 2 })
+        );
+
+        let remote = HeaderChain {
+            headers: vec![
+                headers.head().clone(),
+                first.header.clone(),
+                second.header.clone(),
+            ],
+        };
+        assert!(headers.choose_chain(remote.clone()));
+        assert_eq!(headers.head(), &second.header);
+        assert!(!headers.choose_chain(HeaderChain::default()));
+        assert_eq!(headers, remote);
+    }
+}
@@ -0,0 +1,238 @@
+//! Connection limits, so a single misbehaving neighbour can't exhaust the node.
+use libp2p::{
+    connection_limits::ConnectionLimits,
+    core::{multiaddr::Protocol, Endpoint},
+    swarm::{
+        dummy, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm, ListenFailure,
+        NetworkBehaviour, PollParameters, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    task::{Context, Poll},
+};
+
+/// Limits on the swarm connections, `None` means unlimited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_inbound_peers: Option<u32>,
+    pub max_outbound_peers: Option<u32>,
+    pub max_connections_per_ip: Option<u32>,
+    pub max_pending_dials: Option<u32>,
+    pub max_pending_incoming: Option<u32>,
+    /// Inbound streams being negotiated at once on a single connection.
+    pub max_negotiating_inbound_streams: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_inbound_peers: Some(50),
+            max_outbound_peers: Some(50),
+            max_connections_per_ip: Some(8),
+            max_pending_dials: Some(16),
+            max_pending_incoming: Some(16),
+            max_negotiating_inbound_streams: 32,
+        }
+    }
+}
+
+impl Limits {
+    pub fn connection_limits(&self) -> ConnectionLimits {
+        ConnectionLimits::default()
+            .with_max_established_incoming(self.max_inbound_peers)
+            .with_max_established_outgoing(self.max_outbound_peers)
+            .with_max_pending_outgoing(self.max_pending_dials)
+            .with_max_pending_incoming(self.max_pending_incoming)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("too many connections from {ip}, the limit is {limit}")]
+pub struct IpLimitExceeded {
+    pub ip: IpAddr,
+    pub limit: u32,
+}
+
+/// Denies connections to or from an IP address that already has `limit` connections,
+/// established or still negotiating inbound ones. Addresses without an IP, such as `/memory`,
+/// are not limited.
+pub struct IpLimits {
+    limit: Option<u32>,
+    established: HashMap<ConnectionId, IpAddr>,
+    /// Inbound connections accepted but not established yet.
+    pending: HashMap<ConnectionId, IpAddr>,
+}
+
+impl IpLimits {
+    pub fn new(limit: Option<u32>) -> Self {
+        Self {
+            limit,
+            established: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    fn check(&self, addr: &Multiaddr) -> Result<Option<IpAddr>, ConnectionDenied> {
+        let Some(ip) = ip_of(addr) else {
+            return Ok(None);
+        };
+        if let Some(limit) = self.limit {
+            let connections = self.established.values().chain(self.pending.values());
+            let count = connections.filter(|i| **i == ip).count();
+            if count >= limit as usize {
+                return Err(ConnectionDenied::new(IpLimitExceeded { ip, limit }));
+            }
+        }
+        Ok(Some(ip))
+    }
+
+    fn establish(
+        &mut self,
+        connection_id: ConnectionId,
+        addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        if let Some(ip) = self.check(addr)? {
+            self.established.insert(connection_id, ip);
+        }
+        Ok(dummy::ConnectionHandler)
+    }
+}
+
+fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+impl NetworkBehaviour for IpLimits {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = void::Void;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        // refuse early, before spending a handshake on the connection
+        if let Some(ip) = self.check(remote_addr)? {
+            self.pending.insert(connection_id, ip);
+        }
+        Ok(())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.pending.remove(&connection_id);
+        self.establish(connection_id, remote_addr)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _: PeerId,
+        addr: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.establish(connection_id, addr)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. }) => {
+                self.established.remove(&connection_id);
+            }
+            // the handshake failed, or a behaviour denied the connection
+            FromSwarm::ListenFailure(ListenFailure { connection_id, .. }) => {
+                self.pending.remove(&connection_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_connections_per_ip() {
+        let mut limits = IpLimits::new(Some(2));
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        let other: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+        let (a, b, c) = (
+            ConnectionId::new_unchecked(1),
+            ConnectionId::new_unchecked(2),
+            ConnectionId::new_unchecked(3),
+        );
+        assert!(limits.establish(a, &addr).is_ok());
+        assert!(limits.establish(b, &addr).is_ok());
+        assert!(limits.establish(c, &addr).is_err());
+        assert!(limits.establish(c, &other).is_ok());
+
+        limits.established.remove(&a);
+        assert!(limits.check(&addr).is_ok());
+    }
+
+    #[test]
+    fn counts_pending_inbound_connections() {
+        let mut limits = IpLimits::new(Some(2));
+        let local: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/5001".parse().unwrap();
+        let ids: Vec<_> = (1..=3).map(ConnectionId::new_unchecked).collect();
+        let mut pending = |id| limits.handle_pending_inbound_connection(id, &local, &addr);
+        assert!(pending(ids[0]).is_ok());
+        assert!(pending(ids[1]).is_ok());
+        assert!(pending(ids[2]).is_err());
+
+        // established connections keep their place, pending ones that fail free theirs
+        let peer = PeerId::random();
+        assert!(limits
+            .handle_established_inbound_connection(ids[0], peer, &local, &addr)
+            .is_ok());
+        limits.pending.remove(&ids[1]);
+        assert!(limits
+            .handle_pending_inbound_connection(ids[2], &local, &addr)
+            .is_ok());
+        assert_eq!((limits.established.len(), limits.pending.len()), (1, 1));
+    }
+
+    #[test]
+    fn memory_addresses_are_not_limited() {
+        let mut limits = IpLimits::new(Some(0));
+        let addr: Multiaddr = "/memory/1234".parse().unwrap();
+        assert!(limits
+            .establish(ConnectionId::new_unchecked(1), &addr)
+            .is_ok());
+        assert!(limits.established.is_empty());
+    }
+}
@@ -1,23 +1,251 @@
 use blockchain::{
-    blocks::{Block, Chain},
-    p2p::{self, ChainApp, ChainResponse, EventType, LocalChainRequest},
+    accounts::Accounts,
+    blocks::Block,
+    config::NodeConfig,
+    consensus::{self, ChainSpec},
+    events::EventSink,
+    explorer,
+    fault::{FaultInjector, FaultProfile},
+    finality::Finality,
+    governance,
+    ledger::{self, LedgerMode},
+    light::LightClient,
+    p2p::{self, AppTransport, ChainApp, ChainResponse, EventType, TransportConfig, TransportKind},
+    rpc::{self, RpcRequest},
+    runtime::{self, RuntimeSpec},
+    shell::Shell,
+    tui::Dashboard,
+    utxo::UtxoSet,
+    wallet::{self, Wallet},
     Result,
 };
-use libp2p::{futures::StreamExt, gossipsub, mdns, swarm::SwarmEvent};
-use std::time::Duration;
-use tokio::{io::AsyncBufReadExt, sync::mpsc, time};
+use clap::Parser;
+use libp2p::{futures::StreamExt, Multiaddr};
+use std::{path::PathBuf, time::Duration};
+use tokio::{
+    sync::{broadcast, mpsc},
+    time,
+};
+
+#[derive(Parser)]
+#[command(about = "Minimal libp2p blockchain node")]
+struct Cli {
+    /// Don't read commands from stdin, useful when the node is driven through the RPC API
+    #[arg(long, conflicts_with = "tui")]
+    headless: bool,
+    /// Show an interactive dashboard instead of plain log lines
+    #[arg(long)]
+    tui: bool,
+    /// Serve the JSON-RPC API over HTTP and WebSocket on this localhost port
+    #[arg(long)]
+    rpc_port: Option<u16>,
+    /// Serve Prometheus metrics on http://127.0.0.1:<port>/metrics
+    #[arg(long)]
+    metrics_port: Option<u16>,
+    /// Serve a read-only block explorer on http://127.0.0.1:<port>/
+    #[arg(long)]
+    explorer_port: Option<u16>,
+    /// Transports to enable, tried in this order when dialing
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "tcp")]
+    transport: Vec<TransportKind>,
+    /// Address to listen on, can be repeated [default: any port on every interface, for each transport]
+    #[arg(long)]
+    listen: Vec<Multiaddr>,
+    /// Connection limits and gossipsub cache caps, as a TOML file
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Append one JSON object per chain event (block imported, reorg, ...) to this file
+    #[arg(long, conflicts_with = "event_socket")]
+    event_log: Option<PathBuf>,
+    /// Write the chain events as JSON lines to the Unix socket listening at this path
+    #[arg(long)]
+    event_socket: Option<PathBuf>,
+    /// Drop, delay, duplicate or reorder the messages of the node as described in this JSON file
+    #[arg(long)]
+    fault_profile: Option<PathBuf>,
+    /// Start from the chain exported to this file instead of the genesis block
+    #[arg(long, conflicts_with = "light")]
+    chain: Option<PathBuf>,
+    /// Consensus engine and validators of the network, as a JSON chain spec [default: proof-of-work]
+    #[arg(long)]
+    chain_spec: Option<PathBuf>,
+    /// Hex encoded ed25519 secret key this node signs blocks with, for proof-of-authority
+    #[arg(long, requires = "chain_spec")]
+    authority_key: Option<PathBuf>,
+    /// Keep the data of the last <N> blocks only, and the headers of the older ones
+    #[arg(long, value_name = "N", conflicts_with = "light")]
+    prune: Option<usize>,
+    /// Follow the chain by headers only, fetching block data on demand with `fetch`
+    #[arg(long)]
+    light: bool,
+    /// Address the rewards of mined blocks go to [default: the public key of the node, or alice
+    /// with --dev]
+    #[arg(long)]
+    reward_address: Option<String>,
+    /// Open the wallet file for the `wallet` commands, created if missing. The passphrase is read
+    /// from BLOCKCHAIN_WALLET_PASSPHRASE
+    #[arg(long)]
+    wallet: Option<PathBuf>,
+    /// Derive the node identity from this secret URI, a BIP39 phrase and `//hard` junctions
+    /// like `<phrase>//node//1`, or `//Node1` for one from the dev phrase [default: random]
+    #[arg(long, value_name = "URI")]
+    node_key: Option<String>,
+    /// Use an in-memory wallet with the well-known dev accounts, alice to ferdie
+    #[arg(long, conflicts_with = "wallet")]
+    dev: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    pretty_env_logger::init();
+    let cli = Cli::parse();
+    let (input_sender, mut input_rcv) = mpsc::unbounded_channel::<String>();
+    let (dashboard, shell) = if cli.tui {
+        (Some(Dashboard::start(input_sender)?), None)
+    } else {
+        pretty_env_logger::init();
+        match cli.headless {
+            true => (None, None),
+            false => (None, Some(Shell::start(input_sender)?)),
+        }
+    };
+    if let Some(uri) = &cli.node_key {
+        if !p2p::set_node_key(uri)? {
+            return Err("the node identity was already in use, --node-key can't set it".into());
+        }
+    }
     log::info!("Peer Id: {}", p2p::PEER_ID.clone());
     let (response_sender, mut response_rcv) = mpsc::unbounded_channel::<ChainResponse>();
     let (init_sender, mut init_rcv) = mpsc::unbounded_channel::<bool>();
+    let (mined_sender, mut mined_rcv) = mpsc::unbounded_channel::<p2p::Mined>();
+    let (rpc_sender, mut rpc_rcv) = mpsc::unbounded_channel::<RpcRequest>();
+    let (new_heads, _) = broadcast::channel::<Block>(16);
 
-    let mut chain_app = ChainApp::new(init_sender.clone(), response_sender);
-    chain_app.swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+    let mut finality = None;
+    let mut runtime_spec = RuntimeSpec::default();
+    if let Some(path) = &cli.chain_spec {
+        let spec = ChainSpec::from_file(path)?;
+        let author = match &cli.authority_key {
+            Some(path) => Some(consensus::read_authority_key(path)?),
+            None => None,
+        };
+        if let Some(author) = &author {
+            log::info!("authority key: {}", hex::encode(author.public().to_bytes()));
+        }
+        if let Some(finality_spec) = &spec.finality {
+            finality = Some(Finality::new(finality_spec, author.clone())?);
+        }
+        consensus::set_engine(spec.engine(author)?)?;
+        ledger::set_rewards(spec.rewards.clone());
+        ledger::set_mode(spec.ledger);
+        runtime_spec = spec.runtime.clone();
+        governance::set_spec(spec.governance);
+        log::info!("running chain {}", spec.name);
+    }
+    if cli.dev && runtime_spec.sudo.is_none() {
+        // alice, like the dev chain of Substrate
+        runtime_spec.sudo = Some(Wallet::dev().account(None)?.address.clone());
+    }
+    if let Some(sudo) = &runtime_spec.sudo {
+        log::info!("runtime upgrades can be set on chain by {}", sudo);
+    }
+    runtime::set_spec(runtime_spec);
+    let node_config = match &cli.config {
+        Some(path) => NodeConfig::from_file(path)?,
+        None => NodeConfig::default(),
+    };
+    let config = TransportConfig {
+        transports: cli.transport,
+    };
+    let transport = AppTransport::new(&p2p::KEYS, &config)?;
+    let mut chain_app = ChainApp::with_transport(
+        p2p::KEYS.to_owned(),
+        transport,
+        true,
+        &node_config,
+        init_sender.clone(),
+        response_sender,
+        mined_sender,
+    );
+    let listen = match cli.listen.is_empty() {
+        true => config.default_listen_addrs(),
+        false => cli.listen,
+    };
+    for addr in listen {
+        chain_app.swarm.listen_on(addr)?;
+    }
+    if let Some(keep) = cli.prune {
+        log::info!("pruning all but the last {} blocks", keep);
+        chain_app.pruning = Some(keep);
+    }
+    chain_app.finality = finality;
+    match ledger::mode() {
+        LedgerMode::Utxo => {
+            log::info!("running the UTXO ledger");
+            chain_app.utxo = Some(UtxoSet::default());
+        }
+        LedgerMode::Account => chain_app.accounts = Some(Accounts::default()),
+    }
+    if let Some(path) = &cli.chain {
+        chain_app.chain = p2p::read_chain(path, &chain_app)?;
+        log::info!(
+            "loaded {} blocks from {}",
+            chain_app.chain.blocks.len(),
+            path.display()
+        );
+    }
+    if let Some(path) = &cli.wallet {
+        let passphrase = std::env::var(wallet::PASSPHRASE_VAR)
+            .map_err(|_| format!("set {} to open the wallet", wallet::PASSPHRASE_VAR))?;
+        let wallet = Wallet::open(path, &passphrase)?;
+        log::info!(
+            "opened wallet {} with {} accounts",
+            path.display(),
+            wallet.accounts().count()
+        );
+        chain_app.wallet = Some(wallet);
+    } else if cli.dev {
+        let wallet = Wallet::dev();
+        log::warn!("dev mode, the wallet has the well-known dev accounts");
+        chain_app.reward_address = Some(wallet.account(None)?.address.clone());
+        chain_app.wallet = Some(wallet);
+    }
+    if let Some(address) = cli.reward_address {
+        chain_app.reward_address = Some(address);
+    }
+    if let Some(address) = &chain_app.reward_address {
+        log::info!("mining rewards go to {}", address);
+    }
+    if cli.light {
+        log::info!("running as a light client");
+        chain_app.light = Some(LightClient::default());
+    }
+    if let Some(path) = &cli.event_log {
+        chain_app.events = Some(EventSink::file(path)?);
+    } else if let Some(path) = &cli.event_socket {
+        chain_app.events = Some(EventSink::unix_socket(path)?);
+    }
+    if let Some(path) = &cli.fault_profile {
+        log::warn!("injecting faults from {}", path.display());
+        chain_app.faults = Some(FaultInjector::new(FaultProfile::from_file(path)?));
+    }
+
+    if let Some(port) = cli.metrics_port {
+        let addr = chain_app.metrics.serve(port)?;
+        log::info!("metrics served on http://{}/metrics", addr);
+    }
+    if let Some(port) = cli.explorer_port {
+        let addr = explorer::serve(port, rpc_sender.clone())?;
+        log::info!("block explorer served on http://{}/", addr);
+    }
 
-    let mut stdin = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    let _rpc_handle = match cli.rpc_port {
+        Some(port) => {
+            let (addr, handle) = rpc::start_server(port, rpc_sender, new_heads.clone()).await?;
+            log::info!("JSON-RPC server listening on {}", addr);
+            Some(handle)
+        }
+        None => None,
+    };
 
     tokio::spawn(async move {
         time::sleep(Duration::from_secs(1)).await;
@@ -26,114 +254,36 @@ async fn main() -> Result<()> {
     });
 
     loop {
+        let head = chain_app.chain.head().hash.clone();
+        let faults_due = chain_app.faults.as_ref().and_then(|f| f.next_due());
+        let faults_due_at = faults_due.map_or_else(time::Instant::now, time::Instant::from_std);
         let evt = tokio::select! {
-            line = stdin.next_line() => Some(EventType::Input(line?.expect("can read line from stdin"))),
+            line = input_rcv.recv(), if !cli.headless => match line {
+                Some(line) => Some(EventType::Input(line)),
+                None => {
+                    log::info!("input closed, shutting down");
+                    return Ok(());
+                }
+            },
+            Some(request) = rpc_rcv.recv() => Some(EventType::Rpc(request)),
+            mined = mined_rcv.recv() => Some(EventType::Mined(mined.expect("mined block exists"))),
             response = response_rcv.recv() => Some(EventType::Response(response.expect("response exists"))),
             _init = init_rcv.recv() => Some(EventType::Init),
-            event = chain_app.swarm.select_next_some() => match event {
-                SwarmEvent::Behaviour(event) => Some(event),
-                _ => {
-                    // log::info!("unhandled swarm event: {:?}", event);
-                    None
-                }
-            }
-
+            _ = time::sleep_until(faults_due_at), if faults_due.is_some() => Some(EventType::FaultsDue),
+            event = chain_app.swarm.select_next_some() => p2p::handle_swarm_event(event, &mut chain_app),
         };
         if let Some(event) = evt {
-            match event {
-                EventType::Init => {
-                    let peers = p2p::get_list_peers(&chain_app.swarm);
-                    log::info!("connected nodes: {}", peers.len());
-                    if !peers.is_empty() {
-                        let req = p2p::LocalChainRequest {
-                            from_peer_id: peers.iter().last().unwrap().to_string(),
-                        };
-
-                        let json = serde_json::to_string(&req)?;
-                        chain_app
-                            .swarm
-                            .behaviour_mut()
-                            .gossipsub
-                            .publish(p2p::CHAIN_TOPIC.clone(), json.as_bytes())?;
-                    }
-                }
-                EventType::Response(resp) => {
-                    let json = serde_json::to_string(&resp)?;
-                    chain_app
-                        .swarm
-                        .behaviour_mut()
-                        .gossipsub
-                        .publish(p2p::CHAIN_TOPIC.clone(), json.as_bytes())?;
-                }
-                EventType::Input(line) => match line.as_str() {
-                    "ls p" => p2p::handle_print_peers(&chain_app.swarm),
-                    cmd if cmd.starts_with("ls c") => p2p::handle_print_chain(&chain_app.chain),
-                    cmd if cmd.starts_with("create b") => {
-                        p2p::handle_create_block(cmd, &mut chain_app)
-                    }
-                    _ => log::error!("unknown command"),
-                },
-                EventType::Gossipsub(boxed_event) => {
-                    let gossipsub::Event::Message {
-                        propagation_source,
-                        message_id: _id,
-                        message: msg
-                    } = *boxed_event else {
-                        continue;
-                    };
-                    let peer_id = msg.source.unwrap_or(propagation_source);
-                    if let Ok(resp) = serde_json::from_slice::<ChainResponse>(&msg.data) {
-                        if resp.receiver == p2p::PEER_ID.to_string() {
-                            log::info!("Response from: {}", peer_id);
-                            resp.blocks.iter().for_each(|r| log::info!("{:#?}", r));
-                            chain_app.chain.choose_chain(&Chain {
-                                blocks: resp.blocks,
-                            });
-                        }
-                    } else if let Ok(resp) = serde_json::from_slice::<LocalChainRequest>(&msg.data)
-                    {
-                        log::info!("sending local chain to {}", peer_id.to_string());
-                        let peer_id = resp.from_peer_id;
-                        if p2p::PEER_ID.to_string() == peer_id {
-                            if let Err(e) = chain_app.response_sender.send(ChainResponse {
-                                blocks: chain_app.chain.blocks.clone(),
-                                receiver: peer_id.to_string(),
-                            }) {
-                                log::error!("error sending response via channel: {}", e);
-                            }
-                        }
-                    } else if let Ok(block) = serde_json::from_slice::<Block>(&msg.data) {
-                        log::info!("received new block from {}", peer_id.to_string());
-                        chain_app.chain.try_add_block(block);
-                    } else {
-                        log::error!(
-                            "couldn't deserialize msg: {:?} from: {}",
-                            std::str::from_utf8(&msg.data),
-                            peer_id.to_string()
-                        );
-                    }
-                }
-                EventType::Mdns(mdns::Event::Discovered(discovered_list)) => {
-                    for (peer, _addr) in discovered_list {
-                        chain_app
-                            .swarm
-                            .behaviour_mut()
-                            .gossipsub
-                            .add_explicit_peer(&peer);
-                    }
-                }
-                EventType::Mdns(mdns::Event::Expired(expired_list)) => {
-                    for (peer, _addr) in expired_list {
-                        if !chain_app.swarm.behaviour().mdns.has_node(&peer) {
-                            chain_app
-                                .swarm
-                                .behaviour_mut()
-                                .gossipsub
-                                .remove_explicit_peer(&peer);
-                        }
-                    }
-                }
-            }
+            p2p::handle_event(event, &mut chain_app);
+        }
+        if chain_app.chain.head().hash != head {
+            // nobody listening is fine, the RPC server may be disabled
+            let _ = new_heads.send(chain_app.chain.head().clone());
+        }
+        if let Some(dashboard) = &dashboard {
+            dashboard.update(&chain_app);
+        }
+        if let Some(shell) = &shell {
+            shell.update(&chain_app);
         }
     }
 }
@@ -0,0 +1,153 @@
+//! Binary Merkle tree over sha256, so a single entry of a block can be checked against the
+//! `data_root` of its header without the rest of the block.
+//!
+//! Leaves and inner nodes are hashed with different prefixes, so an inner node can't be passed
+//! off as a leaf. A node without a sibling is promoted to the next level as is.
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([0u8])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1u8])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+/// Root of the tree over `leaves`, the hash of nothing for an empty tree.
+pub fn root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return Sha256::digest([]).into();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Proof that the leaf at `index` is part of a tree of `leaf_count` leaves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    /// Hex encoded sibling hashes, from the leaves up, skipping the levels without one.
+    pub siblings: Vec<String>,
+}
+
+pub fn proof(leaves: &[Hash], index: usize) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut siblings = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut i = index;
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(i ^ 1) {
+            siblings.push(hex::encode(sibling));
+        }
+        level = next_level(&level);
+        i /= 2;
+    }
+    Some(MerkleProof {
+        index,
+        leaf_count: leaves.len(),
+        siblings,
+    })
+}
+
+impl MerkleProof {
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+        let mut siblings = self.siblings.iter();
+        let (mut hash, mut i, mut len) = (*leaf, self.index, self.leaf_count);
+        while len > 1 {
+            let has_sibling = (i ^ 1) < len;
+            if has_sibling {
+                let Some(sibling) = siblings.next().and_then(|s| decode(s)) else {
+                    return false;
+                };
+                hash = match i % 2 {
+                    0 => node_hash(&hash, &sibling),
+                    _ => node_hash(&sibling, &hash),
+                };
+            }
+            i /= 2;
+            len = len.div_ceil(2);
+        }
+        siblings.next().is_none() && &hash == root
+    }
+}
+
+fn decode(hash: &str) -> Option<Hash> {
+    hex::decode(hash).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| leaf_hash(&i.to_be_bytes())).collect()
+    }
+
+    #[test]
+    fn proves_every_leaf() {
+        for n in 1..=9 {
+            let leaves = leaves(n);
+            let root = root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = proof(&leaves, i).expect("leaf exists");
+                assert!(proof.verify(leaf, &root), "leaf {} of {}", i, n);
+            }
+            assert_eq!(proof(&leaves, n), None);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_leaf_or_proof() {
+        let leaves = leaves(5);
+        let root = root(&leaves);
+        let proof = proof(&leaves, 2).expect("leaf exists");
+        assert!(!proof.verify(&leaves[3], &root));
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!moved.verify(&leaves[2], &root));
+
+        let mut truncated = proof;
+        truncated.siblings.pop();
+        assert!(!truncated.verify(&leaves[2], &root));
+    }
+
+    #[test]
+    fn single_leaf_is_its_own_root() {
+        let leaves = leaves(1);
+        assert_eq!(root(&leaves), leaves[0]);
+        assert!(proof(&leaves, 0).unwrap().siblings.is_empty());
+    }
+}
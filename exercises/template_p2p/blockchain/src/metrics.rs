@@ -0,0 +1,207 @@
+//! Node health metrics in the Prometheus text format.
+//!
+//! Swarm and gossipsub metrics (connections, mesh sizes, messages per topic) come from
+//! libp2p's own hooks, registered next to the chain metrics in the same registry.
+use crate::{blocks::Chain, Result};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use libp2p::metrics::Recorder;
+use prometheus_client::{
+    encoding::{text, EncodeLabelSet},
+    metrics::{counter::Counter, family::Family, gauge::Gauge},
+    registry::Registry,
+};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{atomic::AtomicU64, Arc, Mutex},
+};
+
+const CONTENT_TYPE_OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct TipLabels {
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ReasonLabels {
+    pub reason: String,
+}
+
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+    libp2p: libp2p::metrics::Metrics,
+    pub chain_height: Gauge,
+    pub finalized_height: Gauge,
+    pub best_tip: Family<TipLabels, Gauge>,
+    pub blocks_received: Counter,
+    pub blocks_rejected: Family<ReasonLabels, Counter>,
+    pub hash_rate: Gauge<f64, AtomicU64>,
+    pub peers: Gauge,
+}
+
+impl Metrics {
+    /// Registers the chain metrics in `registry`, which may already hold the gossipsub ones.
+    pub fn new(mut registry: Registry) -> Self {
+        let libp2p = libp2p::metrics::Metrics::new(&mut registry);
+        let chain_height = Gauge::default();
+        let finalized_height = Gauge::default();
+        let best_tip = Family::default();
+        let blocks_received = Counter::default();
+        let blocks_rejected = Family::default();
+        let hash_rate = Gauge::default();
+        let peers = Gauge::default();
+
+        let chain = registry.sub_registry_with_prefix("chain");
+        chain.register("height", "Id of the best block", chain_height.clone());
+        chain.register(
+            "finalized_height",
+            "Id of the latest finalized block",
+            finalized_height.clone(),
+        );
+        chain.register(
+            "best_tip",
+            "Hash of the best block, the value is always 1",
+            best_tip.clone(),
+        );
+        chain.register(
+            "blocks_received",
+            "Blocks received from peers",
+            blocks_received.clone(),
+        );
+        chain.register(
+            "blocks_rejected",
+            "Blocks received from peers that could not be added, by reason",
+            blocks_rejected.clone(),
+        );
+        chain.register(
+            "mining_hash_rate",
+            "Hashes per second while mining the last block",
+            hash_rate.clone(),
+        );
+        chain.register("peers", "Peers connected or discovered", peers.clone());
+
+        Self {
+            registry: Arc::new(Mutex::new(registry)),
+            libp2p,
+            chain_height,
+            finalized_height,
+            best_tip,
+            blocks_received,
+            blocks_rejected,
+            hash_rate,
+            peers,
+        }
+    }
+
+    /// Records a swarm or behaviour event through the libp2p metrics.
+    pub fn record<E>(&self, event: &E)
+    where
+        libp2p::metrics::Metrics: Recorder<E>,
+    {
+        self.libp2p.record(event);
+    }
+
+    /// Refreshes the gauges following the state of the node.
+    pub fn observe(&self, chain: &Chain, peers: usize) {
+        let head = chain.head();
+        let tip = TipLabels {
+            hash: head.hash.clone(),
+        };
+        if self.best_tip.get_or_create(&tip).get() == 0 {
+            // only the current tip is reported
+            self.best_tip.clear();
+            self.best_tip.get_or_create(&tip).set(1);
+        }
+        self.chain_height.set(head.id as i64);
+        self.finalized_height
+            .set(chain.finalized.as_ref().map_or(0, |f| f.id as i64));
+        self.peers.set(peers as i64);
+    }
+
+    pub fn reject_block(&self, reason: &str) {
+        self.blocks_rejected
+            .get_or_create(&ReasonLabels {
+                reason: String::from(reason),
+            })
+            .inc();
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        encode(&self.registry)
+    }
+
+    /// Serves the metrics on `http://127.0.0.1:<port>/metrics`, returns the bound address.
+    pub fn serve(&self, port: u16) -> Result<SocketAddr> {
+        let registry = self.registry.clone();
+        let make_service = make_service_fn(move |_| {
+            let registry = registry.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let registry = registry.clone();
+                    async move { Ok::<_, Infallible>(respond(request, &registry)) }
+                }))
+            }
+        });
+        let server =
+            Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], port)))?.serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("metrics server stopped: {}", e);
+            }
+        });
+        Ok(addr)
+    }
+}
+
+fn respond(request: Request<Body>, registry: &Mutex<Registry>) -> Response<Body> {
+    if request.uri().path() != "/metrics" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("try /metrics"))
+            .expect("valid response");
+    }
+    Response::builder()
+        .header(CONTENT_TYPE, CONTENT_TYPE_OPENMETRICS)
+        .body(Body::from(encode(registry)))
+        .expect("valid response")
+}
+
+fn encode(registry: &Mutex<Registry>) -> String {
+    let mut buffer = String::new();
+    let registry = registry.lock().expect("metrics registry lock");
+    text::encode(&mut buffer, &registry).expect("can encode metrics");
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_the_current_tip() {
+        let metrics = Metrics::new(Registry::default());
+        let mut chain = Chain::default();
+        metrics.observe(&chain, 2);
+        let genesis = chain.head().hash.clone();
+        chain.add_data(String::from("next")).unwrap();
+        metrics.observe(&chain, 3);
+        metrics.reject_block("not_next");
+
+        let text = metrics.encode();
+        assert!(text.contains("chain_height 1\n"));
+        assert!(text.contains("chain_peers 3\n"));
+        assert!(text.contains(&format!(
+            "chain_best_tip{{hash=\"{}\"}} 1",
+            chain.head().hash
+        )));
+        assert!(!text.contains(&genesis));
+        assert!(text.contains("chain_blocks_rejected_total{reason=\"not_next\"} 1"));
+    }
+}
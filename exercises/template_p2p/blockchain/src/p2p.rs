@@ -1,36 +1,209 @@
-use crate::blocks::{Block, Chain};
+use crate::{
+    accounts::{AccountError, Accounts, Call, Extrinsic, ExtrinsicPool},
+    blocks::{Block, BlockError, Chain, Header},
+    command,
+    config::NodeConfig,
+    consensus::{self, ConsensusError},
+    contracts::{self, Execution},
+    events::{ChainEvent, EventSink},
+    fault::{Delivery, FaultInjector},
+    finality::{Finality, Vote},
+    index::ChainIndex,
+    keys::{self, KeyError, KeyScheme},
+    ledger::{Address, Balances},
+    light::{BlockBody, HeaderChain, LightClient},
+    limits::IpLimits,
+    metrics::Metrics,
+    rpc::{self, RpcRequest, SubmitError},
+    runtime::{self, RuntimeParams},
+    snapshot,
+    utxo::{Transaction, TxPool, UtxoError, UtxoSet},
+    wallet::{self, Wallet, WalletError},
+};
+use chrono::Utc;
+use itertools::Itertools;
 use libp2p::{
+    connection_limits,
     core::{
         muxing::StreamMuxerBox,
-        transport::{self, Boxed},
+        transport::{self, Boxed, MemoryTransport},
     },
     gossipsub::{self, IdentTopic},
     identity, mdns, noise,
-    swarm::{NetworkBehaviour, Swarm, SwarmBuilder},
-    tcp, yamux, PeerId, Transport,
+    swarm::{
+        behaviour::toggle::Toggle, NetworkBehaviour, Swarm, SwarmBuilder, SwarmEvent, THandlerErr,
+    },
+    tcp, yamux, Multiaddr, PeerId, Transport,
 };
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
+use prometheus_client::registry::Registry;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::time::Duration;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::time::{Duration, Instant};
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
 };
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 
-pub static KEYS: Lazy<identity::Keypair> = Lazy::new(identity::Keypair::generate_ed25519);
+static NODE_KEY: OnceCell<identity::Keypair> = OnceCell::new();
+/// Identity of this node, random unless [`set_node_key`] was called first.
+pub static KEYS: Lazy<identity::Keypair> = Lazy::new(|| {
+    NODE_KEY
+        .get()
+        .cloned()
+        .unwrap_or_else(identity::Keypair::generate_ed25519)
+});
 pub static PEER_ID: Lazy<PeerId> = Lazy::new(|| PeerId::from(KEYS.public()));
 pub static CHAIN_TOPIC: Lazy<IdentTopic> = Lazy::new(|| IdentTopic::new("chains"));
 pub static BLOCK_TOPIC: Lazy<IdentTopic> = Lazy::new(|| IdentTopic::new("blocks"));
+pub static FINALITY_TOPIC: Lazy<IdentTopic> = Lazy::new(|| IdentTopic::new("finality"));
+pub static TX_TOPIC: Lazy<IdentTopic> = Lazy::new(|| IdentTopic::new("transactions"));
+
+/// Events kept for the slowest receiver of [`ChainApp::subscribe`].
+pub const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// Transports a node can listen and dial on. QUIC and WebSocket need the `quic` and
+/// `websocket` cargo features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Tcp,
+    Quic,
+    Websocket,
+}
+
+impl TransportKind {
+    /// Address listening on every interface with a port picked by the OS.
+    pub fn default_listen_addr(&self) -> Multiaddr {
+        let addr = match self {
+            TransportKind::Tcp => "/ip4/0.0.0.0/tcp/0",
+            TransportKind::Quic => "/ip4/0.0.0.0/udp/0/quic-v1",
+            TransportKind::Websocket => "/ip4/0.0.0.0/tcp/0/ws",
+        };
+        addr.parse().expect("valid multiaddr")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error("no transport enabled")]
+    Empty,
+    #[error("the {0:?} transport needs the `{1}` cargo feature")]
+    Disabled(TransportKind, &'static str),
+    #[error("can't build the {0:?} transport: {1}")]
+    Build(TransportKind, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportConfig {
+    pub transports: Vec<TransportKind>,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            transports: vec![TransportKind::Tcp],
+        }
+    }
+}
+
+impl TransportConfig {
+    /// One listen address per enabled transport.
+    pub fn default_listen_addrs(&self) -> Vec<Multiaddr> {
+        self.transports
+            .iter()
+            .map(TransportKind::default_listen_addr)
+            .collect()
+    }
+}
 
 pub struct AppTransport(Boxed<(PeerId, StreamMuxerBox)>);
 
 impl Default for AppTransport {
     fn default() -> Self {
+        AppTransport::tcp(&KEYS)
+    }
+}
+
+impl AppTransport {
+    /// Every transport enabled in `config`, tried in order when dialing.
+    pub fn new(keys: &identity::Keypair, config: &TransportConfig) -> Result<Self, TransportError> {
+        let mut transports = config
+            .transports
+            .iter()
+            .map(|kind| Self::build(keys, *kind));
+        let first = transports.next().ok_or(TransportError::Empty)??;
+        transports.try_fold(first, |AppTransport(a), b| {
+            let AppTransport(b) = b?;
+            let transport = a
+                .or_transport(b)
+                .map(|either, _| either.into_inner())
+                .boxed();
+            Ok(AppTransport(transport))
+        })
+    }
+
+    fn build(keys: &identity::Keypair, kind: TransportKind) -> Result<Self, TransportError> {
+        match kind {
+            TransportKind::Tcp => Ok(Self::tcp(keys)),
+            TransportKind::Quic => Self::quic(keys),
+            TransportKind::Websocket => Self::websocket(keys),
+        }
+    }
+
+    pub fn tcp(keys: &identity::Keypair) -> Self {
         let transport = tcp::tokio::Transport::default()
             .upgrade(transport::upgrade::Version::V1)
-            .authenticate(noise::Config::new(&KEYS).unwrap())
+            .authenticate(noise::Config::new(keys).unwrap())
+            .multiplex(yamux::Config::default())
+            .boxed();
+        AppTransport(transport)
+    }
+
+    /// QUIC brings its own encryption and multiplexing, so there is no upgrade to apply.
+    #[cfg(feature = "quic")]
+    pub fn quic(keys: &identity::Keypair) -> Result<Self, TransportError> {
+        let transport = libp2p_quic::tokio::Transport::new(libp2p_quic::Config::new(keys))
+            .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+            .boxed();
+        Ok(AppTransport(transport))
+    }
+
+    #[cfg(not(feature = "quic"))]
+    pub fn quic(_keys: &identity::Keypair) -> Result<Self, TransportError> {
+        Err(TransportError::Disabled(TransportKind::Quic, "quic"))
+    }
+
+    /// WebSocket over TCP, resolving `/dns` addresses with the system resolver.
+    #[cfg(feature = "websocket")]
+    pub fn websocket(keys: &identity::Keypair) -> Result<Self, TransportError> {
+        let tcp = tcp::tokio::Transport::default();
+        let dns = libp2p::dns::TokioDnsConfig::system(tcp)
+            .map_err(|e| TransportError::Build(TransportKind::Websocket, e.to_string()))?;
+        let transport = libp2p::websocket::WsConfig::new(dns)
+            .upgrade(transport::upgrade::Version::V1)
+            .authenticate(noise::Config::new(keys).unwrap())
+            .multiplex(yamux::Config::default())
+            .boxed();
+        Ok(AppTransport(transport))
+    }
+
+    #[cfg(not(feature = "websocket"))]
+    pub fn websocket(_keys: &identity::Keypair) -> Result<Self, TransportError> {
+        Err(TransportError::Disabled(
+            TransportKind::Websocket,
+            "websocket",
+        ))
+    }
+
+    /// In-process transport listening on `/memory/<port>` addresses, used to simulate networks.
+    pub fn memory(keys: &identity::Keypair) -> Self {
+        let transport = MemoryTransport::default()
+            .upgrade(transport::upgrade::Version::V1)
+            .authenticate(noise::Config::new(keys).unwrap())
             .multiplex(yamux::Config::default())
             .boxed();
         AppTransport(transport)
@@ -38,18 +211,86 @@ impl Default for AppTransport {
 }
 
 pub struct ChainApp {
+    pub peer_id: PeerId,
     pub swarm: Swarm<AppBehaviour>,
     pub chain: Chain,
+    /// Data waiting to be mined into a block, oldest first.
+    pub mempool: VecDeque<String>,
+    /// Whether a block is being mined in the background.
+    pub mining: bool,
+    /// `chain_submitData` calls waiting for their data to be mined.
+    pub(crate) submitted: Vec<rpc::SubmittedData>,
     pub init_sender: mpsc::UnboundedSender<bool>,
     pub response_sender: mpsc::UnboundedSender<ChainResponse>,
+    pub mined_sender: mpsc::UnboundedSender<Mined>,
+    /// Drops, delays, duplicates or reorders incoming messages when set, to test sync.
+    pub faults: Option<FaultInjector>,
+    pub metrics: Metrics,
+    /// Structured stream of the domain events, when enabled.
+    pub events: Option<EventSink>,
+    /// Set in light client mode: the node follows headers only and leaves `chain` alone.
+    pub light: Option<LightClient>,
+    /// Number of recent blocks to keep the data of, when pruning.
+    pub pruning: Option<usize>,
+    /// Peers that said they are pruned, full chains are requested from the others.
+    pub pruned_peers: HashSet<PeerId>,
+    /// Set when the chain spec has finality validators.
+    pub finality: Option<Finality>,
+    /// Address the rewards of the blocks mined by this node go to, none when `None`.
+    pub reward_address: Option<Address>,
+    /// Set when the chain runs the UTXO ledger, follows `chain`.
+    pub utxo: Option<UtxoSet>,
+    /// Transactions waiting to be mined, in UTXO mode.
+    pub transactions: TxPool,
+    /// Set when the chain runs the account ledger, follows `chain`.
+    pub accounts: Option<Accounts>,
+    /// Extrinsics waiting to be mined, in account mode.
+    pub extrinsics: ExtrinsicPool,
+    /// Accounts of the user, when a wallet file is open.
+    pub wallet: Option<Wallet>,
+    /// Locations of the entries of `chain` by hash, address and time, follows `chain`.
+    pub index: ChainIndex,
+    /// Sends the events to the receivers of [`ChainApp::subscribe`].
+    subscribers: broadcast::Sender<ChainEvent>,
+    /// Head the ledger and the pools were last updated at, see [`update_ledger`].
+    ledger_head: Option<String>,
 }
 
 impl ChainApp {
     pub fn new(
         init_sender: mpsc::UnboundedSender<bool>,
         response_sender: mpsc::UnboundedSender<ChainResponse>,
+        mined_sender: mpsc::UnboundedSender<Mined>,
     ) -> Self {
-        let AppTransport(transport) = AppTransport::default();
+        Self::with_transport(
+            KEYS.to_owned(),
+            AppTransport::default(),
+            true,
+            &NodeConfig::default(),
+            init_sender,
+            response_sender,
+            mined_sender,
+        )
+    }
+
+    /// Builds a node with its own identity, transport and limits, mDNS discovery can be turned
+    /// off when peers are wired by hand.
+    pub fn with_transport(
+        keys: identity::Keypair,
+        AppTransport(transport): AppTransport,
+        enable_mdns: bool,
+        config: &NodeConfig,
+        init_sender: mpsc::UnboundedSender<bool>,
+        response_sender: mpsc::UnboundedSender<ChainResponse>,
+        mined_sender: mpsc::UnboundedSender<Mined>,
+    ) -> Self {
+        let peer_id = PeerId::from(keys.public());
+        // rewards go to the node key until another address is set
+        let reward_address = keys
+            .clone()
+            .try_into_ed25519()
+            .ok()
+            .map(|k| keys::encode_address(&k.public().to_bytes()));
 
         // To content-address message, we can take the hash of message and use it as an ID.
         let message_id_fn = |message: &gossipsub::Message| {
@@ -59,19 +300,28 @@ impl ChainApp {
         };
 
         // Set a custom gossipsub configuration
-        let gossipsub_config = gossipsub::ConfigBuilder::default()
+        let mut gossipsub_config = gossipsub::ConfigBuilder::default();
+        gossipsub_config
             .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
             .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
-            .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
-            .build()
-            .expect("Valid config");
+            .message_id_fn(message_id_fn); // content-address messages. No two messages of the same content will be propagated.
+        config.gossipsub.apply(&mut gossipsub_config);
+        let gossipsub_config = gossipsub_config.build().expect("Valid config");
 
+        let mdns = enable_mdns.then(|| {
+            mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)
+                .expect("can create behaviour")
+        });
+        let mut registry = Registry::default();
         let mut behaviour = AppBehaviour {
-            mdns: mdns::tokio::Behaviour::new(mdns::Config::default(), *PEER_ID)
-                .expect("can create behaviour"),
-            gossipsub: gossipsub::Behaviour::new(
-                gossipsub::MessageAuthenticity::Signed(KEYS.to_owned()),
+            limits: connection_limits::Behaviour::new(config.limits.connection_limits()),
+            ip_limits: IpLimits::new(config.limits.max_connections_per_ip),
+            mdns: Toggle::from(mdns),
+            gossipsub: gossipsub::Behaviour::new_with_metrics(
+                gossipsub::MessageAuthenticity::Signed(keys),
                 gossipsub_config,
+                registry.sub_registry_with_prefix("gossipsub"),
+                gossipsub::MetricsConfig::default(),
             )
             .expect("correct configuration"),
         };
@@ -83,16 +333,54 @@ impl ChainApp {
             .gossipsub
             .subscribe(&BLOCK_TOPIC)
             .expect("can subscribe");
+        let _ = behaviour
+            .gossipsub
+            .subscribe(&FINALITY_TOPIC)
+            .expect("can subscribe");
+        let _ = behaviour
+            .gossipsub
+            .subscribe(&TX_TOPIC)
+            .expect("can subscribe");
 
-        let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, *PEER_ID).build();
+        let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, peer_id)
+            .max_negotiating_inbound_streams(config.limits.max_negotiating_inbound_streams)
+            .build();
 
         Self {
+            peer_id,
             swarm,
             chain: Chain::default(),
+            mempool: VecDeque::new(),
+            mining: false,
+            submitted: Vec::new(),
             init_sender,
             response_sender,
+            mined_sender,
+            faults: None,
+            metrics: Metrics::new(registry),
+            events: None,
+            light: None,
+            pruning: None,
+            pruned_peers: HashSet::new(),
+            finality: None,
+            reward_address,
+            utxo: None,
+            transactions: TxPool::default(),
+            accounts: None,
+            extrinsics: ExtrinsicPool::default(),
+            wallet: None,
+            index: ChainIndex::default(),
+            subscribers: broadcast::channel(SUBSCRIPTION_CAPACITY).0,
+            ledger_head: None,
         }
     }
+
+    /// Receives the events of this node from now on. A receiver that falls more than
+    /// [`SUBSCRIPTION_CAPACITY`] events behind skips the oldest ones, see
+    /// [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.subscribers.subscribe()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -104,11 +392,69 @@ pub struct ChainResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LocalChainRequest {
     pub from_peer_id: String,
+    /// Messages are content-addressed, this keeps repeated requests from being dropped as duplicates.
+    #[serde(default)]
+    pub timestamp: i64,
+}
+
+/// Asks the peer `headers_from` for the headers of its chain, for light clients.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeadersRequest {
+    pub headers_from: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeadersResponse {
+    pub headers: Vec<Header>,
+    pub receiver: String,
+}
+
+/// Asks the peer `body_from` for the data of block `id`, with its Merkle proof.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BodyRequest {
+    pub body_from: String,
+    pub id: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BodyResponse {
+    pub body: BlockBody,
+    pub receiver: String,
 }
 
+/// Sent by pruned nodes when they start and instead of their chain, so full chains are
+/// requested from archive nodes. `receiver` is the peer whose request this answers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrunedNotice {
+    pub pruned_peer: String,
+    pub keep: usize,
+    pub receiver: Option<String>,
+    pub timestamp: i64,
+}
+
+/// A block the background miner couldn't build, with the pending entries it was built from.
+#[derive(Debug)]
+pub struct MiningFailure {
+    pub data: String,
+    /// Hashes of the extrinsics of the block.
+    pub extrinsics: Vec<String>,
+    /// Hashes of the UTXO transactions of the block.
+    pub transactions: Vec<String>,
+    pub error: Box<dyn std::error::Error + Send + Sync>,
+}
+
+/// What the background miner sends back, see [`mine_pending`].
+pub type Mined = std::result::Result<Block, MiningFailure>;
+
 pub enum EventType {
     Response(ChainResponse),
     Input(String),
+    Rpc(RpcRequest),
+    Mined(Mined),
+    /// Messages held back by the fault injector are due.
+    FaultsDue,
     Init,
     Gossipsub(Box<gossipsub::Event>),
     Mdns(mdns::Event),
@@ -126,17 +472,33 @@ impl From<mdns::Event> for EventType {
     }
 }
 
+impl From<void::Void> for EventType {
+    fn from(event: void::Void) -> Self {
+        void::unreachable(event)
+    }
+}
+
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "EventType")]
 pub struct AppBehaviour {
+    /// Checked first, so connections over the limits are denied before the other behaviours
+    /// see them.
+    pub limits: connection_limits::Behaviour,
+    pub ip_limits: IpLimits,
     pub gossipsub: gossipsub::Behaviour,
-    pub mdns: mdns::tokio::Behaviour,
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
 }
 
+/// Peers found through mDNS plus the ones we are connected to.
 pub fn get_list_peers(swarm: &Swarm<AppBehaviour>) -> HashSet<PeerId> {
-    let nodes = swarm.behaviour().mdns.discovered_nodes();
+    let nodes = swarm
+        .behaviour()
+        .mdns
+        .as_ref()
+        .into_iter()
+        .flat_map(|mdns| mdns.discovered_nodes());
     let mut unique_peers = HashSet::new();
-    for peer in nodes {
+    for peer in nodes.chain(swarm.connected_peers()) {
         unique_peers.insert(*peer);
     }
     unique_peers
@@ -154,21 +516,1172 @@ pub fn handle_print_chain(chain: &Chain) {
     log::info!("Local Blockchain:");
     let pretty_json = serde_json::to_string_pretty(&chain.blocks).expect("can jsonify blocks");
     log::info!("{}", pretty_json);
+    if let Some(finalized) = &chain.finalized {
+        log::info!(
+            "finalized up to block {} ({})",
+            finalized.id,
+            finalized.hash
+        );
+    }
+}
+
+/// Balances of the ledger the node follows, or the rewards of the blocks it keeps.
+fn balances(chain_app: &ChainApp) -> Balances {
+    match (&chain_app.utxo, &chain_app.accounts) {
+        (Some(set), _) => set.balances(),
+        (None, Some(accounts)) => accounts.balances(),
+        (None, None) => Balances::from_blocks(&chain_app.chain.blocks),
+    }
+}
+
+pub fn handle_print_balances(chain_app: &ChainApp) {
+    let chain = &chain_app.chain;
+    let balances = balances(chain_app);
+    log::info!("Balances:");
+    balances
+        .iter()
+        .for_each(|(address, balance)| log::info!("  {} {}", address, balance));
+    log::info!("{} coins issued", balances.total());
+    if !chain.pruned.is_empty() && chain_app.utxo.is_none() && chain_app.accounts.is_none() {
+        log::warn!(
+            "the rewards of the {} pruned blocks are not counted",
+            chain.pruned.len()
+        );
+    }
+}
+
+/// Sets the identity of this node to the ed25519 key of the secret URI `uri`, once, before
+/// [`KEYS`] or [`PEER_ID`] is first used. Returns `false` if it was too late.
+pub fn set_node_key(uri: &str) -> Result<bool, KeyError> {
+    let seed = keys::seed_from_uri(KeyScheme::Ed25519, uri)?;
+    let key = identity::Keypair::ed25519_from_bytes(seed).map_err(|_| KeyError::InvalidSecret)?;
+    Ok(Lazy::get(&KEYS).is_none() && NODE_KEY.set(key).is_ok())
+}
+
+fn open_wallet(chain_app: &ChainApp) -> Result<&Wallet, WalletError> {
+    chain_app.wallet.as_ref().ok_or(WalletError::NotOpen)
+}
+
+pub fn handle_wallet_new(name: Option<String>, scheme: KeyScheme, chain_app: &mut ChainApp) {
+    let Some(wallet) = chain_app.wallet.as_mut() else {
+        log::error!("{}", WalletError::NotOpen);
+        return;
+    };
+    match wallet.create_account(name, scheme) {
+        Ok((account, phrase)) => {
+            log::info!(
+                "created {} account {}: {}",
+                account.scheme,
+                account.name,
+                account.address
+            );
+            log::warn!(
+                "write down its secret phrase, it is not shown again: {}",
+                phrase
+            );
+        }
+        Err(e) => log::error!("can't create the account: {}", e),
+    }
+}
+
+pub fn handle_wallet_import(name: String, scheme: KeyScheme, uri: &str, chain_app: &mut ChainApp) {
+    let Some(wallet) = chain_app.wallet.as_mut() else {
+        log::error!("{}", WalletError::NotOpen);
+        return;
+    };
+    match wallet.import_account(Some(name), scheme, uri) {
+        Ok(account) => log::info!(
+            "imported {} account {}: {}",
+            account.scheme,
+            account.name,
+            account.address
+        ),
+        Err(e) => log::error!("can't import the account: {}", e),
+    }
+}
+
+pub fn handle_print_wallet(chain_app: &ChainApp) {
+    match open_wallet(chain_app) {
+        Ok(wallet) => {
+            match wallet.path() {
+                Some(path) => log::info!("Wallet {}:", path.display()),
+                None => log::info!("Wallet (in memory):"),
+            }
+            wallet.accounts().for_each(|account| {
+                log::info!(
+                    "  {} ({}) {}",
+                    account.name,
+                    account.scheme,
+                    account.address
+                )
+            });
+        }
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+/// Prints the balance of the wallet account `name`, or of every account.
+pub fn handle_wallet_balance(name: Option<&str>, chain_app: &ChainApp) {
+    let wallet = match open_wallet(chain_app) {
+        Ok(wallet) => wallet,
+        Err(e) => return log::error!("{}", e),
+    };
+    let accounts: Vec<_> = match name {
+        Some(name) => match wallet.account(Some(name)) {
+            Ok(account) => vec![account],
+            Err(e) => return log::error!("{}", e),
+        },
+        None => wallet.accounts().collect(),
+    };
+    let balances = balances(chain_app);
+    for account in accounts {
+        log::info!(
+            "  {} {} {}",
+            account.name,
+            account.address,
+            balances.get(&account.address)
+        );
+    }
+}
+
+/// Pays `amount` to `to` from the wallet account `from`, or the first one, with a transaction
+/// or an extrinsic depending on the ledger. Returns its hash.
+pub fn wallet_send(
+    to: &str,
+    amount: u64,
+    from: Option<&str>,
+    chain_app: &mut ChainApp,
+) -> Result<String, WalletError> {
+    keys::decode_address(to)?;
+    let key = open_wallet(chain_app)?.keypair(from)?;
+    if let Some(set) = &chain_app.utxo {
+        let tx = wallet::build_payment(&key, to, amount, set, &chain_app.transactions)?;
+        return Ok(submit_transaction(tx, chain_app)?);
+    }
+    let call = Call::Transfer {
+        to: String::from(to),
+        amount,
+    };
+    Ok(wallet_call(call, from, chain_app)?.hash())
 }
 
-pub fn handle_create_block(cmd: &str, chain_app: &mut ChainApp) {
-    if let Some(data) = cmd.strip_prefix("create b") {
-        let behaviour = chain_app.swarm.behaviour_mut();
-        let Ok(latest_block) = chain_app.chain.add_data(String::from(data)) else {
-            panic!("error creating block");
+/// Signs `call` with the wallet account `from`, or the first one, and submits it.
+pub fn wallet_call(
+    call: Call,
+    from: Option<&str>,
+    chain_app: &mut ChainApp,
+) -> Result<Extrinsic, WalletError> {
+    let key = open_wallet(chain_app)?.keypair(from)?;
+    let accounts = chain_app.accounts.as_ref().ok_or(AccountError::Disabled)?;
+    let extrinsic = wallet::build_call(&key, call, accounts, &chain_app.extrinsics);
+    submit_extrinsic(extrinsic.clone(), chain_app)?;
+    Ok(extrinsic)
+}
+
+/// Deploys the contract assembled from the file at `path`, see [`contracts::assemble`].
+pub fn handle_contract_deploy(
+    path: &Path,
+    gas_limit: u64,
+    from: Option<&str>,
+    chain_app: &mut ChainApp,
+) {
+    let code = match std::fs::read_to_string(path) {
+        Ok(source) => contracts::assemble(&source).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let code = match code {
+        Ok(code) => code,
+        Err(e) => {
+            log::error!("can't read the contract {}: {}", path.display(), e);
+            return;
+        }
+    };
+    match wallet_call(Call::Deploy { code, gas_limit }, from, chain_app) {
+        Ok(extrinsic) => log::info!(
+            "deploying contract {} in {}",
+            contracts::contract_address(&extrinsic.signer, extrinsic.nonce),
+            extrinsic.hash()
+        ),
+        Err(e) => log::error!("can't deploy: {}", e),
+    }
+}
+
+pub fn handle_contract_call(
+    contract: String,
+    gas_limit: u64,
+    args: Vec<i64>,
+    from: Option<&str>,
+    chain_app: &mut ChainApp,
+) {
+    let call = Call::CallContract {
+        contract,
+        args,
+        gas_limit,
+    };
+    match wallet_call(call, from, chain_app) {
+        Ok(extrinsic) => log::info!("calling the contract in {}", extrinsic.hash()),
+        Err(e) => log::error!("can't call the contract: {}", e),
+    }
+}
+
+/// Prints what a call of `contract` would return, without submitting it.
+pub fn handle_contract_query(contract: &str, args: &[i64], chain_app: &ChainApp) {
+    let Some(accounts) = &chain_app.accounts else {
+        log::error!("{}", AccountError::Disabled);
+        return;
+    };
+    match accounts.query(contract, args) {
+        Ok(Execution {
+            result: Ok(value),
+            gas_used,
+        }) => log::info!("returned {:?}, using {} gas", value, gas_used),
+        Ok(Execution {
+            result: Err(e),
+            gas_used,
+        }) => log::warn!("failed after {} gas: {}", gas_used, e),
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+/// Prints the runtime params of the next block and the upgrades scheduled after it.
+pub fn handle_print_runtime(chain_app: &ChainApp) {
+    let next = chain_app.chain.head().id + 1;
+    let params = match &chain_app.accounts {
+        Some(accounts) => accounts.params(next),
+        None => runtime::spec().params(next).1,
+    };
+    log::info!(
+        "runtime version {} at block {}, difficulty {}: {}",
+        params.version,
+        next,
+        runtime::spec().difficulty(next),
+        serde_json::to_string(&params).expect("can jsonify params")
+    );
+    let on_chain = chain_app.accounts.iter().flat_map(|a| a.upgrades().iter());
+    for (at, params) in on_chain.filter(|(at, _)| *at > next) {
+        log::info!("version {} set on chain for block {}", params.version, at);
+    }
+    for upgrade in runtime::spec().upgrades.iter().filter(|u| u.at > next) {
+        log::info!(
+            "upgrade scheduled by the chain spec for block {}: {}",
+            upgrade.at,
+            serde_json::to_string(upgrade).expect("can jsonify upgrade")
+        );
+    }
+}
+
+/// Schedules the params read from the JSON file at `path` for block `at`, signed by the
+/// sudo account.
+pub fn handle_set_runtime(path: &Path, at: u64, from: Option<&str>, chain_app: &mut ChainApp) {
+    let Some(params) = read_params(path) else {
+        return;
+    };
+    match wallet_call(Call::SetParams { at, params }, from, chain_app) {
+        Ok(extrinsic) => log::info!("scheduling the upgrade in {}", extrinsic.hash()),
+        Err(e) => log::error!("can't schedule the upgrade: {}", e),
+    }
+}
+
+fn read_params(path: &Path) -> Option<RuntimeParams> {
+    let json = std::fs::read(path).map_err(|e| e.to_string());
+    match json.and_then(|json| serde_json::from_slice(&json).map_err(|e| e.to_string())) {
+        Ok(params) => Some(params),
+        Err(e) => {
+            log::error!("can't read the params {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Prints every proposal with its tally, so far for those still taking votes.
+pub fn handle_print_proposals(chain_app: &ChainApp) {
+    let Some(accounts) = &chain_app.accounts else {
+        log::error!("{}", AccountError::Disabled);
+        return;
+    };
+    log::info!("Proposals:");
+    for (id, proposal) in accounts.proposals() {
+        let tally = accounts.tally(*id).expect("the proposal exists");
+        log::info!(
+            "#{} {:?} by {}, version {}: {} aye, {} nay, votes until block {}, enacted at {}",
+            id,
+            proposal.status,
+            proposal.proposer,
+            proposal.params.version,
+            tally.ayes,
+            tally.nays,
+            proposal.voting_ends,
+            proposal.enact_at
+        );
+    }
+}
+
+/// Proposes the params read from the JSON file at `path`, see [`crate::governance`].
+pub fn handle_propose(path: &Path, from: Option<&str>, chain_app: &mut ChainApp) {
+    let Some(params) = read_params(path) else {
+        return;
+    };
+    match wallet_call(Call::Propose { params }, from, chain_app) {
+        Ok(extrinsic) => log::info!("submitting the proposal in {}", extrinsic.hash()),
+        Err(e) => log::error!("can't propose: {}", e),
+    }
+}
+
+pub fn handle_vote(proposal: u64, aye: bool, from: Option<&str>, chain_app: &mut ChainApp) {
+    match wallet_call(Call::Vote { proposal, aye }, from, chain_app) {
+        Ok(extrinsic) => log::info!("voting on proposal #{} in {}", proposal, extrinsic.hash()),
+        Err(e) => log::error!("can't vote: {}", e),
+    }
+}
+
+pub fn handle_find_tx(hash: &str, chain_app: &ChainApp) {
+    let locations = chain_app.index.locate(hash);
+    if locations.is_empty() {
+        log::info!("{} is not in the chain", hash);
+    }
+    for location in locations {
+        log::info!(
+            "{:?} #{} of block {} ({})",
+            location.kind,
+            location.index,
+            location.block_id,
+            location.block_hash
+        );
+    }
+}
+
+pub fn handle_find_address(address: &str, chain_app: &ChainApp) {
+    let history = chain_app.index.history(address);
+    log::info!("{} entries for {}:", history.len(), address);
+    for entry in history {
+        log::info!(
+            "  {} {:?} in block {}",
+            entry.hash,
+            entry.location.kind,
+            entry.location.block_id
+        );
+    }
+}
+
+pub fn handle_find_time(timestamp: i64, chain_app: &ChainApp) {
+    match chain_app.index.height_at(timestamp) {
+        Some(id) => log::info!("block {} was the head at {}", id, timestamp),
+        None => log::info!("the chain didn't exist at {}", timestamp),
+    }
+}
+
+/// Reads the chain exported to `path`, checking its blocks and what they carry for the ledger
+/// of this node.
+pub fn read_chain(path: &Path, chain_app: &ChainApp) -> crate::Result<Chain> {
+    let chain = snapshot::import_chain(path)?;
+    check_transactions(&chain, chain_app)?;
+    Ok(chain)
+}
+
+/// Replaces the local chain by the one exported to `path`, if it is valid and longer. Returns
+/// whether it did.
+pub fn import_chain(path: &Path, chain_app: &mut ChainApp) -> crate::Result<bool> {
+    if chain_app.light.is_some() {
+        return Err("light clients follow the headers only".into());
+    }
+    let chain = read_chain(path, chain_app)?;
+    let Some(old) = chain_app.chain.choose_chain(&chain) else {
+        return Ok(false);
+    };
+    let local_id = chain_app.peer_id;
+    emit_chain_replaced(&local_id, &old, chain_app);
+    Ok(true)
+}
+
+/// Writes the local chain to `path`, returns the number of blocks written.
+pub fn export_chain(path: &Path, chain_app: &ChainApp) -> crate::Result<usize> {
+    if !chain_app.chain.pruned.is_empty() {
+        return Err("the chain is pruned, export it from an archive node".into());
+    }
+    snapshot::export_chain(path, &chain_app.chain)?;
+    Ok(chain_app.chain.blocks.len())
+}
+
+pub fn handle_import_chain(path: &Path, chain_app: &mut ChainApp) {
+    match import_chain(path, chain_app) {
+        Ok(true) => log::info!(
+            "imported {} blocks from {}",
+            chain_app.chain.blocks.len(),
+            path.display()
+        ),
+        Ok(false) => log::warn!(
+            "{} is not longer than the local chain, keeping the local one",
+            path.display()
+        ),
+        Err(e) => log::error!("can't import {}: {}", path.display(), e),
+    }
+}
+
+pub fn handle_export_chain(path: &Path, chain_app: &ChainApp) {
+    match export_chain(path, chain_app) {
+        Ok(blocks) => log::info!("exported {} blocks to {}", blocks, path.display()),
+        Err(e) => log::error!("can't export the chain to {}: {}", path.display(), e),
+    }
+}
+
+pub fn handle_print_headers(headers: &HeaderChain) {
+    log::info!("Local headers:");
+    let pretty_json = serde_json::to_string_pretty(&headers.headers).expect("can jsonify headers");
+    log::info!("{}", pretty_json);
+}
+
+fn publish<T: Serialize>(topic: &IdentTopic, msg: &T, chain_app: &mut ChainApp) {
+    let json = serde_json::to_string(msg).expect("can jsonify message");
+    let delivery = Delivery {
+        peer: chain_app.peer_id,
+        data: json.into_bytes(),
+        topic: Some(topic.hash().to_string()),
+    };
+    let deliveries = match chain_app.faults.as_mut() {
+        Some(faults) => faults.inject(delivery, Instant::now()),
+        None => vec![delivery],
+    };
+    for delivery in deliveries {
+        deliver(delivery, chain_app);
+    }
+}
+
+/// Publishes an outgoing message, or handles a received one, once the faults let it through.
+fn deliver(Delivery { peer, data, topic }: Delivery, chain_app: &mut ChainApp) {
+    let Some(topic) = topic else {
+        return handle_message(peer, &data, chain_app);
+    };
+    if let Err(e) = chain_app
+        .swarm
+        .behaviour_mut()
+        .gossipsub
+        .publish(IdentTopic::new(topic), data)
+    {
+        log::error!("can publish: {}", e);
+    }
+}
+
+/// Asks `peer` for its chain, the answer comes back as a `ChainResponse` addressed to us.
+/// Light clients ask for the headers only.
+pub fn request_chain(peer: &PeerId, chain_app: &mut ChainApp) {
+    let timestamp = Utc::now().timestamp_millis();
+    if chain_app.light.is_some() {
+        let req = HeadersRequest {
+            headers_from: peer.to_string(),
+            timestamp,
         };
-        let json = serde_json::to_string(&latest_block).expect("can jsonify request");
-        log::info!("broadcasting new block");
-        if let Err(e) = behaviour
-            .gossipsub
-            .publish(BLOCK_TOPIC.clone(), json.as_bytes())
+        publish(&CHAIN_TOPIC, &req, chain_app);
+    } else {
+        let Some(peer) = archive_peer(peer, chain_app) else {
+            log::warn!(
+                "{} is pruned and no archive node is known, can't sync",
+                peer
+            );
+            return;
+        };
+        let req = LocalChainRequest {
+            from_peer_id: peer.to_string(),
+            timestamp,
+        };
+        publish(&CHAIN_TOPIC, &req, chain_app);
+    }
+}
+
+/// `peer` unless it is pruned, then any other peer that isn't.
+fn archive_peer(peer: &PeerId, chain_app: &ChainApp) -> Option<PeerId> {
+    let is_archive = |p: &PeerId| !chain_app.pruned_peers.contains(p);
+    if is_archive(peer) {
+        return Some(*peer);
+    }
+    get_list_peers(&chain_app.swarm)
+        .into_iter()
+        .find(|p| is_archive(p))
+}
+
+fn publish_pruned_notice(receiver: Option<&PeerId>, chain_app: &mut ChainApp) {
+    let Some(keep) = chain_app.pruning else {
+        return;
+    };
+    let notice = PrunedNotice {
+        pruned_peer: chain_app.peer_id.to_string(),
+        keep,
+        receiver: receiver.map(PeerId::to_string),
+        timestamp: Utc::now().timestamp_millis(),
+    };
+    publish(&CHAIN_TOPIC, &notice, chain_app);
+}
+
+/// Asks `peer` for the data of block `id`, the answer comes back as a `BodyResponse`.
+pub fn request_body(id: u64, peer: &PeerId, chain_app: &mut ChainApp) {
+    let req = BodyRequest {
+        body_from: peer.to_string(),
+        id,
+        timestamp: Utc::now().timestamp_millis(),
+    };
+    publish(&CHAIN_TOPIC, &req, chain_app);
+}
+
+pub fn publish_block(block: &Block, chain_app: &mut ChainApp) {
+    log::info!("broadcasting new block");
+    publish(&BLOCK_TOPIC, block, chain_app);
+}
+
+/// Queues data to be mined into a block and returns its hex sha256 hash.
+pub fn submit_data(data: String, chain_app: &mut ChainApp) -> String {
+    let hash = hex::encode(Sha256::digest(data.as_bytes()));
+    log::info!("queued data {} for mining", hash);
+    chain_app.mempool.push_back(data);
+    mine_pending(chain_app);
+    hash
+}
+
+/// Queues a transaction of the UTXO ledger to be mined and sends it to the other nodes,
+/// returns its hash.
+pub fn submit_transaction(tx: Transaction, chain_app: &mut ChainApp) -> Result<String, UtxoError> {
+    let set = chain_app.utxo.as_ref().ok_or(UtxoError::Disabled)?;
+    let hash = chain_app.transactions.add(tx.clone(), set)?;
+    log::info!("queued transaction {} for mining", hash);
+    publish(&TX_TOPIC, &tx, chain_app);
+    mine_pending(chain_app);
+    Ok(hash)
+}
+
+/// Queues an extrinsic of the account ledger to be mined and sends it to the other nodes,
+/// returns its hash.
+pub fn submit_extrinsic(
+    extrinsic: Extrinsic,
+    chain_app: &mut ChainApp,
+) -> Result<String, AccountError> {
+    let accounts = chain_app.accounts.as_ref().ok_or(AccountError::Disabled)?;
+    let hash = chain_app.extrinsics.add(extrinsic.clone(), accounts)?;
+    log::info!("queued extrinsic {} for mining", hash);
+    publish(&TX_TOPIC, &extrinsic, chain_app);
+    mine_pending(chain_app);
+    Ok(hash)
+}
+
+/// Checks the transactions or extrinsics of a chain before switching to it.
+fn check_transactions(chain: &Chain, chain_app: &ChainApp) -> Result<(), BlockError> {
+    match (&chain_app.utxo, &chain_app.accounts) {
+        (Some(set), _) => set.check_chain(chain),
+        (None, Some(accounts)) => accounts.check_chain(chain),
+        (None, None) => Ok(()),
+    }
+}
+
+/// Starts mining the oldest data in the mempool on a blocking thread, unless already mining.
+pub fn mine_pending(chain_app: &mut ChainApp) {
+    if chain_app.mining {
+        return;
+    }
+    if chain_app.light.is_some() {
+        log::warn!("light clients don't mine, the data stays queued");
+        return;
+    }
+    // the state root is computed on top of the head, without what it already mined
+    update_ledger(chain_app);
+    let params = next_params(chain_app);
+    while let Some(len) = chain_app.mempool.front().map(String::len) {
+        if len <= params.max_data_len {
+            break;
+        }
+        log::warn!(
+            "dropping data of {} bytes, runtime version {} allows {}",
+            len,
+            params.version,
+            params.max_data_len
+        );
+        if let Some(data) = chain_app.mempool.pop_front() {
+            let error = SubmitError::TooLong {
+                len,
+                version: params.version,
+                max: params.max_data_len,
+            };
+            rpc::fail_submitted(&data, error, chain_app);
+        }
+    }
+    // pending transactions are mined along with the data, or on their own
+    let data = match chain_app.mempool.front() {
+        Some(data) => data.clone(),
+        None if !chain_app.transactions.is_empty() || !chain_app.extrinsics.is_empty() => {
+            String::new()
+        }
+        None => return,
+    };
+    if !consensus::engine().can_author() {
+        log::warn!("not a validator, the data stays queued");
+        return;
+    }
+    let transactions = chain_app.transactions.transactions().to_vec();
+    // the rest waits for the next block
+    let extrinsics: Vec<_> = chain_app
+        .extrinsics
+        .extrinsics()
+        .iter()
+        .take(params.max_extrinsics)
+        .cloned()
+        .collect();
+    let accounts = chain_app.accounts.clone();
+    let previous = chain_app.chain.head().header.clone();
+    let miner = chain_app.reward_address.clone();
+    let mined_sender = chain_app.mined_sender.clone();
+    let hash_rate = chain_app.metrics.hash_rate.clone();
+    chain_app.mining = true;
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let miner = miner.as_deref();
+        let failure = |error| MiningFailure {
+            data: data.clone(),
+            extrinsics: extrinsics.iter().map(Extrinsic::hash).collect(),
+            transactions: transactions.iter().map(Transaction::hash).collect(),
+            error,
+        };
+        let mined = match &accounts {
+            Some(accounts) => {
+                Block::with_state(&previous, data.clone(), extrinsics.clone(), miner, accounts)
+            }
+            None => Block::with_transactions(&previous, data.clone(), transactions.clone(), miner)
+                .map_err(Into::into),
+        }
+        .map_err(failure);
+        if let Ok(block) = &mined {
+            hash_rate.set((block.nonce + 1) as f64 / started.elapsed().as_secs_f64());
+        }
+        if let Err(e) = mined_sender.send(mined) {
+            log::error!("error sending mined block via channel: {}", e);
+        }
+    });
+}
+
+/// Runtime params of the next block on top of the head.
+pub fn next_params(chain_app: &ChainApp) -> RuntimeParams {
+    let next = chain_app.chain.head().id + 1;
+    match &chain_app.accounts {
+        Some(accounts) => accounts.params(next),
+        None => runtime::spec().params(next).1,
+    }
+}
+
+pub fn handle_mined_block(block: Block, chain_app: &mut ChainApp) {
+    chain_app.mining = false;
+    if block.previous_hash != chain_app.chain.head().hash {
+        // the chain moved on while mining, the data stays queued for the next block
+        log::warn!("discarding stale block with id: {}", block.id);
+    } else {
+        if chain_app.mempool.front() == Some(&block.data) {
+            chain_app.mempool.pop_front();
+        }
+        if chain_app.chain.try_add_block(block.clone()).is_ok() {
+            emit(
+                ChainEvent::BlockImported {
+                    id: block.id,
+                    hash: block.hash.clone(),
+                    peer: None,
+                },
+                chain_app,
+            );
+            rpc::reply_submitted(&block, chain_app);
+            publish_block(&block, chain_app);
+        }
+    }
+    mine_pending(chain_app);
+}
+
+/// Frees the miner after a block that couldn't be built. A block the engine couldn't seal
+/// leaves the pending entries queued for the next try. Otherwise the entries it was built
+/// from are dropped, so mining doesn't fail on them again.
+pub fn handle_mining_failure(failure: MiningFailure, chain_app: &mut ChainApp) {
+    chain_app.mining = false;
+    log::error!("can't mine a block: {}", failure.error);
+    if failure.error.downcast_ref::<ConsensusError>().is_some() {
+        return;
+    }
+    log::warn!(
+        "dropping the data and {} pending entries of the block",
+        failure.extrinsics.len() + failure.transactions.len()
+    );
+    if chain_app.mempool.front() == Some(&failure.data) {
+        chain_app.mempool.pop_front();
+        let error = SubmitError::Mining(failure.error.to_string());
+        rpc::fail_submitted(&failure.data, error, chain_app);
+    }
+    chain_app.extrinsics.remove(&failure.extrinsics);
+    chain_app.transactions.remove(&failure.transactions);
+    // the head didn't move, so `update_ledger` won't drop what depended on them
+    if let Some(accounts) = &chain_app.accounts {
+        chain_app.extrinsics.revalidate(accounts);
+    }
+    if let Some(set) = &chain_app.utxo {
+        chain_app.transactions.revalidate(set);
+    }
+    mine_pending(chain_app);
+}
+
+/// Records a swarm event and reports the peers connecting and disconnecting. Returns the
+/// events of the behaviours, for [`handle_event`].
+pub fn handle_swarm_event(
+    event: SwarmEvent<EventType, THandlerErr<AppBehaviour>>,
+    chain_app: &mut ChainApp,
+) -> Option<EventType> {
+    chain_app.metrics.record(&event);
+    match event {
+        SwarmEvent::Behaviour(event) => return Some(event),
+        SwarmEvent::ConnectionEstablished {
+            peer_id,
+            num_established,
+            ..
+        } if num_established.get() == 1 => emit(
+            ChainEvent::PeerConnected {
+                peer: peer_id.to_string(),
+            },
+            chain_app,
+        ),
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            num_established: 0,
+            ..
+        } => emit(
+            ChainEvent::PeerDisconnected {
+                peer: peer_id.to_string(),
+            },
+            chain_app,
+        ),
+        _ => {}
+    }
+    None
+}
+
+pub fn handle_event(event: EventType, chain_app: &mut ChainApp) {
+    let head = chain_app.chain.head().hash.clone();
+    match event {
+        EventType::Init => {
+            publish_pruned_notice(None, chain_app);
+            let peers = get_list_peers(&chain_app.swarm);
+            log::info!("connected nodes: {}", peers.len());
+            if let Some(peer) = peers.iter().last() {
+                request_chain(peer, chain_app);
+            }
+        }
+        EventType::Response(resp) => publish(&CHAIN_TOPIC, &resp, chain_app),
+        EventType::Input(line) => command::handle_input(&line, chain_app),
+        EventType::Rpc(request) => rpc::handle_rpc_request(request, chain_app),
+        EventType::Mined(Ok(block)) => handle_mined_block(block, chain_app),
+        EventType::Mined(Err(failure)) => handle_mining_failure(failure, chain_app),
+        EventType::FaultsDue => {
+            deliver_due_messages(chain_app);
+        }
+        EventType::Gossipsub(event) => handle_gossipsub_event(*event, chain_app),
+        EventType::Mdns(event) => handle_mdns_event(event, chain_app),
+    }
+    update_finality(chain_app);
+    update_ledger(chain_app);
+    chain_app.index.sync(&chain_app.chain);
+    let best = chain_app.chain.head();
+    if best.hash != head {
+        let event = ChainEvent::NewBestBlock {
+            id: best.id,
+            hash: best.hash.clone(),
+        };
+        emit(event, chain_app);
+    }
+    if let Some(keep) = chain_app.pruning {
+        chain_app.chain.prune(keep);
+    }
+    let peers = get_list_peers(&chain_app.swarm).len();
+    chain_app.metrics.observe(&chain_app.chain, peers);
+}
+
+fn handle_gossipsub_event(event: gossipsub::Event, chain_app: &mut ChainApp) {
+    chain_app.metrics.record(&event);
+    match event {
+        gossipsub::Event::Message {
+            propagation_source,
+            message_id: _id,
+            message: msg,
+        } => {
+            let delivery = Delivery {
+                peer: msg.source.unwrap_or(propagation_source),
+                data: msg.data,
+                topic: None,
+            };
+            let deliveries = match chain_app.faults.as_mut() {
+                Some(faults) => faults.inject(delivery, Instant::now()),
+                None => vec![delivery],
+            };
+            for delivery in deliveries {
+                deliver(delivery, chain_app);
+            }
+        }
+        gossipsub::Event::Subscribed { peer_id, topic } if topic == CHAIN_TOPIC.hash() => {
+            // compare chains with every peer that joins, so nodes catch up after (re)connecting
+            request_chain(&peer_id, chain_app);
+        }
+        _ => {}
+    }
+}
+
+/// Handles or publishes the messages the fault injector held back until now, returns whether
+/// there was any.
+pub fn deliver_due_messages(chain_app: &mut ChainApp) -> bool {
+    let Some(faults) = chain_app.faults.as_mut() else {
+        return false;
+    };
+    let due = faults.due(Instant::now());
+    let delivered = !due.is_empty();
+    for delivery in due {
+        deliver(delivery, chain_app);
+    }
+    delivered
+}
+
+fn handle_message(peer_id: PeerId, data: &[u8], chain_app: &mut ChainApp) {
+    let local_id = chain_app.peer_id.to_string();
+    if let Ok(resp) = serde_json::from_slice::<ChainResponse>(data) {
+        if resp.receiver == local_id && chain_app.light.is_none() {
+            log::info!("Response from: {}", peer_id);
+            resp.blocks.iter().for_each(|r| log::info!("{:#?}", r));
+            let remote = Chain {
+                blocks: resp.blocks,
+                pruned: Vec::new(),
+                finalized: None,
+            };
+            if let Err(e) = check_transactions(&remote, chain_app) {
+                log::warn!("ignoring the chain of {}: {}", peer_id, e);
+            } else if let Some(old) = chain_app.chain.choose_chain(&remote) {
+                emit_chain_replaced(&peer_id, &old, chain_app);
+            }
+        }
+    } else if let Ok(resp) = serde_json::from_slice::<LocalChainRequest>(data) {
+        // light clients have no blocks to send, pruned nodes only have the recent ones
+        if resp.from_peer_id == local_id && chain_app.pruning.is_some() {
+            log::info!("pruned, pointing {} to archive nodes", peer_id);
+            publish_pruned_notice(Some(&peer_id), chain_app);
+        } else if resp.from_peer_id == local_id && chain_app.light.is_none() {
+            log::info!("sending local chain to {}", peer_id.to_string());
+            if let Err(e) = chain_app.response_sender.send(ChainResponse {
+                blocks: chain_app.chain.blocks.clone(),
+                receiver: peer_id.to_string(),
+            }) {
+                log::error!("error sending response via channel: {}", e);
+            }
+        }
+    } else if let Ok(block) = serde_json::from_slice::<Block>(data) {
+        log::info!("received new block from {}", peer_id.to_string());
+        chain_app.metrics.blocks_received.inc();
+        match chain_app.light.is_some() {
+            true => handle_header(peer_id, block.header, chain_app),
+            false => handle_block(peer_id, block, chain_app),
+        }
+    } else if let Ok(req) = serde_json::from_slice::<HeadersRequest>(data) {
+        if req.headers_from == local_id {
+            log::info!("sending headers to {}", peer_id);
+            let headers = match &chain_app.light {
+                Some(light) => light.headers.headers.clone(),
+                None => chain_app.chain.headers().cloned().collect(),
+            };
+            let resp = HeadersResponse {
+                headers,
+                receiver: peer_id.to_string(),
+            };
+            publish(&CHAIN_TOPIC, &resp, chain_app);
+        }
+    } else if let Ok(resp) = serde_json::from_slice::<HeadersResponse>(data) {
+        if let Some(light) = chain_app
+            .light
+            .as_mut()
+            .filter(|_| resp.receiver == local_id)
+        {
+            let remote = HeaderChain {
+                headers: resp.headers,
+            };
+            if light.headers.choose_chain(remote) {
+                log::info!(
+                    "synced headers up to {} from {}",
+                    light.headers.head().id,
+                    peer_id
+                );
+            }
+        }
+    } else if let Ok(req) = serde_json::from_slice::<BodyRequest>(data) {
+        let block = chain_app
+            .chain
+            .get_block(req.id)
+            .filter(|_| req.body_from == local_id);
+        if let Some(block) = block.filter(|_| chain_app.light.is_none()) {
+            let resp = BodyResponse {
+                body: BlockBody::from(block),
+                receiver: peer_id.to_string(),
+            };
+            publish(&CHAIN_TOPIC, &resp, chain_app);
+        }
+    } else if let Ok(resp) = serde_json::from_slice::<BodyResponse>(data) {
+        if let Some(light) = chain_app
+            .light
+            .as_mut()
+            .filter(|_| resp.receiver == local_id)
         {
-            log::error!("can publish: {}", e);
+            let id = resp.body.id;
+            match light.add_body(resp.body) {
+                Ok(()) => log::info!("block {}: {}", id, light.bodies[&id]),
+                Err(e) => log::error!("invalid body from {}: {}", peer_id, e),
+            }
+        }
+    } else if let Ok(notice) = serde_json::from_slice::<PrunedNotice>(data) {
+        log::info!("{} is pruned to {} blocks", peer_id, notice.keep);
+        chain_app.pruned_peers.insert(peer_id);
+        if notice.receiver.as_ref() == Some(&local_id) {
+            // our request went to the wrong node, ask an archive node instead
+            request_chain(&peer_id, chain_app);
+        }
+    } else if let Ok(tx) = serde_json::from_slice::<Transaction>(data) {
+        if let Some(set) = chain_app.utxo.as_ref() {
+            if let Err(e) = chain_app.transactions.add(tx, set) {
+                log::warn!("ignoring transaction from {}: {}", peer_id, e);
+            }
+        }
+    } else if let Ok(extrinsic) = serde_json::from_slice::<Extrinsic>(data) {
+        if let Some(accounts) = chain_app.accounts.as_ref() {
+            if let Err(e) = chain_app.extrinsics.add(extrinsic, accounts) {
+                log::warn!("ignoring extrinsic from {}: {}", peer_id, e);
+            }
+        }
+    } else if let Ok(vote) = serde_json::from_slice::<Vote>(data) {
+        if let Some(finality) = chain_app.finality.as_mut() {
+            if let Err(e) = finality.add_vote(&vote) {
+                log::warn!("ignoring vote from {}: {}", peer_id, e);
+            }
+        }
+    } else {
+        log::error!(
+            "couldn't deserialize msg: {:?} from: {}",
+            std::str::from_utf8(data),
+            peer_id.to_string()
+        );
+    }
+}
+
+/// Votes for the new head, if this node is a finality validator, and moves the finalized block
+/// forward once enough votes are in.
+fn update_finality(chain_app: &mut ChainApp) {
+    let Some(finality) = chain_app.finality.as_mut() else {
+        return;
+    };
+    let vote = finality.vote(chain_app.chain.head());
+    let finalized = finality.finalize(&chain_app.chain);
+    if let Some(vote) = vote {
+        publish(&FINALITY_TOPIC, &vote, chain_app);
+    }
+    if let Some(header) = finalized {
+        log::info!("finalized block {}", header.id);
+        let event = ChainEvent::BlockFinalized {
+            id: header.id,
+            hash: header.hash.clone(),
+        };
+        chain_app.chain.finalized = Some(header);
+        emit(event, chain_app);
+    }
+}
+
+/// Applies the blocks added or replaced to the UTXO set or the accounts, and drops the pending
+/// transactions and extrinsics they include or made invalid. Does nothing until the head
+/// changes, the pools check new entries on their own.
+fn update_ledger(chain_app: &mut ChainApp) {
+    let head = &chain_app.chain.head().hash;
+    let no_ledger = chain_app.utxo.is_none() && chain_app.accounts.is_none();
+    if no_ledger || chain_app.ledger_head.as_ref() == Some(head) {
+        return;
+    }
+    let mut synced = true;
+    if let Some(set) = chain_app.utxo.as_mut() {
+        if let Err(e) = set.sync(&chain_app.chain) {
+            log::error!("the UTXO set can't follow the chain: {}", e);
+            synced = false;
+        }
+        chain_app.transactions.revalidate(set);
+    }
+    if let Some(accounts) = chain_app.accounts.as_mut() {
+        if let Err(e) = accounts.sync(&chain_app.chain) {
+            log::error!("the accounts can't follow the chain: {}", e);
+            synced = false;
+        }
+        chain_app.extrinsics.revalidate(accounts);
+    }
+    // a failed sync is tried again after the next event
+    if synced {
+        chain_app.ledger_head = Some(chain_app.chain.head().hash.clone());
+    }
+}
+
+fn handle_block(peer_id: PeerId, block: Block, chain_app: &mut ChainApp) {
+    let (id, hash) = (block.id, block.hash.clone());
+    // blocks that don't follow the head are rejected by `try_add_block`
+    let spends = match (&chain_app.utxo, &chain_app.accounts) {
+        _ if block.previous_hash != chain_app.chain.head().hash => Ok(()),
+        (Some(set), _) => set.check_block(&block),
+        (None, Some(accounts)) => accounts.check_block(&block),
+        (None, None) => Ok(()),
+    };
+    match spends.and_then(|()| chain_app.chain.try_add_block(block)) {
+        Ok(()) => emit(
+            ChainEvent::BlockImported {
+                id,
+                hash,
+                peer: Some(peer_id.to_string()),
+            },
+            chain_app,
+        ),
+        Err(e) => {
+            chain_app.metrics.reject_block(e.reason());
+            emit(
+                ChainEvent::BlockRejected {
+                    id,
+                    hash,
+                    peer: peer_id.to_string(),
+                    reason: String::from(e.reason()),
+                    error: e.to_string(),
+                },
+                chain_app,
+            );
+            if id > chain_app.chain.head().id {
+                // the sender is ahead of us or on another fork, fetch its chain to compare
+                request_chain(&peer_id, chain_app);
+            }
+        }
+    }
+}
+
+/// Light clients keep only the header of the blocks they receive.
+fn handle_header(peer_id: PeerId, header: Header, chain_app: &mut ChainApp) {
+    let light = chain_app.light.as_mut().expect("light client mode");
+    let id = header.id;
+    if let Err(e) = light.headers.try_add_header(header) {
+        chain_app.metrics.reject_block(e.reason());
+        if id > light.headers.head().id {
+            request_chain(&peer_id, chain_app);
+        }
+    }
+}
+
+pub fn emit(event: ChainEvent, chain_app: &mut ChainApp) {
+    // nobody subscribed is fine
+    let _ = chain_app.subscribers.send(event.clone());
+    if let Some(events) = chain_app.events.as_mut() {
+        events.emit(event);
+    }
+}
+
+/// Reports a chain replaced by `peer`'s, and the reorg if blocks of the `old` chain were dropped.
+fn emit_chain_replaced(peer: &PeerId, old: &Chain, chain_app: &mut ChainApp) {
+    let new = &chain_app.chain;
+    // compared by header, either chain may be pruned
+    let dropped: Vec<&Header> = old
+        .headers()
+        .filter(|h| new.get_header(h.id).is_none_or(|n| n.hash != h.hash))
+        .collect();
+    let old_tip = old.head().hash.clone();
+    let new_tip = new.head().hash.clone();
+    let replaced = ChainEvent::ChainReplaced {
+        peer: peer.to_string(),
+        old_length: old.len(),
+        new_length: new.len(),
+        tip: new_tip.clone(),
+    };
+    let reorg = dropped
+        .first()
+        .filter(|first| first.id > 0)
+        .map(|first| ChainEvent::Reorg {
+            fork_id: first.id - 1,
+            depth: dropped.len(),
+            old_tip,
+            new_tip,
+        });
+    emit(replaced, chain_app);
+    if let Some(reorg) = reorg {
+        emit(reorg, chain_app);
+    }
+}
+
+fn handle_mdns_event(event: mdns::Event, chain_app: &mut ChainApp) {
+    match event {
+        mdns::Event::Discovered(discovered_list) => {
+            // a peer is listed once per address
+            for peer in discovered_list.into_iter().map(|(peer, _)| peer).unique() {
+                emit(
+                    ChainEvent::PeerDiscovered {
+                        peer: peer.to_string(),
+                    },
+                    chain_app,
+                );
+                chain_app
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .add_explicit_peer(&peer);
+            }
+        }
+        mdns::Event::Expired(expired_list) => {
+            for peer in expired_list.into_iter().map(|(peer, _)| peer).unique() {
+                let mdns = chain_app.swarm.behaviour().mdns.as_ref();
+                if !mdns.is_some_and(|mdns| mdns.has_node(&peer)) {
+                    emit(
+                        ChainEvent::PeerExpired {
+                            peer: peer.to_string(),
+                        },
+                        chain_app,
+                    );
+                    chain_app
+                        .swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .remove_explicit_peer(&peer);
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_combined_transports() {
+        let keys = identity::Keypair::generate_ed25519();
+        assert!(AppTransport::new(&keys, &TransportConfig::default()).is_ok());
+        let config = TransportConfig {
+            transports: vec![TransportKind::Tcp, TransportKind::Tcp],
+        };
+        assert!(AppTransport::new(&keys, &config).is_ok());
+        let config = TransportConfig { transports: vec![] };
+        assert!(matches!(
+            AppTransport::new(&keys, &config),
+            Err(TransportError::Empty)
+        ));
+    }
+
+    #[cfg(not(feature = "quic"))]
+    #[test]
+    fn disabled_transport_is_an_error() {
+        let keys = identity::Keypair::generate_ed25519();
+        let config = TransportConfig {
+            transports: vec![TransportKind::Tcp, TransportKind::Quic],
+        };
+        assert!(matches!(
+            AppTransport::new(&keys, &config),
+            Err(TransportError::Disabled(TransportKind::Quic, "quic"))
+        ));
+    }
+
+    #[test]
+    fn listens_on_one_address_per_transport() {
+        let config = TransportConfig {
+            transports: vec![TransportKind::Tcp, TransportKind::Quic],
+        };
+        let addrs: Vec<String> = config
+            .default_listen_addrs()
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(addrs, ["/ip4/0.0.0.0/tcp/0", "/ip4/0.0.0.0/udp/0/quic-v1"]);
+    }
+}
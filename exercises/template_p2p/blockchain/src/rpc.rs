@@ -0,0 +1,424 @@
+use crate::{
+    accounts::{Account, AccountError, Extrinsic},
+    blocks::{Block, Header},
+    consensus,
+    contracts::Execution,
+    index::{Entry, Location},
+    p2p::{self, ChainApp},
+    utxo::{Transaction, Unspent, UtxoError},
+    Result,
+};
+use jsonrpsee::{
+    core::{async_trait, RpcResult, SubscriptionResult},
+    proc_macros::rpc,
+    server::{PendingSubscriptionSink, ServerBuilder, ServerHandle, SubscriptionMessage},
+    types::ErrorObjectOwned,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    ops::Range,
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+const INVALID_PARAMS: i32 = -32602;
+const INTERNAL_ERROR: i32 = -32603;
+
+/// How long a `chain_submitData` call waits for its block.
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A block can be looked up either by its id (height) or by its hex hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockId {
+    Number(u64),
+    Hash(String),
+}
+
+/// Requests coming from the RPC server and the explorer, answered by the event loop that owns
+/// the `ChainApp`.
+pub enum RpcRequest {
+    GetBlock(BlockId, oneshot::Sender<Option<Block>>),
+    /// Headers of the blocks in the range, pruned ones included.
+    GetHeaders(Range<u64>, oneshot::Sender<Vec<Header>>),
+    GetHead(oneshot::Sender<Block>),
+    GetFinalized(oneshot::Sender<Option<Header>>),
+    /// Answered once the data is mined, or can't be.
+    SubmitData(
+        String,
+        oneshot::Sender<std::result::Result<Block, SubmitError>>,
+    ),
+    SubmitTransaction(
+        Transaction,
+        oneshot::Sender<std::result::Result<String, UtxoError>>,
+    ),
+    GetUnspent(String, oneshot::Sender<Vec<Unspent>>),
+    SubmitExtrinsic(
+        Extrinsic,
+        oneshot::Sender<std::result::Result<String, AccountError>>,
+    ),
+    GetAccount(String, oneshot::Sender<Option<Account>>),
+    QueryContract(
+        String,
+        Vec<i64>,
+        oneshot::Sender<std::result::Result<Execution, AccountError>>,
+    ),
+    Locate(String, oneshot::Sender<Vec<Location>>),
+    History(String, oneshot::Sender<Vec<Entry>>),
+    HeightAt(i64, oneshot::Sender<Option<u64>>),
+    ExportChain(PathBuf, oneshot::Sender<Result<usize>>),
+    ImportChain(PathBuf, oneshot::Sender<Result<bool>>),
+    Peers(oneshot::Sender<Vec<String>>),
+}
+
+#[rpc(server)]
+pub trait ChainRpc {
+    #[method(name = "chain_getBlock")]
+    async fn get_block(&self, id: BlockId) -> RpcResult<Option<Block>>;
+
+    #[method(name = "chain_getHead")]
+    async fn get_head(&self) -> RpcResult<Block>;
+
+    /// Header of the latest finalized block, `null` without finality validators.
+    #[method(name = "chain_getFinalizedHead")]
+    async fn get_finalized_head(&self) -> RpcResult<Option<Header>>;
+
+    /// Queues data to be mined and returns the block it was mined in.
+    #[method(name = "chain_submitData")]
+    async fn submit_data(&self, data: String) -> RpcResult<Block>;
+
+    /// Queues a signed UTXO transaction to be mined and returns its hash.
+    #[method(name = "chain_submitTransaction")]
+    async fn submit_transaction(&self, tx: Transaction) -> RpcResult<String>;
+
+    /// Unspent outputs of an address, in UTXO mode.
+    #[method(name = "chain_getUnspent")]
+    async fn get_unspent(&self, address: String) -> RpcResult<Vec<Unspent>>;
+
+    /// Queues a signed extrinsic of the account ledger to be mined and returns its hash.
+    #[method(name = "chain_submitExtrinsic")]
+    async fn submit_extrinsic(&self, extrinsic: Extrinsic) -> RpcResult<String>;
+
+    /// Balance and next nonce of an address, `null` unless the node follows the account ledger.
+    #[method(name = "chain_getAccount")]
+    async fn get_account(&self, address: String) -> RpcResult<Option<Account>>;
+
+    /// What a call of the contract would return, without submitting it.
+    #[method(name = "contracts_query")]
+    async fn query_contract(&self, address: String, args: Vec<i64>) -> RpcResult<Execution>;
+
+    /// Blocks holding the data, transaction or extrinsic with this hash, oldest first.
+    #[method(name = "index_getLocations")]
+    async fn get_locations(&self, hash: String) -> RpcResult<Vec<Location>>;
+
+    /// Transactions and extrinsics sent or received by an address, oldest first.
+    #[method(name = "index_getHistory")]
+    async fn get_history(&self, address: String) -> RpcResult<Vec<Entry>>;
+
+    /// Id of the head of the chain at a unix timestamp, `null` before the genesis block.
+    #[method(name = "index_getHeightAt")]
+    async fn get_height_at(&self, timestamp: i64) -> RpcResult<Option<u64>>;
+
+    /// Writes the chain to a file of the node, in the `export-chain` format, and returns the
+    /// number of blocks written.
+    #[method(name = "chain_exportChain")]
+    async fn export_chain(&self, path: PathBuf) -> RpcResult<usize>;
+
+    /// Switches to the chain exported to a file of the node, if it is valid and longer, and
+    /// returns whether it did.
+    #[method(name = "chain_importChain")]
+    async fn import_chain(&self, path: PathBuf) -> RpcResult<bool>;
+
+    #[method(name = "system_peers")]
+    async fn peers(&self) -> RpcResult<Vec<String>>;
+
+    #[subscription(name = "chain_subscribeNewHeads" => "chain_newHead", unsubscribe = "chain_unsubscribeNewHeads", item = Block)]
+    async fn subscribe_new_heads(&self) -> SubscriptionResult;
+}
+
+pub struct RpcServerImpl {
+    requests: mpsc::UnboundedSender<RpcRequest>,
+    new_heads: broadcast::Sender<Block>,
+}
+
+impl RpcServerImpl {
+    async fn call<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<T>) -> RpcRequest,
+    ) -> RpcResult<T> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests
+            .send(request(sender))
+            .map_err(|_| internal_error("node is shutting down"))?;
+        receiver
+            .await
+            .map_err(|_| internal_error("node dropped the request"))
+    }
+}
+
+#[async_trait]
+impl ChainRpcServer for RpcServerImpl {
+    async fn get_block(&self, id: BlockId) -> RpcResult<Option<Block>> {
+        self.call(|reply| RpcRequest::GetBlock(id, reply)).await
+    }
+
+    async fn get_head(&self) -> RpcResult<Block> {
+        self.call(RpcRequest::GetHead).await
+    }
+
+    async fn get_finalized_head(&self) -> RpcResult<Option<Header>> {
+        self.call(RpcRequest::GetFinalized).await
+    }
+
+    async fn submit_data(&self, data: String) -> RpcResult<Block> {
+        let call = self.call(|reply| RpcRequest::SubmitData(data, reply));
+        let code = |e: &SubmitError| match e {
+            SubmitError::TooLong { .. } => INVALID_PARAMS,
+            _ => INTERNAL_ERROR,
+        };
+        tokio::time::timeout(SUBMIT_TIMEOUT, call)
+            .await
+            .map_err(|_| internal_error("the data was not mined in time"))??
+            .map_err(|e| ErrorObjectOwned::owned(code(&e), e.to_string(), None::<()>))
+    }
+
+    async fn submit_transaction(&self, tx: Transaction) -> RpcResult<String> {
+        self.call(|reply| RpcRequest::SubmitTransaction(tx, reply))
+            .await?
+            .map_err(|e| ErrorObjectOwned::owned(INVALID_PARAMS, e.to_string(), None::<()>))
+    }
+
+    async fn get_unspent(&self, address: String) -> RpcResult<Vec<Unspent>> {
+        self.call(|reply| RpcRequest::GetUnspent(address, reply))
+            .await
+    }
+
+    async fn submit_extrinsic(&self, extrinsic: Extrinsic) -> RpcResult<String> {
+        self.call(|reply| RpcRequest::SubmitExtrinsic(extrinsic, reply))
+            .await?
+            .map_err(|e| ErrorObjectOwned::owned(INVALID_PARAMS, e.to_string(), None::<()>))
+    }
+
+    async fn get_account(&self, address: String) -> RpcResult<Option<Account>> {
+        self.call(|reply| RpcRequest::GetAccount(address, reply))
+            .await
+    }
+
+    async fn query_contract(&self, address: String, args: Vec<i64>) -> RpcResult<Execution> {
+        self.call(|reply| RpcRequest::QueryContract(address, args, reply))
+            .await?
+            .map_err(|e| ErrorObjectOwned::owned(INVALID_PARAMS, e.to_string(), None::<()>))
+    }
+
+    async fn get_locations(&self, hash: String) -> RpcResult<Vec<Location>> {
+        self.call(|reply| RpcRequest::Locate(hash, reply)).await
+    }
+
+    async fn get_history(&self, address: String) -> RpcResult<Vec<Entry>> {
+        self.call(|reply| RpcRequest::History(address, reply)).await
+    }
+
+    async fn get_height_at(&self, timestamp: i64) -> RpcResult<Option<u64>> {
+        self.call(|reply| RpcRequest::HeightAt(timestamp, reply))
+            .await
+    }
+
+    async fn export_chain(&self, path: PathBuf) -> RpcResult<usize> {
+        self.call(|reply| RpcRequest::ExportChain(path, reply))
+            .await?
+            .map_err(|e| internal_error(&e.to_string()))
+    }
+
+    async fn import_chain(&self, path: PathBuf) -> RpcResult<bool> {
+        self.call(|reply| RpcRequest::ImportChain(path, reply))
+            .await?
+            .map_err(|e| internal_error(&e.to_string()))
+    }
+
+    async fn peers(&self) -> RpcResult<Vec<String>> {
+        self.call(RpcRequest::Peers).await
+    }
+
+    async fn subscribe_new_heads(&self, pending: PendingSubscriptionSink) -> SubscriptionResult {
+        let mut heads = self.new_heads.subscribe();
+        let sink = pending.accept().await?;
+        loop {
+            tokio::select! {
+                _ = sink.closed() => return Ok(()),
+                head = heads.recv() => match head {
+                    Ok(block) => sink.send(SubscriptionMessage::from_json(&block)?).await?,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("new heads subscriber lagged, skipped {} heads", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+fn internal_error(msg: &str) -> ErrorObjectOwned {
+    ErrorObjectOwned::owned(INTERNAL_ERROR, msg, None::<()>)
+}
+
+/// Starts the JSON-RPC server (HTTP and WebSocket on the same port) bound to localhost.
+pub async fn start_server(
+    port: u16,
+    requests: mpsc::UnboundedSender<RpcRequest>,
+    new_heads: broadcast::Sender<Block>,
+) -> Result<(SocketAddr, ServerHandle)> {
+    let server = ServerBuilder::default()
+        .build(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+        .await?;
+    let addr = server.local_addr()?;
+    let handle = server.start(
+        RpcServerImpl {
+            requests,
+            new_heads,
+        }
+        .into_rpc(),
+    );
+    Ok((addr, handle))
+}
+
+/// Why a `chain_submitData` call didn't get its block.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SubmitError {
+    #[error("light clients don't mine")]
+    Light,
+    #[error("this node is not a validator and can't author blocks")]
+    NotValidator,
+    #[error("data of {len} bytes, runtime version {version} allows {max}")]
+    TooLong {
+        len: usize,
+        version: u32,
+        max: usize,
+    },
+    #[error("can't mine the data: {0}")]
+    Mining(String),
+}
+
+/// Data of a `chain_submitData` call, waiting to be mined.
+pub struct SubmittedData {
+    data: String,
+    reply: oneshot::Sender<std::result::Result<Block, SubmitError>>,
+}
+
+/// Answers the oldest `chain_submitData` call waiting for the data of `block`.
+pub fn reply_submitted(block: &Block, chain_app: &mut ChainApp) {
+    if let Some(reply) = take_submitted(&block.data, chain_app) {
+        let _ = reply.send(Ok(block.clone()));
+    }
+}
+
+/// Fails the oldest `chain_submitData` call waiting for `data`, dropped from the mempool.
+pub fn fail_submitted(data: &str, error: SubmitError, chain_app: &mut ChainApp) {
+    if let Some(reply) = take_submitted(data, chain_app) {
+        let _ = reply.send(Err(error));
+    }
+}
+
+fn take_submitted(
+    data: &str,
+    chain_app: &mut ChainApp,
+) -> Option<oneshot::Sender<std::result::Result<Block, SubmitError>>> {
+    let submitted = &mut chain_app.submitted;
+    // the calls that timed out are gone
+    submitted.retain(|s| !s.reply.is_closed());
+    let i = submitted.iter().position(|s| s.data == data)?;
+    Some(submitted.remove(i).reply)
+}
+
+pub fn handle_rpc_request(request: RpcRequest, chain_app: &mut ChainApp) {
+    // the caller may have gone away, so failing to reply is not an error
+    match request {
+        RpcRequest::GetBlock(id, reply) => {
+            let block = match id {
+                BlockId::Number(id) => chain_app.chain.get_block(id),
+                BlockId::Hash(hash) => chain_app.chain.get_block_by_hash(&hash),
+            };
+            let _ = reply.send(block.cloned());
+        }
+        RpcRequest::GetHeaders(range, reply) => {
+            let headers = range
+                .map_while(|id| chain_app.chain.get_header(id).cloned())
+                .collect();
+            let _ = reply.send(headers);
+        }
+        RpcRequest::GetHead(reply) => {
+            let _ = reply.send(chain_app.chain.head().clone());
+        }
+        RpcRequest::GetFinalized(reply) => {
+            let _ = reply.send(chain_app.chain.finalized.clone());
+        }
+        RpcRequest::SubmitData(data, reply) => {
+            let params = p2p::next_params(chain_app);
+            if chain_app.light.is_some() {
+                let _ = reply.send(Err(SubmitError::Light));
+            } else if !consensus::engine().can_author() {
+                let _ = reply.send(Err(SubmitError::NotValidator));
+            } else if data.len() > params.max_data_len {
+                let _ = reply.send(Err(SubmitError::TooLong {
+                    len: data.len(),
+                    version: params.version,
+                    max: params.max_data_len,
+                }));
+            } else {
+                p2p::submit_data(data.clone(), chain_app);
+                chain_app.submitted.push(SubmittedData { data, reply });
+            }
+        }
+        RpcRequest::SubmitTransaction(tx, reply) => {
+            let _ = reply.send(p2p::submit_transaction(tx, chain_app));
+        }
+        RpcRequest::GetUnspent(address, reply) => {
+            let unspent = chain_app
+                .utxo
+                .as_ref()
+                .map(|set| set.unspent(&address))
+                .unwrap_or_default();
+            let _ = reply.send(unspent);
+        }
+        RpcRequest::SubmitExtrinsic(extrinsic, reply) => {
+            let _ = reply.send(p2p::submit_extrinsic(extrinsic, chain_app));
+        }
+        RpcRequest::GetAccount(address, reply) => {
+            let account = chain_app.accounts.as_ref().map(|accounts| Account {
+                nonce: chain_app.extrinsics.next_nonce(&address, accounts),
+                ..accounts.get(&address)
+            });
+            let _ = reply.send(account);
+        }
+        RpcRequest::QueryContract(address, args, reply) => {
+            let execution = match &chain_app.accounts {
+                Some(accounts) => accounts.query(&address, &args),
+                None => Err(AccountError::Disabled),
+            };
+            let _ = reply.send(execution);
+        }
+        RpcRequest::Locate(hash, reply) => {
+            let _ = reply.send(chain_app.index.locate(&hash).to_vec());
+        }
+        RpcRequest::History(address, reply) => {
+            let _ = reply.send(chain_app.index.history(&address).to_vec());
+        }
+        RpcRequest::HeightAt(timestamp, reply) => {
+            let _ = reply.send(chain_app.index.height_at(timestamp));
+        }
+        RpcRequest::ExportChain(path, reply) => {
+            let _ = reply.send(p2p::export_chain(&path, chain_app));
+        }
+        RpcRequest::ImportChain(path, reply) => {
+            let _ = reply.send(p2p::import_chain(&path, chain_app));
+        }
+        RpcRequest::Peers(reply) => {
+            let peers = p2p::get_list_peers(&chain_app.swarm)
+                .iter()
+                .map(|p| p.to_string())
+                .collect();
+            let _ = reply.send(peers);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Rules that change without a new node binary, like Substrate's forkless runtime upgrades.
+//!
+//! Each [`RuntimeParams`] is a runtime version. The chain spec schedules upgrades at given
+//! heights, and its sudo account can schedule one on chain with a `set_params` extrinsic, see
+//! [`crate::accounts::Call::SetParams`]. Either way every node switches at the activation
+//! block, so the network doesn't fork. The proof-of-work difficulty is checked on headers
+//! alone, by light clients too, so only the chain spec schedule changes it.
+use crate::{
+    blocks::{Block, BlockError},
+    contracts,
+    ledger::Address,
+};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+/// Leading zeros of proof-of-work hashes before any upgrade, as the genesis block has.
+pub const DEFAULT_DIFFICULTY: usize = 2;
+
+/// Most leading zeros any upgrade can ask for. Each one is a zero byte of the hash, and a
+/// block with five would take days to mine, stalling the chain.
+pub const MAX_DIFFICULTY: usize = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RuntimeParams {
+    /// Must grow with every upgrade.
+    pub version: u32,
+    /// Longest data of a block, in bytes.
+    pub max_data_len: usize,
+    /// Most extrinsics in a block.
+    pub max_extrinsics: usize,
+    /// Highest gas limit of an extrinsic, which bounds the time a block takes to check.
+    pub max_gas_limit: u64,
+    /// Block reward instead of the halving schedule of the chain spec, in account mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reward: Option<u64>,
+    /// Leading zeros of proof-of-work hashes, when more than the chain spec asks for, in
+    /// account mode, at most [`MAX_DIFFICULTY`]. Light clients only check those of the chain
+    /// spec.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<usize>,
+}
+
+impl Default for RuntimeParams {
+    fn default() -> Self {
+        Self {
+            version: 1,
+            max_data_len: 64 * 1024,
+            max_extrinsics: 512,
+            max_gas_limit: contracts::MAX_GAS_LIMIT,
+            reward: None,
+            difficulty: None,
+        }
+    }
+}
+
+impl RuntimeParams {
+    /// Checks the size of what `block` carries.
+    pub fn check_block(&self, block: &Block) -> Result<(), BlockError> {
+        if block.data.len() > self.max_data_len {
+            return Err(BlockError::DataTooLong {
+                id: block.id,
+                len: block.data.len(),
+                max: self.max_data_len,
+            });
+        }
+        if block.extrinsics.len() > self.max_extrinsics {
+            return Err(BlockError::TooManyExtrinsics {
+                id: block.id,
+                count: block.extrinsics.len(),
+                max: self.max_extrinsics,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// New rules from block `at` on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<RuntimeParams>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeSpec {
+    #[serde(default)]
+    pub upgrades: Vec<Upgrade>,
+    /// Account allowed to schedule upgrades on chain.
+    #[serde(default)]
+    pub sudo: Option<Address>,
+}
+
+impl RuntimeSpec {
+    /// Leading zeros of the proof-of-work hash of block `id`, at most [`MAX_DIFFICULTY`].
+    pub fn difficulty(&self, id: u64) -> usize {
+        self.upgrades
+            .iter()
+            .filter(|u| u.at <= id)
+            .filter_map(|u| Some((u.at, u.difficulty?)))
+            .max_by_key(|(at, _)| *at)
+            .map_or(DEFAULT_DIFFICULTY, |(_, difficulty)| difficulty)
+            .min(MAX_DIFFICULTY)
+    }
+
+    /// Rules the spec schedules for block `id`, with the block they apply from.
+    pub fn params(&self, id: u64) -> (u64, RuntimeParams) {
+        self.upgrades
+            .iter()
+            .filter(|u| u.at <= id)
+            .filter_map(|u| Some((u.at, u.params.clone()?)))
+            .max_by_key(|(at, _)| *at)
+            .unwrap_or_default()
+    }
+}
+
+static SPEC: OnceCell<RuntimeSpec> = OnceCell::new();
+
+/// Upgrades of this node, none unless [`set_spec`] was called.
+pub fn spec() -> &'static RuntimeSpec {
+    SPEC.get_or_init(RuntimeSpec::default)
+}
+
+/// Sets the upgrades of this node, once, before any block is produced or checked.
+/// Returns `false` if it was already set.
+pub fn set_spec(spec: RuntimeSpec) -> bool {
+    SPEC.set(spec).is_ok()
+}
+
+/// Prefix of the binary proof-of-work hash of block `id`.
+pub fn difficulty_prefix(id: u64) -> String {
+    "0".repeat(spec().difficulty(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_apply_from_their_block() {
+        let spec: RuntimeSpec = serde_json::from_str(
+            r#"{
+                "upgrades": [
+                    { "at": 20, "params": { "version": 3, "max_data_len": 16 } },
+                    { "at": 10, "difficulty": 1, "params": { "version": 2 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(spec.difficulty(9), DEFAULT_DIFFICULTY);
+        assert_eq!(spec.difficulty(10), 1);
+        assert_eq!(spec.difficulty(25), 1);
+        assert_eq!(spec.params(9), (0, RuntimeParams::default()));
+        assert_eq!(spec.params(19).1.version, 2);
+        let (at, params) = spec.params(20);
+        assert_eq!((at, params.version, params.max_data_len), (20, 3, 16));
+        assert_eq!(
+            params.max_extrinsics,
+            RuntimeParams::default().max_extrinsics
+        );
+    }
+}
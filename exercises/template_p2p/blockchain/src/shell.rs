@@ -0,0 +1,134 @@
+use crate::{
+    command::{self, COMMANDS},
+    p2p::{self, ChainApp},
+    Result,
+};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::FileHistory,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+use tokio::sync::mpsc;
+
+const HISTORY_FILE: &str = ".blockchain_history";
+const PROMPT: &str = ">> ";
+
+/// Completes command names and subcommands, and peer ids for `sync` and `fetch`.
+struct ShellHelper {
+    peers: Arc<Mutex<Vec<String>>>,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let (previous, word) = line.split_at(start);
+        let previous: Vec<&str> = previous.split_whitespace().collect();
+        let options: Vec<String> = match previous.as_slice() {
+            [] => COMMANDS
+                .iter()
+                .filter_map(|(usage, _)| usage.split_whitespace().next())
+                .map(String::from)
+                .collect(),
+            ["sync"] | ["fetch", _] => self.peers.lock().expect("shell peers lock").clone(),
+            [command] => COMMANDS
+                .iter()
+                .filter_map(|(usage, _)| {
+                    let mut words = usage.split_whitespace();
+                    (words.next() == Some(command)).then(|| words.next())?
+                })
+                .filter(|subcommand| !subcommand.starts_with('<'))
+                .map(String::from)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut candidates: Vec<Pair> = options
+            .into_iter()
+            .filter(|option| option.starts_with(word))
+            .map(|option| Pair {
+                display: option.clone(),
+                replacement: format!("{} ", option),
+            })
+            .collect();
+        candidates.dedup_by(|a, b| a.display == b.display);
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Line editor reading commands from the terminal, with history and tab completion.
+pub struct Shell {
+    peers: Arc<Mutex<Vec<String>>>,
+}
+
+impl Shell {
+    /// Reads lines on a dedicated thread and sends them through `input_sender`.
+    /// Dropping the sender (Ctrl-C or Ctrl-D) means the user quit.
+    pub fn start(input_sender: mpsc::UnboundedSender<String>) -> Result<Self> {
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let mut editor = Editor::<ShellHelper, FileHistory>::new()?;
+        editor.set_helper(Some(ShellHelper {
+            peers: peers.clone(),
+        }));
+        // there is no history the first time the shell runs
+        let _ = editor.load_history(HISTORY_FILE);
+
+        thread::spawn(move || {
+            loop {
+                match editor.readline(PROMPT) {
+                    Ok(line) => {
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        if !command::holds_secret(&line) {
+                            let _ = editor.add_history_entry(line.as_str());
+                        }
+                        if input_sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                    Err(e) => {
+                        log::error!("can't read command: {}", e);
+                        break;
+                    }
+                }
+            }
+            if let Err(e) = editor.save_history(HISTORY_FILE) {
+                log::error!("can't save command history: {}", e);
+            }
+        });
+        Ok(Self { peers })
+    }
+
+    pub fn update(&self, chain_app: &ChainApp) {
+        *self.peers.lock().expect("shell peers lock") = p2p::get_list_peers(&chain_app.swarm)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+    }
+}
@@ -0,0 +1,318 @@
+//! In-process network of `ChainApp` nodes over libp2p's `MemoryTransport`.
+//!
+//! Nodes are wired by hand instead of through mDNS and driven round-robin by the harness,
+//! so tests can script blocks, partitions and heals and then check that every node ends up
+//! on the same tip.
+use crate::{
+    blocks::Block,
+    config::NodeConfig,
+    fault::{FaultInjector, FaultProfile},
+    p2p::{
+        self, AppTransport, ChainApp, ChainResponse, EventType, Mined, BLOCK_TOPIC, CHAIN_TOPIC,
+    },
+    Result,
+};
+use libp2p::{
+    futures::{FutureExt, StreamExt},
+    identity,
+    multiaddr::Protocol,
+    Multiaddr, PeerId,
+};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+const IDLE_WAIT: Duration = Duration::from_millis(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A scripted step, see [`Simulation::run_script`].
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Mine a block with `data` on `node` and broadcast it.
+    CreateBlock { node: usize, data: String },
+    /// Split the network: nodes only stay connected to the nodes in their own group.
+    Partition(Vec<Vec<usize>>),
+    /// Reconnect every pair of nodes.
+    Heal,
+    /// Make `node` drop, delay, duplicate or reorder the messages it receives, `None` stops it.
+    Faults {
+        node: usize,
+        profile: Option<FaultProfile>,
+    },
+    /// Let the network run for a while.
+    Run(Duration),
+    /// Wait until every node has the same tip, fail after the timeout.
+    AssertConverged(Duration),
+}
+
+pub struct SimNode {
+    pub app: ChainApp,
+    pub addr: Multiaddr,
+    init_rcv: mpsc::UnboundedReceiver<bool>,
+    response_rcv: mpsc::UnboundedReceiver<ChainResponse>,
+    mined_rcv: mpsc::UnboundedReceiver<Mined>,
+}
+
+impl SimNode {
+    fn new() -> Result<Self> {
+        let keys = identity::Keypair::generate_ed25519();
+        let (init_sender, init_rcv) = mpsc::unbounded_channel();
+        let (response_sender, response_rcv) = mpsc::unbounded_channel();
+        let (mined_sender, mined_rcv) = mpsc::unbounded_channel();
+        let transport = AppTransport::memory(&keys);
+        let mut app = ChainApp::with_transport(
+            keys,
+            transport,
+            false,
+            &NodeConfig::default(),
+            init_sender,
+            response_sender,
+            mined_sender,
+        );
+        let addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+        app.swarm.listen_on(addr.clone())?;
+        Ok(Self {
+            app,
+            addr,
+            init_rcv,
+            response_rcv,
+            mined_rcv,
+        })
+    }
+
+    /// Handles every event that is ready without waiting, returns whether there was any.
+    fn poll(&mut self) -> bool {
+        let mut events = Vec::new();
+        while self.init_rcv.try_recv().is_ok() {
+            events.push(EventType::Init);
+        }
+        while let Ok(response) = self.response_rcv.try_recv() {
+            events.push(EventType::Response(response));
+        }
+        while let Ok(mined) = self.mined_rcv.try_recv() {
+            events.push(EventType::Mined(mined));
+        }
+        let mut busy = !events.is_empty();
+        for event in events {
+            p2p::handle_event(event, &mut self.app);
+        }
+        busy |= p2p::deliver_due_messages(&mut self.app);
+        while let Some(event) = self.app.swarm.select_next_some().now_or_never() {
+            busy = true;
+            if let Some(event) = p2p::handle_swarm_event(event, &mut self.app) {
+                p2p::handle_event(event, &mut self.app);
+            }
+        }
+        busy
+    }
+
+    pub fn tip(&self) -> &Block {
+        self.app.chain.head()
+    }
+
+    /// Whether `peer` is connected and subscribed to both chain topics.
+    fn is_linked_to(&self, peer: &PeerId) -> bool {
+        self.app
+            .swarm
+            .behaviour()
+            .gossipsub
+            .all_peers()
+            .any(|(p, topics)| {
+                p == peer
+                    && topics.contains(&&CHAIN_TOPIC.hash())
+                    && topics.contains(&&BLOCK_TOPIC.hash())
+            })
+    }
+}
+
+pub struct Simulation {
+    pub nodes: Vec<SimNode>,
+    links: HashSet<(usize, usize)>,
+}
+
+impl Simulation {
+    /// Starts `n` nodes connected to each other.
+    pub async fn new(n: usize) -> Result<Self> {
+        let nodes = (0..n).map(|_| SimNode::new()).collect::<Result<_>>()?;
+        let mut sim = Self {
+            nodes,
+            links: HashSet::new(),
+        };
+        sim.heal().await?;
+        Ok(sim)
+    }
+
+    pub fn node(&self, i: usize) -> &ChainApp {
+        &self.nodes[i].app
+    }
+
+    pub fn peer_id(&self, i: usize) -> PeerId {
+        self.nodes[i].app.peer_id
+    }
+
+    /// Mines a block on node `i` right away and broadcasts it.
+    pub fn create_block(&mut self, i: usize, data: &str) -> Result<Block> {
+        let app = &mut self.nodes[i].app;
+        let block = app.chain.add_data(String::from(data))?;
+        p2p::publish_block(&block, app);
+        Ok(block)
+    }
+
+    /// Applies `profile` to the messages node `i` receives, `None` lets them through again.
+    pub fn set_faults(&mut self, i: usize, profile: Option<FaultProfile>) {
+        self.nodes[i].app.faults = profile.map(FaultInjector::new);
+    }
+
+    /// Disconnects every pair of nodes that are not in the same group and waits for the links
+    /// to be down. Nodes missing from `groups` are isolated.
+    pub async fn partition(&mut self, groups: &[Vec<usize>]) -> Result<()> {
+        let group_of = |node: usize| groups.iter().position(|g| g.contains(&node));
+        let cut: Vec<(usize, usize)> = self
+            .links
+            .iter()
+            .filter(|(a, b)| group_of(*a).is_none() || group_of(*a) != group_of(*b))
+            .copied()
+            .collect();
+        for &(a, b) in cut.iter() {
+            self.unlink(a, b);
+        }
+        let cut_off = self
+            .run_until(CONNECT_TIMEOUT, |sim| {
+                cut.iter()
+                    .all(|&(a, b)| !sim.nodes[a].is_linked_to(&sim.peer_id(b)))
+            })
+            .await;
+        if !cut_off {
+            return Err("simulated nodes could not disconnect".into());
+        }
+        Ok(())
+    }
+
+    /// Connects every pair of nodes that is not connected yet and waits for the links to be up.
+    pub async fn heal(&mut self) -> Result<()> {
+        let n = self.nodes.len();
+        for a in 0..n {
+            for b in a + 1..n {
+                if !self.links.contains(&(a, b)) {
+                    self.link(a, b)?;
+                }
+            }
+        }
+        let linked = self
+            .run_until(CONNECT_TIMEOUT, |sim| {
+                sim.links
+                    .iter()
+                    .all(|&(a, b)| sim.nodes[a].is_linked_to(&sim.peer_id(b)))
+            })
+            .await;
+        if !linked {
+            return Err("simulated nodes could not connect".into());
+        }
+        Ok(())
+    }
+
+    fn link(&mut self, a: usize, b: usize) -> Result<()> {
+        let (peer_a, peer_b) = (self.peer_id(a), self.peer_id(b));
+        let addr_b = self.nodes[b].addr.clone();
+        self.nodes[a].app.swarm.dial(addr_b)?;
+        let gossipsub = &mut self.nodes[a].app.swarm.behaviour_mut().gossipsub;
+        gossipsub.add_explicit_peer(&peer_b);
+        let gossipsub = &mut self.nodes[b].app.swarm.behaviour_mut().gossipsub;
+        gossipsub.add_explicit_peer(&peer_a);
+        self.links.insert((a, b));
+        Ok(())
+    }
+
+    fn unlink(&mut self, a: usize, b: usize) {
+        let (peer_a, peer_b) = (self.peer_id(a), self.peer_id(b));
+        let swarm = &mut self.nodes[a].app.swarm;
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .remove_explicit_peer(&peer_b);
+        let _ = swarm.disconnect_peer_id(peer_b);
+        let swarm = &mut self.nodes[b].app.swarm;
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .remove_explicit_peer(&peer_a);
+        let _ = swarm.disconnect_peer_id(peer_a);
+        self.links.remove(&(a, b));
+    }
+
+    /// Drives every node, in order, until `duration` has passed.
+    pub async fn run_for(&mut self, duration: Duration) {
+        self.run_until(duration, |_| false).await;
+    }
+
+    /// Drives every node, in order, until `done` holds or `timeout` passes.
+    /// Returns whether `done` held.
+    pub async fn run_until(&mut self, timeout: Duration, done: impl Fn(&Self) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if done(self) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            let mut busy = false;
+            for node in self.nodes.iter_mut() {
+                busy |= node.poll();
+            }
+            if !busy {
+                tokio::time::sleep(IDLE_WAIT).await;
+            }
+        }
+    }
+
+    pub fn tips(&self) -> Vec<&Block> {
+        self.nodes.iter().map(|n| n.tip()).collect()
+    }
+
+    pub fn converged(&self) -> bool {
+        self.tips().windows(2).all(|t| t[0].hash == t[1].hash)
+    }
+
+    /// Runs the nodes until they share the same tip, panicking with every tip if they don't.
+    pub async fn assert_converged(&mut self, timeout: Duration) {
+        let all: Vec<usize> = (0..self.nodes.len()).collect();
+        self.assert_converged_within(&all, timeout).await;
+    }
+
+    /// Like [`Simulation::assert_converged`] but only for some of the nodes, e.g. one side of
+    /// a partition.
+    pub async fn assert_converged_within(&mut self, nodes: &[usize], timeout: Duration) {
+        let same_tip = |sim: &Self| {
+            nodes
+                .windows(2)
+                .all(|w| sim.nodes[w[0]].tip().hash == sim.nodes[w[1]].tip().hash)
+        };
+        if !self.run_until(timeout, same_tip).await {
+            let tips: Vec<(usize, u64, &str)> = nodes
+                .iter()
+                .map(|&i| (i, self.nodes[i].tip().id, self.nodes[i].tip().hash.as_str()))
+                .collect();
+            panic!("nodes did not converge on the same tip: {:?}", tips);
+        }
+    }
+
+    pub async fn run_script(&mut self, script: &[Action]) -> Result<()> {
+        for action in script {
+            log::info!("simulation: {:?}", action);
+            match action {
+                Action::CreateBlock { node, data } => {
+                    self.create_block(*node, data)?;
+                }
+                Action::Partition(groups) => self.partition(groups).await?,
+                Action::Heal => self.heal().await?,
+                Action::Faults { node, profile } => self.set_faults(*node, profile.clone()),
+                Action::Run(duration) => self.run_for(*duration).await,
+                Action::AssertConverged(timeout) => self.assert_converged(*timeout).await,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! Chain export file, to seed a network with a pre-built chain or inspect one offline.
+//!
+//! The file is JSON lines so it can be written and checked one block at a time: a header with
+//! the number of blocks, one line per block, then the sha256 of the block lines.
+//!
+//! ```text
+//! {"format":"blockchain-export","version":1,"blocks":2}
+//! {"id":0,"nonce":...,"data":"genesis"}
+//! {"id":1,"nonce":...,"data":"hello"}
+//! {"checksum":"9f86d0..."}
+//! ```
+use crate::blocks::{Block, BlockError, Chain};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+const FORMAT: &str = "blockchain-export";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportHeader {
+    format: String,
+    version: u32,
+    blocks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportTrailer {
+    checksum: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("can't read the chain file: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line} is not valid: {source}")]
+    Malformed {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("not a chain export, or an unsupported version of it")]
+    UnsupportedFormat,
+    #[error("the file announces {expected} blocks but holds {found}")]
+    CountMismatch { expected: u64, found: u64 },
+    #[error("the file holds no block")]
+    Empty,
+    #[error("the first block, at line {0}, is not the genesis block")]
+    WrongGenesis(usize),
+    #[error("first bad block, at line {line}: {source}")]
+    InvalidBlock { line: usize, source: BlockError },
+    #[error("checksum mismatch, the file is corrupted")]
+    ChecksumMismatch,
+}
+
+/// Writes `chain` in the export format.
+pub fn write_chain(writer: impl Write, chain: &Chain) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    let header = ExportHeader {
+        format: String::from(FORMAT),
+        version: VERSION,
+        blocks: chain.blocks.len() as u64,
+    };
+    write_line(&mut writer, &header)?;
+    let mut hasher = Sha256::new();
+    for block in chain.blocks.iter() {
+        let line = to_line(block);
+        hasher.update(&line);
+        writer.write_all(&line)?;
+    }
+    let trailer = ExportTrailer {
+        checksum: hex::encode(hasher.finalize()),
+    };
+    write_line(&mut writer, &trailer)?;
+    writer.flush()
+}
+
+/// Reads a chain in the export format, validating every block against the one before it as
+/// it goes, so the error points at the first bad block. The chain must start from our genesis
+/// block.
+pub fn read_chain(reader: impl BufRead) -> Result<Chain, ImportError> {
+    let blocks = read(reader, |line, block, previous| match previous {
+        Some(previous) => block
+            .validate(previous)
+            .map_err(|source| ImportError::InvalidBlock { line, source }),
+        None if *block != Block::default() => Err(ImportError::WrongGenesis(line)),
+        None => Ok(()),
+    })?;
+    Ok(Chain {
+        blocks,
+        pruned: Vec::new(),
+        finalized: None,
+    })
+}
+
+/// Reads the blocks of an export, checking the file but not the blocks, for tools that report
+/// every invalid block instead of stopping at the first one.
+pub fn read_blocks(reader: impl BufRead) -> Result<Vec<Block>, ImportError> {
+    read(reader, |_, _, _| Ok(()))
+}
+
+/// Reads an export, calling `check` with the line number, each block and the one before it.
+fn read(
+    reader: impl BufRead,
+    mut check: impl FnMut(usize, &Block, Option<&Block>) -> Result<(), ImportError>,
+) -> Result<Vec<Block>, ImportError> {
+    let mut lines = reader.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let (line, header) = lines.next().ok_or(ImportError::UnsupportedFormat)?;
+    let header: ExportHeader = parse(line, &header?)?;
+    if header.format != FORMAT || header.version != VERSION {
+        return Err(ImportError::UnsupportedFormat);
+    }
+
+    let mut hasher = Sha256::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    let count_mismatch = |found: usize| ImportError::CountMismatch {
+        expected: header.blocks,
+        found: found as u64,
+    };
+    while (blocks.len() as u64) < header.blocks {
+        let (line, text) = lines.next().ok_or_else(|| count_mismatch(blocks.len()))?;
+        let text = text?;
+        let block: Block =
+            parse(line, &text).map_err(|e| match serde_json::from_str::<ExportTrailer>(&text) {
+                Ok(_) => count_mismatch(blocks.len()),
+                Err(_) => e,
+            })?;
+        check(line, &block, blocks.last())?;
+        hasher.update(text.as_bytes());
+        hasher.update(b"\n");
+        blocks.push(block);
+    }
+    if blocks.is_empty() {
+        return Err(ImportError::Empty);
+    }
+
+    let (line, trailer) = lines.next().ok_or(ImportError::ChecksumMismatch)?;
+    let trailer = trailer?;
+    let trailer: ExportTrailer =
+        parse(line, &trailer).map_err(|e| match serde_json::from_str::<Block>(&trailer) {
+            Ok(_) => count_mismatch(blocks.len() + 1),
+            Err(_) => e,
+        })?;
+    if trailer.checksum != hex::encode(hasher.finalize()) {
+        return Err(ImportError::ChecksumMismatch);
+    }
+    Ok(blocks)
+}
+
+pub fn export_chain(path: impl AsRef<Path>, chain: &Chain) -> io::Result<()> {
+    write_chain(File::create(path)?, chain)
+}
+
+pub fn import_chain(path: impl AsRef<Path>) -> Result<Chain, ImportError> {
+    read_chain(BufReader::new(File::open(path)?))
+}
+
+pub fn import_blocks(path: impl AsRef<Path>) -> Result<Vec<Block>, ImportError> {
+    read_blocks(BufReader::new(File::open(path)?))
+}
+
+fn to_line<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut line = serde_json::to_vec(value).expect("can jsonify export line");
+    line.push(b'\n');
+    line
+}
+
+fn write_line<T: Serialize>(writer: &mut impl Write, value: &T) -> io::Result<()> {
+    writer.write_all(&to_line(value))
+}
+
+fn parse<'a, T: Deserialize<'a>>(line: usize, text: &'a str) -> Result<T, ImportError> {
+    serde_json::from_str(text).map_err(|source| ImportError::Malformed { line, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(n: u64) -> Chain {
+        let mut chain = Chain::default();
+        for i in 1..=n {
+            chain
+                .add_data(format!("block {}", i))
+                .expect("can add data");
+        }
+        chain
+    }
+
+    fn export(chain: &Chain) -> String {
+        let mut buffer = Vec::new();
+        write_chain(&mut buffer, chain).expect("can write to memory");
+        String::from_utf8(buffer).expect("json is utf-8")
+    }
+
+    fn import(text: &str) -> Result<Chain, ImportError> {
+        read_chain(text.as_bytes())
+    }
+
+    #[test]
+    fn round_trips() {
+        let chain = chain(3);
+        let text = export(&chain);
+        assert_eq!(text.lines().count(), 6);
+        assert!(import(&text).expect("valid export") == chain);
+    }
+
+    #[test]
+    fn reports_the_first_bad_block() {
+        let chain = chain(3);
+        let mut lines: Vec<String> = export(&chain).lines().map(String::from).collect();
+        let mut block: Block = serde_json::from_str(&lines[3]).unwrap();
+        block.data = String::from("tampered");
+        lines[3] = serde_json::to_string(&block).unwrap();
+        match import(&lines.join("\n")) {
+            Err(ImportError::InvalidBlock { line: 4, source }) => {
+                assert_eq!(source, BlockError::InvalidDataRoot { id: 2 })
+            }
+            other => panic!("unexpected import result: {:?}", other.map(|c| c.blocks)),
+        }
+    }
+
+    #[test]
+    fn reads_invalid_blocks_when_asked() {
+        let mut chain = chain(2);
+        chain.blocks[2].data = String::from("tampered");
+        let text = export(&chain);
+        assert!(matches!(
+            import(&text),
+            Err(ImportError::InvalidBlock { line: 4, .. })
+        ));
+        let blocks = read_blocks(text.as_bytes()).expect("blocks are not validated");
+        assert_eq!(blocks, chain.blocks);
+    }
+
+    #[test]
+    fn checks_count_and_checksum() {
+        let text = export(&chain(2));
+        let mut lines: Vec<&str> = text.lines().collect();
+
+        let truncated = [lines[..3].join("\n"), String::from(lines[4])].join("\n");
+        assert!(matches!(
+            import(&truncated),
+            Err(ImportError::CountMismatch {
+                expected: 3,
+                found: 2
+            })
+        ));
+
+        lines[4] = r#"{"checksum":"00"}"#;
+        assert!(matches!(
+            import(&lines.join("\n")),
+            Err(ImportError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            import(r#"{"format":"other","version":1,"blocks":0}"#),
+            Err(ImportError::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    fn starts_from_the_genesis_block() {
+        let mut chain = chain(1);
+        chain.blocks.remove(0);
+        assert!(matches!(
+            import(&export(&chain)),
+            Err(ImportError::WrongGenesis(2))
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{
+    blocks::{Block, MINING_STATUS},
+    p2p::{self, ChainApp},
+    Result,
+};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use once_cell::sync::Lazy;
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block as Pane, Borders, List, ListItem, Paragraph},
+    Frame, Terminal,
+};
+use std::{
+    collections::VecDeque,
+    io, panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+const MAX_BLOCKS: usize = 10;
+const MAX_LOG_LINES: usize = 500;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+static LOG_LINES: Lazy<Mutex<VecDeque<String>>> = Lazy::new(Default::default);
+/// Whether the dashboard holds the terminal in raw mode and on the alternate screen.
+static TERMINAL_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Gives the terminal back to the shell, once, whoever gets there first: the dashboard thread,
+/// the drop of the [`Dashboard`] or the panic hook.
+fn restore_terminal() {
+    if TERMINAL_TAKEN.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// Restores the terminal when dropped, so errors returned from `main` don't leave it in raw mode.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Logger that keeps the latest records in memory so the dashboard can render them,
+/// since writing to stderr would corrupt the terminal.
+struct DashboardLogger {
+    level: log::LevelFilter,
+}
+
+impl log::Log for DashboardLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {} > {}",
+            chrono::Local::now().format("%H:%M:%S"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let mut lines = LOG_LINES.lock().expect("log lines lock");
+        if lines.len() == MAX_LOG_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Snapshot of the node state, refreshed by the event loop and drawn by the dashboard thread.
+#[derive(Default)]
+struct DashboardState {
+    peers: Vec<String>,
+    blocks: Vec<Block>,
+    finalized: Option<u64>,
+    mempool: Vec<String>,
+}
+
+/// Keep it alive until the node exits, dropping it restores the terminal.
+pub struct Dashboard {
+    state: Arc<Mutex<DashboardState>>,
+    _terminal: TerminalGuard,
+}
+
+impl Dashboard {
+    /// Takes over the terminal and the logger, and sends every line typed in the command
+    /// input through `input_sender`. Dropping the sender (Esc or Ctrl-C) means the user quit.
+    pub fn start(input_sender: mpsc::UnboundedSender<String>) -> Result<Self> {
+        let level = std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(log::LevelFilter::Info);
+        log::set_boxed_logger(Box::new(DashboardLogger { level }))?;
+        log::set_max_level(level);
+
+        enable_raw_mode()?;
+        TERMINAL_TAKEN.store(true, Ordering::SeqCst);
+        let guard = TerminalGuard;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+        let state = Arc::new(Mutex::new(DashboardState::default()));
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            let result = run(terminal, thread_state, &input_sender);
+            restore_terminal();
+            if let Err(e) = result {
+                log::error!("dashboard stopped: {}", e);
+            }
+            // the event loop exits once the sender is gone, the terminal must be back by then
+            drop(input_sender);
+        });
+        Ok(Self {
+            state,
+            _terminal: guard,
+        })
+    }
+
+    pub fn update(&self, chain_app: &ChainApp) {
+        let mut state = self.state.lock().expect("dashboard state lock");
+        state.peers = p2p::get_list_peers(&chain_app.swarm)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        state.blocks = chain_app
+            .chain
+            .blocks
+            .iter()
+            .rev()
+            .take(MAX_BLOCKS)
+            .cloned()
+            .collect();
+        state.finalized = chain_app.chain.finalized.as_ref().map(|f| f.id);
+        state.mempool = chain_app.mempool.iter().cloned().collect();
+    }
+}
+
+fn run<B: Backend>(
+    mut terminal: Terminal<B>,
+    state: Arc<Mutex<DashboardState>>,
+    input_sender: &mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    let mut input = String::new();
+    let mut hash_rate = HashRate::default();
+    loop {
+        let mining = MINING_STATUS.current();
+        let rate = hash_rate.sample(mining);
+        terminal.draw(|f| {
+            let state = state.lock().expect("dashboard state lock");
+            draw(f, &state, mining, rate, &input)
+        })?;
+
+        if !event::poll(REDRAW_INTERVAL)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut input);
+                if input_sender.send(line).is_err() {
+                    return Ok(());
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Estimates hashes per second from successive samples of the mining nonce.
+#[derive(Default)]
+struct HashRate {
+    last: Option<(u64, u64, Instant)>,
+    rate: f64,
+}
+
+impl HashRate {
+    fn sample(&mut self, mining: Option<(u64, u64)>) -> f64 {
+        let Some((id, nonce)) = mining else {
+            self.last = None;
+            return 0.0;
+        };
+        let now = Instant::now();
+        match self.last {
+            Some((last_id, last_nonce, at)) if last_id == id && nonce > last_nonce => {
+                self.rate = (nonce - last_nonce) as f64 / now.duration_since(at).as_secs_f64();
+                self.last = Some((id, nonce, now));
+            }
+            Some((last_id, ..)) if last_id == id => {}
+            _ => self.last = Some((id, nonce, now)),
+        }
+        self.rate
+    }
+}
+
+fn draw<B: Backend>(
+    f: &mut Frame<B>,
+    state: &DashboardState,
+    mining: Option<(u64, u64)>,
+    hash_rate: f64,
+    input: &str,
+) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(55),
+            Constraint::Min(5),
+            Constraint::Length(3),
+        ])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(45),
+            Constraint::Percentage(25),
+        ])
+        .split(rows[0]);
+    let side = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(3)])
+        .split(columns[2]);
+
+    let peers: Vec<ListItem> = state
+        .peers
+        .iter()
+        .map(|p| ListItem::new(p.as_str()))
+        .collect();
+    f.render_widget(
+        List::new(peers).block(pane(&format!("Peers ({})", state.peers.len()))),
+        columns[0],
+    );
+
+    let blocks: Vec<ListItem> = state
+        .blocks
+        .iter()
+        .map(|b| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("#{:<5}", b.id),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    format!("{} ", short(&b.hash)),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::raw(b.data.as_str()),
+            ]))
+        })
+        .collect();
+    let title = match state.finalized {
+        Some(id) => format!("Latest blocks (finalized #{})", id),
+        None => String::from("Latest blocks"),
+    };
+    f.render_widget(List::new(blocks).block(pane(&title)), columns[1]);
+
+    let mining = match mining {
+        Some((id, nonce)) => format!(
+            "block #{}\nnonce {} ({:.1} kH/s)",
+            id,
+            nonce,
+            hash_rate / 1_000.0
+        ),
+        None => String::from("idle"),
+    };
+    f.render_widget(Paragraph::new(mining).block(pane("Mining")), side[0]);
+
+    let mempool: Vec<ListItem> = state
+        .mempool
+        .iter()
+        .map(|d| ListItem::new(d.as_str()))
+        .collect();
+    f.render_widget(
+        List::new(mempool).block(pane(&format!("Mempool ({})", state.mempool.len()))),
+        side[1],
+    );
+
+    draw_log(f, rows[1]);
+
+    f.render_widget(
+        Paragraph::new(format!("> {}", input)).block(pane(
+            "Command (type help to list the commands, Esc to quit)",
+        )),
+        rows[2],
+    );
+    f.set_cursor(rows[2].x + 3 + input.chars().count() as u16, rows[2].y + 1);
+}
+
+fn draw_log<B: Backend>(f: &mut Frame<B>, area: Rect) {
+    let lines = LOG_LINES.lock().expect("log lines lock");
+    let visible = area.height.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = lines
+        .iter()
+        .skip(lines.len().saturating_sub(visible))
+        .map(|l| ListItem::new(l.as_str()))
+        .collect();
+    f.render_widget(List::new(items).block(pane("Log")), area);
+}
+
+fn pane(title: &str) -> Pane<'_> {
+    Pane::default().title(title).borders(Borders::ALL)
+}
+
+/// First and last 8 characters of a hash, a hash too short for that as is.
+fn short(hash: &str) -> String {
+    let end = hash.len().checked_sub(8).and_then(|i| hash.get(i..));
+    match (hash.get(..8), end) {
+        (Some(start), Some(end)) if hash.len() > 16 => format!("{}…{}", start, end),
+        _ => String::from(hash),
+    }
+}
@@ -0,0 +1,459 @@
+//! Unspent transaction outputs, the ledger of chains whose spec sets `"ledger": "utxo"`.
+//!
+//! Coins are outputs instead of account balances. A transaction spends whole outputs, each
+//! input signed by the key of the output's address, and creates new ones for the same total.
+//! The coinbase of a block is an output too, spent as output 0 of the block hash.
+//!
+//! The [`UtxoSet`] follows the chain block by block and keeps what each block spent, so a
+//! reorg rolls it back to the fork before applying the new blocks.
+use crate::{
+    blocks::{Block, BlockError, Chain},
+    keys::{self, KeyScheme, Keypair},
+    ledger::{Address, Balances},
+    runtime,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+
+/// Output `index` of the transaction with hash `tx`, or of the block with hash `tx` for its
+/// coinbase.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutPoint {
+    pub tx: String,
+    pub index: u32,
+}
+
+impl std::fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.tx, self.index)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxInput {
+    pub outpoint: OutPoint,
+    /// Scheme of the key owning the output.
+    #[serde(default)]
+    pub scheme: KeyScheme,
+    /// Hex encoded signature of [`Transaction::signing_hash`] by the owner of the output.
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxOutput {
+    pub to: Address,
+    pub amount: u64,
+}
+
+/// An output that can be spent, as listed for an address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Unspent {
+    pub outpoint: OutPoint,
+    pub output: TxOutput,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub inputs: Vec<TxInput>,
+    pub outputs: Vec<TxOutput>,
+}
+
+impl Transaction {
+    /// Unsigned transaction spending `inputs`, see [`Transaction::sign_input`].
+    pub fn new(inputs: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Self {
+        let inputs = inputs
+            .into_iter()
+            .map(|outpoint| TxInput {
+                outpoint,
+                scheme: KeyScheme::default(),
+                signature: String::new(),
+            })
+            .collect();
+        Self { inputs, outputs }
+    }
+
+    /// Hex encoded sha256 of the transaction, signatures included.
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_vec(self).expect("can jsonify transaction");
+        hex::encode(Sha256::digest(json))
+    }
+
+    /// What each input signs: the transaction without its signatures and their schemes, so the
+    /// inputs can be signed in any order.
+    pub fn signing_hash(&self) -> Vec<u8> {
+        let mut unsigned = self.clone();
+        unsigned.inputs.iter_mut().for_each(|input| {
+            input.scheme = KeyScheme::default();
+            input.signature.clear();
+        });
+        let json = serde_json::to_vec(&unsigned).expect("can jsonify transaction");
+        Sha256::digest(json).to_vec()
+    }
+
+    /// Signs input `index` with the key owning the output it spends.
+    pub fn sign_input(&mut self, index: usize, key: &Keypair) {
+        self.inputs[index].scheme = key.scheme();
+        let signature = hex::encode(key.sign(&self.signing_hash()));
+        self.inputs[index].signature = signature;
+    }
+
+    fn output_points(&self) -> impl Iterator<Item = OutPoint> + '_ {
+        let hash = self.hash();
+        (0..self.outputs.len() as u32).map(move |index| OutPoint {
+            tx: hash.clone(),
+            index,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum UtxoError {
+    #[error("this chain doesn't run the UTXO ledger")]
+    Disabled,
+    #[error("a transaction needs inputs and outputs")]
+    EmptyTransaction,
+    #[error("output {0} is unknown or already spent")]
+    MissingOutput(OutPoint),
+    #[error("output {0} is already spent by a pending transaction")]
+    DoubleSpend(OutPoint),
+    #[error("input {index} is not signed by the owner of its output")]
+    InvalidSignature { index: usize },
+    #[error("inputs worth {inputs} can't pay for outputs worth {outputs}")]
+    Unbalanced { inputs: u64, outputs: u64 },
+    #[error("block with id: {0} is pruned, the outputs can't be rebuilt")]
+    PrunedBlock(u64),
+}
+
+/// What applying a block changed, to undo it on reorg.
+#[derive(Debug, Clone)]
+struct Applied {
+    id: u64,
+    hash: String,
+    spent: Vec<(OutPoint, TxOutput)>,
+    created: Vec<OutPoint>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UtxoSet {
+    outputs: BTreeMap<OutPoint, TxOutput>,
+    /// One entry per block applied, from the genesis block.
+    applied: Vec<Applied>,
+}
+
+impl UtxoSet {
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.outputs.get(outpoint)
+    }
+
+    /// Unspent outputs of `address`.
+    pub fn unspent(&self, address: &str) -> Vec<Unspent> {
+        self.outputs
+            .iter()
+            .filter(|(_, output)| output.to == address)
+            .map(|(outpoint, output)| Unspent {
+                outpoint: outpoint.clone(),
+                output: output.clone(),
+            })
+            .collect()
+    }
+
+    pub fn balances(&self) -> Balances {
+        self.outputs
+            .values()
+            .map(|output| (output.to.clone(), output.amount))
+            .collect()
+    }
+
+    /// Id and hash of the last block applied.
+    pub fn tip(&self) -> Option<(u64, &str)> {
+        self.applied.last().map(|a| (a.id, a.hash.as_str()))
+    }
+
+    /// Checks that `tx` only spends unspent outputs, each signed by its owner, and keeps the
+    /// total amount.
+    pub fn check_transaction(&self, tx: &Transaction) -> Result<(), UtxoError> {
+        if tx.inputs.is_empty() || tx.outputs.is_empty() {
+            return Err(UtxoError::EmptyTransaction);
+        }
+        let message = tx.signing_hash();
+        let mut seen = HashSet::new();
+        let mut inputs = 0u64;
+        for (index, input) in tx.inputs.iter().enumerate() {
+            if !seen.insert(&input.outpoint) {
+                return Err(UtxoError::DoubleSpend(input.outpoint.clone()));
+            }
+            let output = self
+                .outputs
+                .get(&input.outpoint)
+                .ok_or_else(|| UtxoError::MissingOutput(input.outpoint.clone()))?;
+            let signed = hex::decode(&input.signature).is_ok_and(|signature| {
+                keys::verify(input.scheme, &output.to, &message, &signature)
+            });
+            if !signed {
+                return Err(UtxoError::InvalidSignature { index });
+            }
+            inputs = inputs.saturating_add(output.amount);
+        }
+        let outputs = tx
+            .outputs
+            .iter()
+            .fold(0u64, |sum, o| sum.saturating_add(o.amount));
+        if inputs != outputs {
+            return Err(UtxoError::Unbalanced { inputs, outputs });
+        }
+        Ok(())
+    }
+
+    /// Applies the coinbase and transactions of `block`, which must follow the last block
+    /// applied. Nothing changes if a transaction is invalid.
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), BlockError> {
+        runtime::spec().params(block.id).1.check_block(block)?;
+        let mut applied = Applied {
+            id: block.id,
+            hash: block.hash.clone(),
+            spent: Vec::new(),
+            created: Vec::new(),
+        };
+        if let Some(coinbase) = &block.coinbase {
+            let outpoint = OutPoint {
+                tx: block.hash.clone(),
+                index: 0,
+            };
+            let output = TxOutput {
+                to: coinbase.to.clone(),
+                amount: coinbase.amount,
+            };
+            self.outputs.insert(outpoint.clone(), output);
+            applied.created.push(outpoint);
+        }
+        for tx in block.transactions.iter() {
+            if let Err(source) = self.check_transaction(tx) {
+                self.undo(applied);
+                return Err(BlockError::InvalidTransaction {
+                    id: block.id,
+                    source,
+                });
+            }
+            for input in tx.inputs.iter() {
+                let output = self.outputs.remove(&input.outpoint).expect("checked input");
+                applied.spent.push((input.outpoint.clone(), output));
+            }
+            for (outpoint, output) in tx.output_points().zip(tx.outputs.iter()) {
+                self.outputs.insert(outpoint.clone(), output.clone());
+                applied.created.push(outpoint);
+            }
+        }
+        self.applied.push(applied);
+        Ok(())
+    }
+
+    /// Undoes the last block applied, returns its id.
+    pub fn rollback(&mut self) -> Option<u64> {
+        let applied = self.applied.pop()?;
+        let id = applied.id;
+        self.undo(applied);
+        Some(id)
+    }
+
+    fn undo(&mut self, applied: Applied) {
+        for outpoint in applied.created.iter() {
+            self.outputs.remove(outpoint);
+        }
+        self.outputs.extend(applied.spent);
+    }
+
+    /// Rolls back the blocks that are no longer in `chain` and applies the new ones.
+    pub fn sync(&mut self, chain: &Chain) -> Result<(), BlockError> {
+        while let Some((id, hash)) = self.tip() {
+            if chain.get_header(id).is_some_and(|h| h.hash == hash) {
+                break;
+            }
+            self.rollback();
+        }
+        let next = self.tip().map_or(0, |(id, _)| id + 1);
+        for id in next..chain.len() as u64 {
+            let block = chain.get_block(id).ok_or(BlockError::InvalidTransaction {
+                id,
+                source: UtxoError::PrunedBlock(id),
+            })?;
+            self.apply_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the transactions of `block` on top of the current outputs, without applying them.
+    pub fn check_block(&self, block: &Block) -> Result<(), BlockError> {
+        self.clone().apply_block(block)
+    }
+
+    /// Checks the transactions of every block of `chain`, e.g. before switching to it.
+    pub fn check_chain(&self, chain: &Chain) -> Result<(), BlockError> {
+        self.clone().sync(chain)
+    }
+}
+
+/// Transactions waiting to be mined, no two of them spending the same output.
+#[derive(Debug, Default)]
+pub struct TxPool {
+    transactions: Vec<Transaction>,
+}
+
+impl TxPool {
+    /// Adds `tx` if it is valid on top of `set`, returns its hash.
+    pub fn add(&mut self, tx: Transaction, set: &UtxoSet) -> Result<String, UtxoError> {
+        set.check_transaction(&tx)?;
+        let spent: HashSet<&OutPoint> = self.spent().collect();
+        if let Some(input) = tx.inputs.iter().find(|i| spent.contains(&i.outpoint)) {
+            return Err(UtxoError::DoubleSpend(input.outpoint.clone()));
+        }
+        let hash = tx.hash();
+        self.transactions.push(tx);
+        Ok(hash)
+    }
+
+    fn spent(&self) -> impl Iterator<Item = &OutPoint> {
+        self.transactions
+            .iter()
+            .flat_map(|tx| tx.inputs.iter().map(|i| &i.outpoint))
+    }
+
+    /// Whether a pending transaction spends `outpoint`.
+    pub fn is_spent(&self, outpoint: &OutPoint) -> bool {
+        self.spent().any(|spent| spent == outpoint)
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Drops the transactions with the given hashes.
+    pub fn remove(&mut self, hashes: &[String]) {
+        self.transactions.retain(|tx| !hashes.contains(&tx.hash()));
+    }
+
+    /// Drops the transactions mined or made invalid by the blocks applied to `set`.
+    pub fn revalidate(&mut self, set: &UtxoSet) {
+        self.transactions
+            .retain(|tx| set.check_transaction(tx).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger;
+
+    fn mine(chain: &mut Chain, miner: Option<&str>, transactions: Vec<Transaction>) -> Block {
+        let head = chain.head().header.clone();
+        let block = Block::with_transactions(&head, String::from("block"), transactions, miner)
+            .expect("can mine");
+        chain.blocks.push(block.clone());
+        block
+    }
+
+    fn pay(from: &Keypair, outpoint: OutPoint, to: &[(&str, u64)]) -> Transaction {
+        let outputs = to
+            .iter()
+            .map(|(to, amount)| TxOutput {
+                to: String::from(*to),
+                amount: *amount,
+            })
+            .collect();
+        let mut tx = Transaction::new(vec![outpoint], outputs);
+        tx.sign_input(0, from);
+        tx
+    }
+
+    #[test]
+    fn spends_outputs_once() {
+        let alice = Keypair::generate(KeyScheme::Sr25519);
+        let (alice_address, bob) = (
+            alice.address(),
+            Keypair::generate(KeyScheme::Ed25519).address(),
+        );
+        let mut chain = Chain::default();
+        let reward = mine(&mut chain, Some(&alice_address), Vec::new());
+        let mut set = UtxoSet::default();
+        set.sync(&chain).unwrap();
+        let coinbase = OutPoint {
+            tx: reward.hash.clone(),
+            index: 0,
+        };
+        let amount = ledger::rewards().reward(1);
+        assert_eq!(set.balances().get(&alice_address), amount);
+
+        let tx = pay(
+            &alice,
+            coinbase.clone(),
+            &[(&bob, 20), (&alice_address, amount - 20)],
+        );
+        let mut forged = tx.clone();
+        forged.outputs[0].amount = amount;
+        assert_eq!(
+            set.check_transaction(&forged),
+            Err(UtxoError::InvalidSignature { index: 0 })
+        );
+        let mut pool = TxPool::default();
+        pool.add(tx.clone(), &set).expect("valid transaction");
+        let double = pay(&alice, coinbase.clone(), &[(&bob, amount)]);
+        assert_eq!(
+            pool.add(double.clone(), &set),
+            Err(UtxoError::DoubleSpend(coinbase.clone()))
+        );
+
+        mine(&mut chain, None, pool.transactions().to_vec());
+        set.sync(&chain).unwrap();
+        pool.revalidate(&set);
+        assert!(pool.is_empty());
+        assert_eq!(set.balances().get(&bob), 20);
+        assert_eq!(
+            set.check_transaction(&double),
+            Err(UtxoError::MissingOutput(coinbase))
+        );
+        let head = chain.head().header.clone();
+        let block = Block::with_transactions(&head, String::new(), vec![double], None).unwrap();
+        assert!(matches!(
+            set.check_block(&block),
+            Err(BlockError::InvalidTransaction { id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn rolls_back_on_reorg() {
+        let alice = Keypair::generate(KeyScheme::Ed25519);
+        let alice_address = alice.address();
+        let mut chain = Chain::default();
+        let reward = mine(&mut chain, Some(&alice_address), Vec::new());
+        let amount = ledger::rewards().reward(1);
+        let coinbase = OutPoint {
+            tx: reward.hash.clone(),
+            index: 0,
+        };
+        let mut fork = Chain {
+            blocks: chain.blocks.clone(),
+            pruned: Vec::new(),
+            finalized: None,
+        };
+        mine(
+            &mut chain,
+            None,
+            vec![pay(&alice, coinbase.clone(), &[("bob", amount)])],
+        );
+        let mut set = UtxoSet::default();
+        set.sync(&chain).unwrap();
+        assert_eq!(set.balances().get("bob"), amount);
+
+        for _ in 0..2 {
+            mine(&mut fork, None, Vec::new());
+        }
+        set.sync(&fork).unwrap();
+        assert_eq!(set.balances().get("bob"), 0);
+        assert_eq!(set.balances().get(&alice_address), amount);
+        assert_eq!(set.tip(), Some((3, fork.head().hash.as_str())));
+    }
+}
@@ -0,0 +1,372 @@
+//! Accounts of the user, kept apart from the node identity in an encrypted wallet file.
+//!
+//! The wallet is a JSON file listing named ed25519 or sr25519 accounts. Names, schemes and
+//! addresses are in the clear, secret seeds are sealed with ChaCha20-Poly1305 under a key
+//! derived from the passphrase with Argon2. The passphrase is checked on open against a sealed
+//! marker, so a typo doesn't go unnoticed until the first payment.
+//!
+//! New accounts come from a BIP39 phrase, shown once on creation, so an account can be
+//! imported again from its phrase or from any secret URI like `<phrase>//stash`.
+use crate::{
+    accounts::{AccountError, Accounts, Call, Extrinsic, ExtrinsicPool},
+    keys::{self, KeyError, KeyScheme, Keypair},
+    ledger::Address,
+    utxo::{Transaction, TxOutput, TxPool, UtxoError, UtxoSet},
+};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Environment variable the node reads the wallet passphrase from.
+pub const PASSPHRASE_VAR: &str = "BLOCKCHAIN_WALLET_PASSPHRASE";
+
+const CHECK: &[u8] = b"blockchain wallet";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalletError {
+    #[error("no wallet is open, start the node with --wallet <file> or --dev")]
+    NotOpen,
+    #[error("can't access the wallet file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid wallet file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the wallet file has an invalid salt")]
+    InvalidSalt,
+    #[error("wrong wallet passphrase")]
+    WrongPassphrase,
+    #[error("the secret key of `{0}` is corrupted")]
+    Corrupted(String),
+    #[error("no account named `{0}` in the wallet")]
+    UnknownAccount(String),
+    #[error("an account named `{0}` is already in the wallet")]
+    DuplicateAccount(String),
+    #[error("the wallet has no account, create one with `wallet new`")]
+    Empty,
+    #[error(transparent)]
+    Key(#[from] KeyError),
+    #[error("{address} has {balance}, can't send {amount}")]
+    InsufficientFunds {
+        address: Address,
+        balance: u64,
+        amount: u64,
+    },
+    #[error(transparent)]
+    Utxo(#[from] UtxoError),
+    #[error(transparent)]
+    Account(#[from] AccountError),
+}
+
+/// Public part of a wallet account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WalletAccount {
+    pub name: String,
+    pub scheme: KeyScheme,
+    pub address: Address,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredAccount {
+    #[serde(flatten)]
+    account: WalletAccount,
+    /// Hex encoded nonce and sealed seed.
+    secret: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WalletFile {
+    /// Hex encoded Argon2 salt.
+    salt: String,
+    /// [`CHECK`] sealed with the wallet key.
+    check: String,
+    accounts: Vec<StoredAccount>,
+}
+
+pub struct Wallet {
+    /// File the wallet is saved to, `None` for a wallet kept in memory only.
+    path: Option<PathBuf>,
+    file: WalletFile,
+    cipher: ChaCha20Poly1305,
+}
+
+impl Wallet {
+    fn empty(path: Option<PathBuf>, passphrase: &str) -> Self {
+        let salt: [u8; 16] = rand::random();
+        let cipher = cipher(passphrase, &salt).expect("valid salt");
+        let file = WalletFile {
+            salt: hex::encode(salt),
+            check: seal(&cipher, CHECK),
+            accounts: Vec::new(),
+        };
+        Self { path, file, cipher }
+    }
+
+    /// Opens the wallet at `path`, or creates an empty one if there is no file yet.
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self, WalletError> {
+        if !path.exists() {
+            let wallet = Self::empty(Some(path.to_owned()), passphrase);
+            wallet.save()?;
+            return Ok(wallet);
+        }
+        let file: WalletFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let cipher = hex::decode(&file.salt)
+            .ok()
+            .and_then(|salt| cipher(passphrase, &salt))
+            .ok_or(WalletError::InvalidSalt)?;
+        if open(&cipher, &file.check).as_deref() != Some(CHECK) {
+            return Err(WalletError::WrongPassphrase);
+        }
+        Ok(Self {
+            path: Some(path.to_owned()),
+            file,
+            cipher,
+        })
+    }
+
+    /// In-memory wallet with the well-known dev accounts, `alice` to `ferdie`, as sr25519 keys
+    /// derived from [`keys::DEV_PHRASE`].
+    pub fn dev() -> Self {
+        let mut wallet = Self::empty(None, "");
+        for name in keys::DEV_ACCOUNTS {
+            let uri = format!("//{}", name);
+            wallet
+                .import_account(Some(name.to_lowercase()), KeyScheme::Sr25519, &uri)
+                .expect("dev accounts are valid and distinct");
+        }
+        wallet
+    }
+
+    fn save(&self) -> Result<(), WalletError> {
+        if let Some(path) = &self.path {
+            let json = serde_json::to_string_pretty(&self.file)?;
+            std::fs::write(path, json)?;
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &WalletAccount> {
+        self.file.accounts.iter().map(|a| &a.account)
+    }
+
+    /// Adds a key from a new random phrase to the wallet and saves it, the phrase is returned
+    /// along with the account and isn't kept. Accounts are named `account-<n>` by default.
+    pub fn create_account(
+        &mut self,
+        name: Option<String>,
+        scheme: KeyScheme,
+    ) -> Result<(&WalletAccount, String), WalletError> {
+        let phrase = keys::generate_phrase();
+        let account = self.import_account(name, scheme, &phrase)?;
+        Ok((account, phrase))
+    }
+
+    /// Adds the key of the secret URI `uri` to the wallet and saves it, see
+    /// [`keys::seed_from_uri`].
+    pub fn import_account(
+        &mut self,
+        name: Option<String>,
+        scheme: KeyScheme,
+        uri: &str,
+    ) -> Result<&WalletAccount, WalletError> {
+        let name = name.unwrap_or_else(|| format!("account-{}", self.file.accounts.len() + 1));
+        if self.accounts().any(|a| a.name == name) {
+            return Err(WalletError::DuplicateAccount(name));
+        }
+        let seed = keys::seed_from_uri(scheme, uri)?;
+        let key = Keypair::from_seed(scheme, &seed)?;
+        self.file.accounts.push(StoredAccount {
+            account: WalletAccount {
+                name,
+                scheme,
+                address: key.address(),
+            },
+            secret: seal(&self.cipher, &seed),
+        });
+        self.save()?;
+        Ok(&self.file.accounts.last().expect("just added").account)
+    }
+
+    /// The account named `name`, or the first one.
+    pub fn account(&self, name: Option<&str>) -> Result<&WalletAccount, WalletError> {
+        self.stored(name).map(|a| &a.account)
+    }
+
+    fn stored(&self, name: Option<&str>) -> Result<&StoredAccount, WalletError> {
+        match name {
+            Some(name) => self
+                .file
+                .accounts
+                .iter()
+                .find(|a| a.account.name == name)
+                .ok_or_else(|| WalletError::UnknownAccount(String::from(name))),
+            None => self.file.accounts.first().ok_or(WalletError::Empty),
+        }
+    }
+
+    /// Decrypts the key of the account named `name`, or of the first one.
+    pub fn keypair(&self, name: Option<&str>) -> Result<Keypair, WalletError> {
+        let stored = self.stored(name)?;
+        let corrupted = || WalletError::Corrupted(stored.account.name.clone());
+        let seed: [u8; 32] = open(&self.cipher, &stored.secret)
+            .and_then(|seed| seed.try_into().ok())
+            .ok_or_else(corrupted)?;
+        let key = Keypair::from_seed(stored.account.scheme, &seed)?;
+        if key.address() != stored.account.address {
+            return Err(corrupted());
+        }
+        Ok(key)
+    }
+}
+
+/// Cipher keyed by the passphrase, `None` if the salt is too short.
+fn cipher(passphrase: &str, salt: &[u8]) -> Option<ChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .ok()?;
+    Some(ChaCha20Poly1305::new(&key.into()))
+}
+
+fn seal(cipher: &ChaCha20Poly1305, plaintext: &[u8]) -> String {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .expect("can encrypt in memory");
+    hex::encode([&nonce[..], &sealed].concat())
+}
+
+fn open(cipher: &ChaCha20Poly1305, sealed: &str) -> Option<Vec<u8>> {
+    let sealed = hex::decode(sealed).ok()?;
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// Pays `amount` to `to` from the outputs of `key` that no pending transaction spends, the
+/// change goes back to `key`.
+pub fn build_payment(
+    key: &Keypair,
+    to: &str,
+    amount: u64,
+    set: &UtxoSet,
+    pool: &TxPool,
+) -> Result<Transaction, WalletError> {
+    let address = key.address();
+    let mut inputs = Vec::new();
+    let mut total = 0u64;
+    for unspent in set.unspent(&address) {
+        if total >= amount {
+            break;
+        }
+        if !pool.is_spent(&unspent.outpoint) {
+            total += unspent.output.amount;
+            inputs.push(unspent.outpoint);
+        }
+    }
+    if total < amount || amount == 0 {
+        return Err(WalletError::InsufficientFunds {
+            address,
+            balance: total,
+            amount,
+        });
+    }
+    let mut outputs = vec![TxOutput {
+        to: String::from(to),
+        amount,
+    }];
+    if total > amount {
+        outputs.push(TxOutput {
+            to: address,
+            amount: total - amount,
+        });
+    }
+    let mut tx = Transaction::new(inputs, outputs);
+    for index in 0..tx.inputs.len() {
+        tx.sign_input(index, key);
+    }
+    Ok(tx)
+}
+
+/// Signs `call`, numbered after the pending extrinsics of `key`.
+pub fn build_call(
+    key: &Keypair,
+    call: Call,
+    accounts: &Accounts,
+    pool: &ExtrinsicPool,
+) -> Extrinsic {
+    let nonce = pool.next_nonce(&key.address(), accounts);
+    Extrinsic::signed(key, nonce, call)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_sealed_with_the_passphrase() {
+        let path = std::env::temp_dir().join(format!("wallet-{}.json", rand::random::<u64>()));
+        let mut wallet = Wallet::open(&path, "correct horse").unwrap();
+        assert!(matches!(wallet.keypair(None), Err(WalletError::Empty)));
+        let (alice, phrase) = wallet
+            .create_account(Some(String::from("alice")), KeyScheme::Sr25519)
+            .unwrap();
+        let alice = alice.clone();
+        let (second, _) = wallet.create_account(None, KeyScheme::Ed25519).unwrap();
+        assert_eq!(second.name, "account-2");
+        assert!(matches!(
+            wallet.create_account(Some(String::from("alice")), KeyScheme::Ed25519),
+            Err(WalletError::DuplicateAccount(_))
+        ));
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        let key = wallet.keypair(Some("alice")).unwrap();
+        assert_eq!(key.address(), alice.address);
+        assert!(json.contains(&alice.address), "addresses are in the clear");
+        assert!(!json.contains(&phrase), "phrases are not kept");
+
+        assert!(matches!(
+            Wallet::open(&path, "wrong horse"),
+            Err(WalletError::WrongPassphrase)
+        ));
+        let reopened = Wallet::open(&path, "correct horse").unwrap();
+        assert_eq!(reopened.accounts().count(), 2);
+        assert_eq!(reopened.account(None).unwrap(), &alice);
+        assert_eq!(reopened.keypair(None).unwrap().address(), alice.address);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn accounts_are_imported_from_phrases() {
+        let mut wallet = Wallet::dev();
+        assert_eq!(wallet.path(), None);
+        assert_eq!(wallet.accounts().count(), keys::DEV_ACCOUNTS.len());
+        assert_eq!(
+            wallet.account(None).unwrap().address,
+            "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+        );
+        let (created, phrase) = wallet.create_account(None, KeyScheme::Ed25519).unwrap();
+        let created = created.clone();
+        let imported = wallet
+            .import_account(Some(String::from("again")), KeyScheme::Ed25519, &phrase)
+            .unwrap();
+        assert_eq!(imported.address, created.address);
+        assert_eq!(
+            wallet.keypair(Some("again")).unwrap().address(),
+            created.address
+        );
+        assert!(matches!(
+            wallet.import_account(None, KeyScheme::Sr25519, "//Alice/stash"),
+            Err(WalletError::Key(KeyError::SoftJunction(_)))
+        ));
+    }
+}
@@ -0,0 +1,39 @@
+use blockchain::{
+    consensus::{self, AuraSpec, ChainSpec, ConsensusSpec},
+    sim::Simulation,
+};
+use libp2p::identity::ed25519;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nodes_agree_on_signed_blocks() {
+    // the engine is process wide, so every simulated node signs with the same validator key
+    let validator = ed25519::Keypair::generate();
+    let spec = ChainSpec {
+        name: String::from("test"),
+        consensus: ConsensusSpec::Aura(AuraSpec {
+            slot_duration_secs: 1,
+            validators: vec![hex::encode(validator.public().to_bytes())],
+        }),
+        finality: None,
+        rewards: Default::default(),
+        ledger: Default::default(),
+        runtime: Default::default(),
+        governance: Default::default(),
+    };
+    consensus::set_engine(spec.engine(Some(validator)).expect("valid spec"))
+        .expect("engine not set yet");
+
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    sim.create_block(0, "first").expect("can author block");
+    sim.assert_converged(TIMEOUT).await;
+    let second = sim.create_block(1, "second").expect("can author block");
+    sim.assert_converged(TIMEOUT).await;
+
+    let chain = &sim.node(0).chain;
+    assert_eq!(chain.head(), &second);
+    assert!(chain.blocks[1..].iter().all(|b| b.signature.is_some()));
+    assert!(chain.blocks[2].timestamp > chain.blocks[1].timestamp);
+}
@@ -0,0 +1,60 @@
+use blockchain::{
+    blocks::{Block, Chain},
+    consensus::{self, AuraSpec, ChainSpec, ConsensusSpec},
+    snapshot,
+};
+use libp2p::identity::ed25519;
+use std::{path::Path, process::Command};
+
+fn verify(file: &Path, spec: Option<&Path>) -> bool {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_chain-tool"));
+    command.arg("verify").arg(file);
+    if let Some(spec) = spec {
+        command.arg("--chain-spec").arg(spec);
+    }
+    command.status().expect("chain-tool runs").success()
+}
+
+#[test]
+fn verifies_aura_exports_with_their_chain_spec() {
+    let validator = ed25519::Keypair::generate();
+    let spec = ChainSpec {
+        name: String::from("test"),
+        consensus: ConsensusSpec::Aura(AuraSpec {
+            slot_duration_secs: 1,
+            validators: vec![hex::encode(validator.public().to_bytes())],
+        }),
+        finality: None,
+        rewards: Default::default(),
+        ledger: Default::default(),
+        runtime: Default::default(),
+        governance: Default::default(),
+    };
+    consensus::set_engine(spec.engine(Some(validator)).expect("valid spec"))
+        .expect("engine not set yet");
+    let mut chain = Chain::default();
+    for data in ["first", "second"] {
+        let block = Block::new(&chain.head().header, String::from(data), None)
+            .expect("the validator can author");
+        chain.try_add_block(block).expect("valid block");
+    }
+
+    let dir = std::env::temp_dir();
+    let id = rand::random::<u64>();
+    let (file, spec_file) = (
+        dir.join(format!("aura-{}.jsonl", id)),
+        dir.join(format!("aura-{}.json", id)),
+    );
+    snapshot::export_chain(&file, &chain).expect("can export");
+    std::fs::write(
+        &spec_file,
+        serde_json::to_string(&spec).expect("can jsonify spec"),
+    )
+    .expect("can write spec");
+
+    // signed blocks show no proof-of-work
+    assert!(!verify(&file, None));
+    assert!(verify(&file, Some(&spec_file)));
+    let _ = std::fs::remove_file(file);
+    let _ = std::fs::remove_file(spec_file);
+}
@@ -0,0 +1,33 @@
+//! Fixtures shared by the integration tests.
+// each test crate uses some of them only
+#![allow(dead_code)]
+
+use blockchain::{accounts::Accounts, ledger::Address, p2p, sim::Simulation, wallet::Wallet};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Mines a block on node 0 and waits until `rewarded` says its reward arrived.
+pub async fn mine_reward(sim: &mut Simulation, rewarded: impl Fn(&Simulation) -> bool) {
+    p2p::submit_data(String::from("reward"), &mut sim.nodes[0].app);
+    let rewarded = sim.run_until(TIMEOUT, rewarded).await;
+    assert!(rewarded, "the reward was not mined");
+}
+
+/// Starts `n` nodes following the account ledger, with `wallet` open on node 0 and its first
+/// account funded by the reward of a block. Returns the address of that account.
+pub async fn funded_account_sim(n: usize, wallet: Wallet) -> (Simulation, Address) {
+    let alice = wallet.account(None).expect("an account").address.clone();
+    let mut sim = Simulation::new(n).await.expect("can start simulation");
+    for node in sim.nodes.iter_mut() {
+        node.app.accounts = Some(Accounts::default());
+    }
+    sim.nodes[0].app.wallet = Some(wallet);
+    sim.nodes[0].app.reward_address = Some(alice.clone());
+    mine_reward(&mut sim, |sim| {
+        let accounts = sim.node(0).accounts.as_ref().expect("account mode");
+        accounts.get(&alice).balance > 0
+    })
+    .await;
+    (sim, alice)
+}
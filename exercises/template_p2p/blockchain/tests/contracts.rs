@@ -0,0 +1,58 @@
+mod common;
+
+use blockchain::{accounts::Call, contracts, p2p, sim::Simulation, wallet::Wallet};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const COUNTER: &str = "
+    push 0
+    push 0
+    load
+    arg 0
+    add
+    store
+    push 0
+    load
+    return
+";
+
+fn counter(sim: &Simulation, node: usize, address: &str) -> Option<i64> {
+    let accounts = sim.node(node).accounts.as_ref().expect("account mode");
+    accounts.contract(address)?.storage.get(&0).copied()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contract_calls_reach_other_nodes() {
+    let (mut sim, _) = common::funded_account_sim(2, Wallet::dev()).await;
+
+    let code = contracts::assemble(COUNTER).expect("valid code");
+    let deploy = Call::Deploy {
+        code,
+        gas_limit: 10_000,
+    };
+    let deployed = p2p::wallet_call(deploy, None, &mut sim.nodes[0].app).expect("can deploy");
+    let address = contracts::contract_address(&deployed.signer, deployed.nonce);
+    for n in [5, 3] {
+        let call = Call::CallContract {
+            contract: address.clone(),
+            args: vec![n],
+            gas_limit: 10_000,
+        };
+        p2p::wallet_call(call, None, &mut sim.nodes[0].app).expect("can call");
+    }
+    let called = sim
+        .run_until(TIMEOUT, |sim| counter(sim, 1, &address) == Some(8))
+        .await;
+    assert!(called, "the calls were not mined");
+
+    let node = sim.node(1);
+    let accounts = node.accounts.as_ref().expect("account mode");
+    assert_eq!(
+        node.chain.head().state_root.as_deref(),
+        Some(accounts.state_root().as_str()),
+        "blocks commit to the contract storage"
+    );
+    let query = accounts.query(&address, &[1]).expect("contract exists");
+    assert_eq!(query.result, Ok(Some(9)));
+}
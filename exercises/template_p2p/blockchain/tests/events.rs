@@ -0,0 +1,102 @@
+use blockchain::{
+    events::{ChainEvent, EventSink},
+    sim::Simulation,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_imports_and_reorgs() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    let (sink, log) = EventSink::memory();
+    sim.nodes[1].app.events = Some(sink);
+
+    let shared = sim.create_block(0, "shared").expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+    assert_eq!(
+        log.events(),
+        [
+            ChainEvent::BlockImported {
+                id: 1,
+                hash: shared.hash.clone(),
+                peer: Some(sim.peer_id(0).to_string()),
+            },
+            ChainEvent::NewBestBlock {
+                id: 1,
+                hash: shared.hash.clone(),
+            },
+        ]
+    );
+
+    sim.partition(&[vec![0], vec![1]])
+        .await
+        .expect("can partition");
+    let dropped = sim.create_block(1, "short fork").expect("can create block");
+    sim.create_block(0, "long fork 1")
+        .expect("can create block");
+    let tip = sim
+        .create_block(0, "long fork 2")
+        .expect("can create block");
+    sim.heal().await.expect("can heal");
+    sim.assert_converged(TIMEOUT).await;
+
+    let events = log.events();
+    assert!(events.contains(&ChainEvent::ChainReplaced {
+        peer: sim.peer_id(0).to_string(),
+        old_length: 3,
+        new_length: 4,
+        tip: tip.hash.clone(),
+    }));
+    assert!(events.contains(&ChainEvent::Reorg {
+        fork_id: 1,
+        depth: 1,
+        old_tip: dropped.hash.clone(),
+        new_tip: tip.hash.clone(),
+    }));
+}
+
+/// Runs the simulation until `events` got `expected`, keeping what was received in `seen`.
+async fn wait_for(
+    sim: &mut Simulation,
+    events: &mut broadcast::Receiver<ChainEvent>,
+    seen: &mut Vec<ChainEvent>,
+    expected: &ChainEvent,
+) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        while let Ok(event) = events.try_recv() {
+            seen.push(event);
+        }
+        if seen.contains(expected) {
+            return true;
+        }
+        sim.run_for(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subscribers_see_heads_and_peers() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    let mut events = sim.node(1).subscribe();
+    let mut seen = Vec::new();
+
+    let block = sim.create_block(0, "head").expect("can create block");
+    let head = ChainEvent::NewBestBlock {
+        id: 1,
+        hash: block.hash.clone(),
+    };
+    assert!(wait_for(&mut sim, &mut events, &mut seen, &head).await);
+
+    let peer = sim.peer_id(0).to_string();
+    sim.partition(&[vec![0], vec![1]])
+        .await
+        .expect("can partition");
+    let disconnected = ChainEvent::PeerDisconnected { peer: peer.clone() };
+    assert!(wait_for(&mut sim, &mut events, &mut seen, &disconnected).await);
+    sim.heal().await.expect("can heal");
+    let connected = ChainEvent::PeerConnected { peer };
+    assert!(wait_for(&mut sim, &mut events, &mut seen, &connected).await);
+}
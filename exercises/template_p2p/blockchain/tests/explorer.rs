@@ -0,0 +1,79 @@
+use blockchain::{
+    explorer,
+    p2p::ChainApp,
+    rpc::{self, RpcRequest},
+    sim::Simulation,
+};
+use std::{net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Fetches `path`, answering the requests of the explorer from `chain_app` meanwhile.
+async fn get(
+    addr: SocketAddr,
+    path: &str,
+    requests: &mut mpsc::UnboundedReceiver<RpcRequest>,
+    chain_app: &mut ChainApp,
+) -> String {
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    let response = tokio::spawn(async move {
+        let mut stream = tokio::net::TcpStream::connect(addr)
+            .await
+            .expect("can connect");
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .expect("can send request");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("can read response");
+        response
+    });
+    tokio::pin!(response);
+    loop {
+        tokio::select! {
+            response = &mut response => return response.expect("request task"),
+            Some(request) = requests.recv() => rpc::handle_rpc_request(request, chain_app),
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_blocks_and_peers() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    let block = sim
+        .create_block(0, "<b>hello</b>")
+        .expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+
+    let peer = sim.peer_id(0).to_string();
+    let (sender, mut requests) = mpsc::unbounded_channel();
+    let addr = explorer::serve(0, sender).expect("can serve explorer");
+    let app = &mut sim.nodes[1].app;
+
+    let list = get(addr, "/", &mut requests, app).await;
+    assert!(list.starts_with("HTTP/1.1 200 OK"));
+    assert!(list.contains(&format!("href=\"/block/{}\"", block.hash)));
+
+    let detail = get(addr, &format!("/block/{}", block.hash), &mut requests, app).await;
+    assert!(detail.contains("<h1>Block 1</h1>"));
+    assert!(detail.contains("&lt;b&gt;hello&lt;/b&gt;"));
+
+    let search = get(addr, "/search?q=+1+", &mut requests, app).await;
+    assert!(search.starts_with("HTTP/1.1 303 See Other"));
+    assert!(search.contains("location: /block/1"));
+
+    let peers = get(addr, "/peers", &mut requests, app).await;
+    assert!(peers.contains(&peer));
+
+    for missing in ["/block/7", &format!("/block/{}", u64::MAX)] {
+        let missing = get(addr, missing, &mut requests, app).await;
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
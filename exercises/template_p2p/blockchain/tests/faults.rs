@@ -0,0 +1,82 @@
+use blockchain::{
+    fault::{FaultProfile, LinkFaults, OutgoingFaults},
+    sim::Simulation,
+};
+use std::{collections::HashMap, time::Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn profile(default: LinkFaults) -> FaultProfile {
+    FaultProfile {
+        seed: 42,
+        default,
+        peers: HashMap::new(),
+        outgoing: OutgoingFaults::default(),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lost_block_is_fetched_with_the_next_one() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    sim.set_faults(
+        1,
+        Some(profile(LinkFaults {
+            drop: 1.0,
+            ..Default::default()
+        })),
+    );
+    sim.create_block(0, "lost").expect("can create block");
+    sim.run_for(Duration::from_secs(1)).await;
+    assert_eq!(sim.node(1).chain.head().id, 0);
+
+    sim.set_faults(1, None);
+    let block = sim.create_block(0, "found").expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+    assert_eq!(sim.node(1).chain.head().hash, block.hash);
+    assert_eq!(sim.node(1).chain.blocks.len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn faults_apply_to_published_blocks() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    let outgoing = OutgoingFaults {
+        drop: 1.0,
+        ..Default::default()
+    };
+    sim.set_faults(
+        0,
+        Some(FaultProfile {
+            outgoing,
+            ..profile(LinkFaults::default())
+        }),
+    );
+    sim.create_block(0, "unsent").expect("can create block");
+    sim.run_for(Duration::from_secs(1)).await;
+    assert_eq!(sim.node(1).chain.head().id, 0);
+
+    sim.set_faults(0, None);
+    let block = sim.create_block(0, "sent").expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+    assert_eq!(sim.node(1).chain.head().hash, block.hash);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_survives_delayed_duplicated_and_reordered_messages() {
+    let mut sim = Simulation::new(3).await.expect("can start simulation");
+    let adverse = LinkFaults {
+        duplicate: 0.5,
+        delay_ms: 20,
+        jitter_ms: 100,
+        reorder: 0.5,
+        reorder_window_ms: 500,
+        ..Default::default()
+    };
+    sim.set_faults(1, Some(profile(adverse.clone())));
+    sim.set_faults(2, Some(profile(adverse)));
+
+    sim.create_block(0, "one").expect("can create block");
+    sim.create_block(0, "two").expect("can create block");
+    let block = sim.create_block(0, "three").expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+    assert!(sim.tips().iter().all(|tip| tip.hash == block.hash));
+}
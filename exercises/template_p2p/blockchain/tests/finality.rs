@@ -0,0 +1,48 @@
+use blockchain::{
+    finality::{Finality, FinalitySpec},
+    p2p,
+    sim::Simulation,
+};
+use libp2p::identity::ed25519;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn validators_finalize_blocks_that_longer_forks_cannot_revert() {
+    let mut sim = Simulation::new(4).await.expect("can start simulation");
+    // nodes 0 to 2 are validators, node 3 only follows the votes
+    let keys: Vec<_> = (0..3).map(|_| ed25519::Keypair::generate()).collect();
+    let spec = FinalitySpec {
+        validators: keys
+            .iter()
+            .map(|k| hex::encode(k.public().to_bytes()))
+            .collect(),
+    };
+    for (i, node) in sim.nodes.iter_mut().enumerate() {
+        let finality = Finality::new(&spec, keys.get(i).cloned()).expect("valid spec");
+        node.app.finality = Some(finality);
+    }
+
+    sim.partition(&[vec![0, 1, 2], vec![3]])
+        .await
+        .expect("can partition");
+    let block = sim.create_block(0, "final").expect("can create block");
+    let finalized = sim
+        .run_until(TIMEOUT, |sim| {
+            (0..3).all(|i| sim.node(i).chain.finalized.as_ref() == Some(&block.header))
+        })
+        .await;
+    assert!(finalized, "validators did not finalize block 1");
+
+    // node 3 builds a longer chain on its own, the validators keep the finalized block
+    for data in ["fork 1", "fork 2", "fork 3"] {
+        sim.create_block(3, data).expect("can create block");
+    }
+    sim.heal().await.expect("can heal");
+    let forked = sim.peer_id(3);
+    p2p::request_chain(&forked, &mut sim.nodes[0].app);
+    sim.run_for(Duration::from_secs(1)).await;
+    assert_eq!(sim.node(0).chain.get_block(1), Some(&block));
+    assert_eq!(sim.node(0).metrics.finalized_height.get(), 1);
+}
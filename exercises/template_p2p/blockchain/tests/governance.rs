@@ -0,0 +1,112 @@
+mod common;
+
+use blockchain::{
+    accounts::Call,
+    governance::{self, GovernanceSpec, ProposalStatus},
+    p2p,
+    runtime::{self, RuntimeParams, RuntimeSpec, Upgrade},
+    sim::Simulation,
+    wallet::Wallet,
+};
+use std::{sync::Once, time::Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Short voting periods, and an easy chain spec difficulty so governance can raise it.
+fn set_specs() {
+    static SPECS: Once = Once::new();
+    SPECS.call_once(|| {
+        assert!(governance::set_spec(GovernanceSpec {
+            voting_period: 2,
+            enactment_delay: 1,
+            quorum_percent: 50,
+        }));
+        assert!(runtime::set_spec(RuntimeSpec {
+            upgrades: vec![Upgrade {
+                at: 1,
+                difficulty: Some(1),
+                params: None,
+            }],
+            sudo: None,
+        }));
+    });
+}
+
+/// Proposes `params` from node 0 and votes for them, then mines until they apply. Returns the
+/// id of the first block under them.
+async fn enact(params: RuntimeParams, sim: &mut Simulation) -> u64 {
+    p2p::wallet_call(Call::Propose { params }, None, &mut sim.nodes[0].app).expect("can propose");
+    let vote = Call::Vote {
+        proposal: 0,
+        aye: true,
+    };
+    p2p::wallet_call(vote, None, &mut sim.nodes[0].app).expect("can vote");
+    let voted = sim
+        .run_until(TIMEOUT, |sim| {
+            let accounts = sim.node(1).accounts.as_ref().expect("account mode");
+            accounts.tally(0).is_some_and(|tally| tally.ayes > 0)
+        })
+        .await;
+    assert!(voted, "the vote was not mined");
+
+    let enact_at = sim
+        .node(1)
+        .accounts
+        .as_ref()
+        .expect("account mode")
+        .proposals()[&0]
+        .enact_at;
+    while sim.node(0).chain.head().id < enact_at {
+        p2p::submit_data(String::from("next"), &mut sim.nodes[0].app);
+        let id = sim.node(0).chain.head().id;
+        let mined = sim
+            .run_until(TIMEOUT, |sim| sim.node(1).chain.head().id > id)
+            .await;
+        assert!(mined, "the chain stalled before the enactment");
+    }
+    let accounts = sim.node(1).accounts.as_ref().expect("account mode");
+    assert_eq!(accounts.proposals()[&0].status, ProposalStatus::Approved);
+    enact_at
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn approved_proposals_change_the_reward() {
+    set_specs();
+    let (mut sim, _) = common::funded_account_sim(2, Wallet::dev()).await;
+    let params = RuntimeParams {
+        version: 2,
+        reward: Some(5),
+        ..RuntimeParams::default()
+    };
+    let enact_at = enact(params, &mut sim).await;
+    let block = sim
+        .node(1)
+        .chain
+        .get_block(enact_at)
+        .expect("enacted block");
+    assert_eq!(block.coinbase.as_ref().map(|c| c.amount), Some(5));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn approved_proposals_raise_the_difficulty() {
+    set_specs();
+    let (mut sim, _) = common::funded_account_sim(2, Wallet::dev()).await;
+    let params = RuntimeParams {
+        version: 2,
+        difficulty: Some(2),
+        ..RuntimeParams::default()
+    };
+    let enact_at = enact(params, &mut sim).await;
+
+    // blocks after the enactment still get mined, and other nodes accept them
+    p2p::submit_data(String::from("harder"), &mut sim.nodes[0].app);
+    let mined = sim
+        .run_until(TIMEOUT, |sim| sim.node(1).chain.head().id > enact_at)
+        .await;
+    assert!(mined, "no block was mined at the new difficulty");
+    for id in enact_at..=enact_at + 1 {
+        let block = sim.node(1).chain.get_block(id).expect("mined block");
+        // two zero bytes, as `hash2binary` doesn't pad
+        assert!(block.hash.starts_with("0000"), "{} is too easy", block.hash);
+    }
+}
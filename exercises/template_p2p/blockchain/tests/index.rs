@@ -0,0 +1,42 @@
+use blockchain::{index::EntryKind, sim::Simulation};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn data_hash(data: &str) -> String {
+    hex::encode(Sha256::digest(data.as_bytes()))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn index_follows_reorgs() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    let shared = sim.create_block(0, "shared").expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+
+    sim.partition(&[vec![0], vec![1]])
+        .await
+        .expect("can partition");
+    sim.create_block(1, "short fork").expect("can create block");
+    // `create_block` doesn't go through the event loop, which keeps the index in step
+    let app = &mut sim.nodes[1].app;
+    app.index.sync(&app.chain);
+    assert_eq!(app.index.locate(&data_hash("short fork")).len(), 1);
+    sim.create_block(0, "long fork 1")
+        .expect("can create block");
+    let tip = sim
+        .create_block(0, "long fork 2")
+        .expect("can create block");
+    sim.heal().await.expect("can heal");
+    sim.assert_converged(TIMEOUT).await;
+
+    let index = &sim.node(1).index;
+    assert!(index.locate(&data_hash("short fork")).is_empty());
+    let located = index.locate(&data_hash("long fork 2"));
+    assert_eq!(located.len(), 1);
+    assert_eq!(located[0].block_hash, tip.hash);
+    assert_eq!(located[0].kind, EntryKind::Data);
+    assert_eq!(index.locate(&data_hash("shared"))[0].block_id, shared.id);
+    assert_eq!(index.tip(), Some((3, tip.hash.as_str())));
+    assert_eq!(index.height_at(tip.timestamp), Some(3));
+}
@@ -0,0 +1,49 @@
+use blockchain::{light::LightClient, p2p, sim::Simulation};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn syncs_headers_and_fetches_proven_bodies() {
+    let mut sim = Simulation::new(3).await.expect("can start simulation");
+    sim.nodes[2].app.light = Some(LightClient::default());
+    let light_head = |sim: &Simulation| {
+        let light = sim.node(2).light.as_ref().expect("light client");
+        light.headers.head().clone()
+    };
+
+    let first = sim.create_block(0, "gossiped").expect("can create block");
+    sim.assert_converged_within(&[0, 1], TIMEOUT).await;
+    assert!(
+        sim.run_until(TIMEOUT, |sim| light_head(sim) == first.header)
+            .await
+    );
+
+    // the light client misses some blocks and catches up from the headers of a full node
+    sim.partition(&[vec![0, 1], vec![2]])
+        .await
+        .expect("can partition");
+    sim.create_block(1, "missed 1").expect("can create block");
+    let tip = sim.create_block(1, "missed 2").expect("can create block");
+    sim.assert_converged_within(&[0, 1], TIMEOUT).await;
+    sim.heal().await.expect("can heal");
+    let full_node = sim.peer_id(0);
+    p2p::request_chain(&full_node, &mut sim.nodes[2].app);
+    assert!(
+        sim.run_until(TIMEOUT, |sim| light_head(sim) == tip.header)
+            .await
+    );
+    assert_eq!(
+        sim.node(2).chain.blocks.len(),
+        1,
+        "light clients keep no blocks"
+    );
+
+    p2p::request_body(2, &full_node, &mut sim.nodes[2].app);
+    let fetched = |sim: &Simulation| {
+        let light = sim.node(2).light.as_ref().expect("light client");
+        light.bodies.get(&2).cloned()
+    };
+    assert!(sim.run_until(TIMEOUT, |sim| fetched(sim).is_some()).await);
+    assert_eq!(fetched(&sim).as_deref(), Some("missed 1"));
+}
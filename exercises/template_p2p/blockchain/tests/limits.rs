@@ -0,0 +1,76 @@
+use blockchain::{
+    config::NodeConfig,
+    limits::Limits,
+    p2p::{AppTransport, ChainApp},
+};
+use libp2p::{
+    futures::{FutureExt, StreamExt},
+    identity,
+    swarm::SwarmEvent,
+    Multiaddr,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+fn tcp_node(config: &NodeConfig) -> ChainApp {
+    let keys = identity::Keypair::generate_ed25519();
+    let transport = AppTransport::tcp(&keys);
+    ChainApp::with_transport(
+        keys,
+        transport,
+        false,
+        config,
+        mpsc::unbounded_channel().0,
+        mpsc::unbounded_channel().0,
+        mpsc::unbounded_channel().0,
+    )
+}
+
+/// Drives every node for `duration`, returning the address the first one listens on.
+async fn run(nodes: &mut [ChainApp], duration: Duration) -> Option<Multiaddr> {
+    let mut listen_addr = None;
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        for node in nodes.iter_mut() {
+            while let Some(event) = node.swarm.select_next_some().now_or_never() {
+                if let SwarmEvent::NewListenAddr { address, .. } = event {
+                    listen_addr.get_or_insert(address);
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    listen_addr
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn limits_connections_from_the_same_ip() {
+    let limited = NodeConfig {
+        limits: Limits {
+            max_connections_per_ip: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut nodes = vec![
+        tcp_node(&limited),
+        tcp_node(&NodeConfig::default()),
+        tcp_node(&NodeConfig::default()),
+    ];
+    nodes[0]
+        .swarm
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .expect("can listen");
+    let addr = run(&mut nodes, Duration::from_millis(200))
+        .await
+        .expect("listening");
+
+    nodes[1].swarm.dial(addr.clone()).expect("can dial");
+    run(&mut nodes, Duration::from_secs(1)).await;
+    nodes[2].swarm.dial(addr).expect("can dial");
+    run(&mut nodes, Duration::from_secs(1)).await;
+
+    assert_eq!(nodes[0].swarm.connected_peers().count(), 1);
+    assert!(nodes[0].swarm.is_connected(&nodes[1].peer_id));
+    assert!(!nodes[2].swarm.is_connected(&nodes[0].peer_id));
+}
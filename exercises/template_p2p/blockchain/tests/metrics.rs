@@ -0,0 +1,33 @@
+use blockchain::sim::Simulation;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_chain_and_gossipsub_metrics() {
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    sim.create_block(0, "hello").expect("can create block");
+    sim.assert_converged(TIMEOUT).await;
+
+    let addr = sim.node(1).metrics.serve(0).expect("can serve metrics");
+    let mut stream = tokio::net::TcpStream::connect(addr)
+        .await
+        .expect("can connect");
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .expect("can send request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .await
+        .expect("can read response");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("chain_height 1\n"));
+    assert!(response.contains("chain_blocks_received_total 1\n"));
+    assert!(response.contains("chain_peers 1\n"));
+    assert!(response.contains("gossipsub_mesh_peer_counts"));
+    assert!(response.contains("libp2p_swarm"));
+}
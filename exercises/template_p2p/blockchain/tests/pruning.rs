@@ -0,0 +1,32 @@
+use blockchain::{p2p, sim::Simulation};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pruned_nodes_keep_recent_blocks_and_send_syncs_to_archive_nodes() {
+    let mut sim = Simulation::new(3).await.expect("can start simulation");
+    sim.nodes[1].app.pruning = Some(2);
+
+    sim.partition(&[vec![0, 1], vec![2]])
+        .await
+        .expect("can partition");
+    for data in ["one", "two", "three"] {
+        sim.create_block(0, data).expect("can create block");
+    }
+    sim.assert_converged_within(&[0, 1], TIMEOUT).await;
+    sim.run_for(Duration::from_millis(100)).await;
+    let pruned = &sim.node(1).chain;
+    assert_eq!(pruned.blocks.len(), 2);
+    assert_eq!(pruned.pruned.len(), 2);
+    assert_eq!(pruned.len(), 4);
+    assert!(pruned.is_valid());
+
+    // node 2 asks the pruned node first and is pointed to the archive node
+    sim.heal().await.expect("can heal");
+    let pruned_peer = sim.peer_id(1);
+    p2p::request_chain(&pruned_peer, &mut sim.nodes[2].app);
+    sim.assert_converged(TIMEOUT).await;
+    assert!(sim.node(2).pruned_peers.contains(&pruned_peer));
+    assert_eq!(sim.node(2).chain.blocks.len(), 4);
+}
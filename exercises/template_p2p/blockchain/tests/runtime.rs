@@ -0,0 +1,65 @@
+mod common;
+
+use blockchain::{
+    accounts::Call,
+    blocks::{Block, BlockError},
+    p2p,
+    runtime::{self, RuntimeParams, RuntimeSpec},
+    wallet::Wallet,
+};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sudo_upgrades_reach_other_nodes() {
+    let wallet = Wallet::dev();
+    let alice = wallet.account(None).expect("dev accounts").address.clone();
+    assert!(runtime::set_spec(RuntimeSpec {
+        upgrades: Vec::new(),
+        sudo: Some(alice.clone()),
+    }));
+    let (mut sim, _) = common::funded_account_sim(2, wallet).await;
+
+    let at = sim.node(0).chain.head().id + 3;
+    let params = RuntimeParams {
+        version: 2,
+        max_data_len: 8,
+        ..RuntimeParams::default()
+    };
+    let call = Call::SetParams {
+        at,
+        params: params.clone(),
+    };
+    p2p::wallet_call(call, None, &mut sim.nodes[0].app).expect("alice is sudo");
+    let scheduled = sim
+        .run_until(TIMEOUT, |sim| {
+            let accounts = sim.node(1).accounts.as_ref().expect("account mode");
+            accounts.upgrades() == [(at, params.clone())]
+        })
+        .await;
+    assert!(scheduled, "the upgrade was not mined");
+
+    while sim.node(0).chain.head().id + 1 < at {
+        p2p::submit_data(String::from("short"), &mut sim.nodes[0].app);
+        let id = sim.node(0).chain.head().id;
+        let mined = sim
+            .run_until(TIMEOUT, |sim| sim.node(1).chain.head().id > id)
+            .await;
+        assert!(mined, "the chain stalled before the upgrade");
+    }
+    let node = sim.node(1);
+    let accounts = node.accounts.as_ref().expect("account mode");
+    assert_eq!(accounts.params(at), params);
+    let head = node.chain.head().header.clone();
+    let block = Block::with_extrinsics(&head, String::from("much too long"), Vec::new(), None)
+        .expect("can mine");
+    assert_eq!(
+        accounts.check_block(&block),
+        Err(BlockError::DataTooLong {
+            id: at,
+            len: 13,
+            max: 8
+        })
+    );
+}
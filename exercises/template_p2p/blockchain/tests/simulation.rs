@@ -0,0 +1,157 @@
+use blockchain::{
+    blocks::BlockError,
+    consensus::ConsensusError,
+    light::LightClient,
+    p2p::{self, EventType, MiningFailure},
+    rpc::{RpcRequest, SubmitError},
+    runtime::RuntimeParams,
+    sim::{Action, Simulation},
+};
+use std::time::Duration;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn block_reaches_every_node() {
+    let mut sim = Simulation::new(3).await.expect("can start simulation");
+    let block = sim.create_block(0, "hello").expect("can create block");
+
+    sim.assert_converged(TIMEOUT).await;
+    assert!(sim.tips().iter().all(|tip| tip.hash == block.hash));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn longest_fork_wins_after_heal() {
+    let mut sim = Simulation::new(4).await.expect("can start simulation");
+    sim.partition(&[vec![0, 1], vec![2, 3]])
+        .await
+        .expect("can partition");
+
+    sim.create_block(0, "left 1").expect("can create block");
+    sim.create_block(0, "left 2").expect("can create block");
+    sim.create_block(2, "right 1").expect("can create block");
+    sim.assert_converged_within(&[0, 1], TIMEOUT).await;
+    sim.assert_converged_within(&[2, 3], TIMEOUT).await;
+    assert_ne!(sim.node(0).chain.head().hash, sim.node(2).chain.head().hash);
+
+    sim.heal().await.expect("can heal");
+    sim.assert_converged(TIMEOUT).await;
+    assert_eq!(sim.node(3).chain.head().data, "left 2");
+    assert_eq!(sim.node(3).chain.blocks.len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripted_partition_and_heal() {
+    let mut sim = Simulation::new(3).await.expect("can start simulation");
+    let create = |node: usize, data: &str| Action::CreateBlock {
+        node,
+        data: String::from(data),
+    };
+    sim.run_script(&[
+        create(1, "shared"),
+        Action::AssertConverged(TIMEOUT),
+        Action::Partition(vec![vec![0, 1]]),
+        create(2, "isolated"),
+        create(0, "majority 1"),
+        Action::Run(Duration::from_millis(500)),
+        create(1, "majority 2"),
+        Action::Run(Duration::from_millis(500)),
+        Action::Heal,
+        Action::AssertConverged(TIMEOUT),
+    ])
+    .await
+    .expect("script runs");
+
+    assert_eq!(sim.node(2).chain.head().data, "majority 2");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_blocks_free_the_miner() {
+    let mut sim = Simulation::new(1).await.expect("can start simulation");
+    let app = &mut sim.nodes[0].app;
+    let fail = |error: Box<dyn std::error::Error + Send + Sync>| {
+        EventType::Mined(Err(MiningFailure {
+            data: String::from("bad"),
+            extrinsics: Vec::new(),
+            transactions: Vec::new(),
+            error,
+        }))
+    };
+
+    // the engine couldn't seal: the data waits for the next try
+    app.mempool.push_back(String::from("bad"));
+    app.mining = true;
+    p2p::handle_event(fail(ConsensusError::NotAuthority.into()), app);
+    assert!(!app.mining);
+    assert_eq!(app.mempool, ["bad"]);
+
+    // the block was refused: its data is dropped
+    app.mining = true;
+    let refused = BlockError::DataTooLong {
+        id: 1,
+        len: 3,
+        max: 2,
+    };
+    p2p::handle_event(fail(refused.into()), app);
+    assert!(app.mempool.is_empty());
+
+    p2p::submit_data(String::from("good"), app);
+    let mined = sim
+        .run_until(TIMEOUT, |sim| sim.node(0).chain.head().data == "good")
+        .await;
+    assert!(mined, "the node stopped mining");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submitted_data_is_answered_with_its_block() {
+    let mut sim = Simulation::new(1).await.expect("can start simulation");
+    let submit = |data: String, sim: &mut Simulation| {
+        let (reply, answer) = oneshot::channel();
+        let request = RpcRequest::SubmitData(data, reply);
+        p2p::handle_event(EventType::Rpc(request), &mut sim.nodes[0].app);
+        answer
+    };
+
+    let mut answer = submit(String::from("rpc"), &mut sim);
+    assert_eq!(answer.try_recv(), Err(TryRecvError::Empty));
+    let mined = sim
+        .run_until(TIMEOUT, |sim| sim.node(0).chain.head().data == "rpc")
+        .await;
+    assert!(mined, "the data was not mined");
+    let block = answer.try_recv().expect("the call is answered");
+    assert_eq!(block.expect("mined").hash, sim.node(0).chain.head().hash);
+
+    // data the runtime refuses is not queued
+    let data = "x".repeat(RuntimeParams::default().max_data_len + 1);
+    let mut answer = submit(data, &mut sim);
+    let refused = answer.try_recv().expect("the call is answered");
+    assert!(matches!(refused, Err(SubmitError::TooLong { .. })));
+
+    // light clients don't wait for a block that never comes
+    sim.nodes[0].app.light = Some(LightClient::default());
+    let mut answer = submit(String::from("light"), &mut sim);
+    assert_eq!(answer.try_recv(), Ok(Err(SubmitError::Light)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chains_are_exported_and_imported_over_rpc() {
+    let mut from = Simulation::new(1).await.expect("can start simulation");
+    let mut to = Simulation::new(1).await.expect("can start simulation");
+    let block = from.create_block(0, "exported").expect("can create block");
+    let path = std::env::temp_dir().join(format!("chain-{}.jsonl", rand::random::<u64>()));
+
+    let (reply, mut answer) = oneshot::channel();
+    let request = RpcRequest::ExportChain(path.clone(), reply);
+    p2p::handle_event(EventType::Rpc(request), &mut from.nodes[0].app);
+    assert_eq!(answer.try_recv().expect("answered").ok(), Some(2));
+
+    for replaced in [true, false] {
+        let (reply, mut answer) = oneshot::channel();
+        let request = RpcRequest::ImportChain(path.clone(), reply);
+        p2p::handle_event(EventType::Rpc(request), &mut to.nodes[0].app);
+        assert_eq!(answer.try_recv().expect("answered").ok(), Some(replaced));
+    }
+    assert_eq!(to.node(0).chain.head(), &block);
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use blockchain::{
+    keys::{KeyScheme, Keypair},
+    ledger::{self, LedgerMode},
+    p2p,
+    sim::Simulation,
+    utxo::{OutPoint, Transaction, TxOutput, UtxoError, UtxoSet},
+};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn balance(sim: &Simulation, node: usize, address: &str) -> u64 {
+    let set = sim.node(node).utxo.as_ref().expect("UTXO mode");
+    set.balances().get(address)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transactions_spread_and_spend_outputs_once() {
+    assert!(ledger::set_mode(LedgerMode::Utxo));
+    let alice = Keypair::generate(KeyScheme::Sr25519);
+    let alice_address = alice.address();
+    let mut sim = Simulation::new(2).await.expect("can start simulation");
+    for node in sim.nodes.iter_mut() {
+        node.app.utxo = Some(UtxoSet::default());
+    }
+    sim.nodes[0].app.reward_address = Some(alice_address.clone());
+
+    common::mine_reward(&mut sim, |sim| balance(sim, 1, &alice_address) > 0).await;
+    let reward = balance(&sim, 1, &alice_address);
+    let coinbase = OutPoint {
+        tx: sim.node(1).chain.blocks[1].hash.clone(),
+        index: 0,
+    };
+
+    let pay = |to: &str| {
+        let outputs = vec![
+            TxOutput {
+                to: String::from(to),
+                amount: 20,
+            },
+            TxOutput {
+                to: alice_address.clone(),
+                amount: reward - 20,
+            },
+        ];
+        let mut tx = Transaction::new(vec![coinbase.clone()], outputs);
+        tx.sign_input(0, &alice);
+        tx
+    };
+    // node 1 mines the transaction, node 0 learns about it from the block
+    p2p::submit_transaction(pay("bob"), &mut sim.nodes[1].app).expect("valid transaction");
+    let paid = sim
+        .run_until(TIMEOUT, |sim| {
+            balance(sim, 0, "bob") == 20 && sim.node(1).transactions.is_empty()
+        })
+        .await;
+    assert!(paid, "the transaction was not mined");
+    assert_eq!(balance(&sim, 0, &alice_address), reward - 20);
+    assert_eq!(
+        p2p::submit_transaction(pay("carol"), &mut sim.nodes[0].app),
+        Err(UtxoError::MissingOutput(coinbase))
+    );
+}
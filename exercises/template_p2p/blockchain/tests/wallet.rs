@@ -0,0 +1,48 @@
+mod common;
+
+use blockchain::{
+    accounts::AccountError,
+    keys::{KeyScheme, Keypair},
+    p2p,
+    sim::Simulation,
+    wallet::{Wallet, WalletError},
+};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn balance(sim: &Simulation, node: usize, address: &str) -> u64 {
+    let accounts = sim.node(node).accounts.as_ref().expect("account mode");
+    accounts.get(address).balance
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wallet_transfers_reach_other_nodes() {
+    let path = std::env::temp_dir().join(format!("wallet-{}.json", rand::random::<u64>()));
+    let mut wallet = Wallet::open(&path, "passphrase").expect("can create wallet");
+    wallet
+        .create_account(Some(String::from("alice")), KeyScheme::Sr25519)
+        .expect("can create account");
+    let bob = Keypair::generate(KeyScheme::Ed25519).address();
+    let (mut sim, alice) = common::funded_account_sim(2, wallet).await;
+    let reward = balance(&sim, 0, &alice);
+    // the block mining the transfer pays no reward, to keep the balances simple
+    sim.nodes[0].app.reward_address = None;
+
+    p2p::wallet_send(&bob, 20, Some("alice"), &mut sim.nodes[0].app).expect("can send");
+    let paid = sim
+        .run_until(TIMEOUT, |sim| {
+            balance(sim, 1, &bob) == 20 && sim.node(0).extrinsics.is_empty()
+        })
+        .await;
+    assert!(paid, "the transfer was not mined");
+    assert_eq!(balance(&sim, 1, &alice), reward - 20);
+    assert_eq!(sim.node(1).accounts.as_ref().unwrap().get(&alice).nonce, 1);
+    assert!(matches!(
+        p2p::wallet_send(&bob, reward, None, &mut sim.nodes[0].app),
+        Err(WalletError::Account(
+            AccountError::InsufficientBalance { .. }
+        ))
+    ));
+    std::fs::remove_file(path).expect("can remove wallet");
+}